@@ -0,0 +1,82 @@
+//! HMAC-SHA256 signatures for activity log entries.
+//!
+//! The MCP server signs each entry as it appends it (when `sign_activity` is on)
+//! and the desktop app verifies the log. The server includes this file with
+//! `#[path]`, so keep it free of `crate::` imports.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret key file, stored next to the activity log
+pub const KEY_FILE_NAME: &str = "activity.key";
+
+/// Read the signing key from `dir`
+pub fn load_key(dir: &Path) -> io::Result<Vec<u8>> {
+    let text = fs::read_to_string(dir.join(KEY_FILE_NAME))?;
+    decode_hex(text.trim())
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "activity.key is not valid hex"))
+}
+
+/// Read the signing key from `dir`, generating a random one on first use
+pub fn load_or_create_key(dir: &Path) -> io::Result<Vec<u8>> {
+    match load_key(dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        other => return other,
+    }
+
+    fs::create_dir_all(dir)?;
+    let key: Vec<u8> = [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()]
+        .iter()
+        .flat_map(|id| *id.as_bytes())
+        .collect();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    match options.open(dir.join(KEY_FILE_NAME)) {
+        Ok(mut file) => {
+            file.write_all(encode_hex(&key).as_bytes())?;
+            Ok(key)
+        }
+        // Another process won the race; use its key
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => load_key(dir),
+        Err(e) => Err(e),
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of `payload`
+pub fn sign(key: &[u8], payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    encode_hex(&mac.finalize().into_bytes())
+}
+
+/// Check a hex signature produced by `sign`, in constant time
+pub fn verify(key: &[u8], payload: &str, signature: &str) -> bool {
+    let Some(expected) = decode_hex(signature) else { return false };
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&expected).is_ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
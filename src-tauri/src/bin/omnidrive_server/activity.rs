@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::fs::{self, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use chrono::Utc;
 
@@ -22,33 +23,179 @@ pub struct ActivityEntry {
     pub agent: String,
     /// Human-readable summary
     pub summary: String,
+    /// Bytes read or written, for tools that move file content (absent in older lines)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Who the entry counts as for `agent_scopes` (see `scope_identity`); unlike `agent`,
+    /// never a name the client picked (absent in older lines)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// HMAC-SHA256 over the entry serialized without this field (when signing is on)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<String>,
 }
 
 lazy_static::lazy_static! {
-    static ref ACTIVITY_DIR: PathBuf = {
-        let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-        path.push(".omnidrive");
-        path
-    };
+    static ref ACTIVITY_DIR: PathBuf = crate::config::get_config_dir();
 
     static ref ACTIVITY_FILE: PathBuf = ACTIVITY_DIR.join("activity.jsonl");
 
+    /// Advisory lock file shared with the desktop app, which reads and clears the log
+    static ref ACTIVITY_LOCK: PathBuf = ACTIVITY_DIR.join("activity.lock");
+
+    /// The client that launched the server, credited when a request names no agent
     static ref CURRENT_AGENT: Mutex<String> = Mutex::new("Generic MCP Client".to_string());
 
     static ref LOG_MUTEX: Mutex<()> = Mutex::new(());
+
+    /// Signing key, loaded (or created) the first time a signed entry is written
+    static ref SIGNING_KEY: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+}
+
+/// Mirrors `AppConfig::redact_home`; set on startup and on every config reload
+static REDACT_HOME: AtomicBool = AtomicBool::new(false);
+
+/// Mirrors `AppConfig::sign_activity`; set on startup and on every config reload
+static SIGN_ACTIVITY: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// The agent behind the request being handled (see `with_agent`)
+    static REQUEST_AGENT: RequestAgent;
+}
+
+/// Who the request being handled comes from. It travels with the request, so
+/// concurrent clients are each credited with their own activity.
+#[derive(Debug, Clone, Default)]
+pub struct RequestAgent {
+    /// The name the client gives itself (`x-omnidrive-agent` over SSE, or its initialize clientInfo)
+    pub name: Option<String>,
+    /// The Origin an SSE request came from ("" when it sent none); `None` on stdio
+    pub origin: Option<String>,
+}
+
+impl RequestAgent {
+    /// The name activity is credited to: the client's own name, else its Origin
+    fn display_name(&self) -> Option<String> {
+        self.name.clone().or_else(|| self.origin.clone().filter(|o| !o.is_empty()))
+    }
+}
+
+/// Run `fut` on behalf of `agent`: activity it logs is credited to that agent
+pub async fn with_agent<F: std::future::Future>(agent: RequestAgent, fut: F) -> F::Output {
+    REQUEST_AGENT.scope(agent, fut).await
+}
+
+/// The agent of the request being handled, for carrying it onto another thread
+pub fn request_agent() -> Option<RequestAgent> {
+    REQUEST_AGENT.try_with(RequestAgent::clone).ok()
+}
+
+/// `with_agent` for synchronous work, e.g. on the blocking pool
+pub fn with_agent_sync<R>(agent: Option<RequestAgent>, work: impl FnOnce() -> R) -> R {
+    match agent {
+        Some(agent) => REQUEST_AGENT.sync_scope(agent, work),
+        None => work(),
+    }
 }
 
 /// Max size of log file before we truncate (e.g., 2 MB)
 const MAX_LOG_SIZE_BYTES: u64 = 2 * 1024 * 1024;
 
+/// The identity `agent_scopes` are checked against. Names a client picks for itself
+/// (`x-omnidrive-agent`, its initialize clientInfo) never count: over SSE it is the
+/// Origin the request passed pairing with ("" without one, i.e. unlisted), and on
+/// stdio the client that launched the server.
+pub fn scope_identity() -> String {
+    REQUEST_AGENT
+        .try_with(|agent| agent.origin.clone())
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| CURRENT_AGENT.lock().unwrap().clone())
+}
+
+/// Set the name of the client that launched the server
 pub fn set_agent_name(name: String) {
     if let Ok(mut agent) = CURRENT_AGENT.lock() {
         *agent = name;
     }
 }
 
+/// The agent activity is credited to: the current request's, else the launching client
 pub fn get_agent_name() -> String {
-    CURRENT_AGENT.lock().unwrap().clone()
+    REQUEST_AGENT
+        .try_with(RequestAgent::display_name)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| CURRENT_AGENT.lock().unwrap().clone())
+}
+
+pub fn set_redact_home(enabled: bool) {
+    REDACT_HOME.store(enabled, Ordering::Relaxed);
+}
+
+pub fn set_sign_activity(enabled: bool) {
+    SIGN_ACTIVITY.store(enabled, Ordering::Relaxed);
+}
+
+/// The HMAC key for signing entries, if signing is enabled and the key is usable
+fn signing_key() -> Option<Vec<u8>> {
+    if !SIGN_ACTIVITY.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut cached = SIGNING_KEY.lock().ok()?;
+    if cached.is_none() {
+        match crate::audit::load_or_create_key(&ACTIVITY_DIR) {
+            Ok(key) => *cached = Some(key),
+            Err(e) => eprintln!("[OmniDrive] Activity signing disabled, key unavailable: {}", e),
+        }
+    }
+    cached.clone()
+}
+
+/// Replace the user's home directory with `~` in `text` when redaction is enabled
+pub fn redact_home(text: &str) -> String {
+    if !REDACT_HOME.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    match dirs::home_dir() {
+        Some(home) => redact_home_prefix(text, &home),
+        None => text.to_string(),
+    }
+}
+
+/// Undo `redact_home` for a logged path, so it can be checked against the sandbox again
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            format!("{}{}", home.to_string_lossy().trim_end_matches(['/', '\\']), rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Replace every occurrence of `home` that ends on a path boundary with `~`,
+/// so `/home/al` never eats the start of `/home/alice`
+fn redact_home_prefix(text: &str, home: &Path) -> String {
+    let home = home.to_string_lossy();
+    let home = home.trim_end_matches(['/', '\\']);
+    if home.is_empty() {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find(home) {
+        let after = &rest[pos + home.len()..];
+        let on_boundary = after
+            .chars()
+            .next()
+            .map_or(true, |c| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')));
+        out.push_str(&rest[..pos]);
+        out.push_str(if on_boundary { "~" } else { home });
+        rest = after;
+    }
+    out.push_str(rest);
+    out
 }
 
 /// Log an action to the central JSONL file
@@ -57,9 +204,21 @@ pub fn log_activity(
     category: &str,
     path: Option<&str>,
     summary: &str,
+) {
+    log_activity_bytes(tool, category, path, summary, None);
+}
+
+/// Log an action along with the number of bytes it read or wrote
+pub fn log_activity_bytes(
+    tool: &str,
+    category: &str,
+    path: Option<&str>,
+    summary: &str,
+    bytes: Option<u64>,
 ) {
     // Fire and forget, don't crash main server if logging fails
-    let _ = try_log_activity(tool, category, path, summary);
+    let path = path.map(redact_home);
+    let _ = try_log_activity(tool, category, path.as_deref(), &redact_home(summary), bytes);
 }
 
 fn try_log_activity(
@@ -67,6 +226,7 @@ fn try_log_activity(
     category: &str,
     path: Option<&str>,
     summary: &str,
+    bytes: Option<u64>,
 ) -> Result<(), std::io::Error> {
     let _guard = LOG_MUTEX.lock().unwrap();
 
@@ -74,13 +234,6 @@ fn try_log_activity(
         fs::create_dir_all(&*ACTIVITY_DIR)?;
     }
 
-    // Check size for rotation
-    if let Ok(meta) = fs::metadata(&*ACTIVITY_FILE) {
-        if meta.len() > MAX_LOG_SIZE_BYTES {
-            rotate_log()?;
-        }
-    }
-
     let entry = ActivityEntry {
         id: uuid::Uuid::new_v4().to_string(), // Requires uuid crate
         timestamp: Utc::now().to_rfc3339(),
@@ -89,29 +242,138 @@ fn try_log_activity(
         path: path.map(|s| s.to_string()),
         agent: get_agent_name(),
         summary: summary.to_string(),
+        bytes,
+        scope: Some(scope_identity()),
+        sig: None,
     };
 
-    let json = serde_json::to_string(&entry)?;
-    
+    let mut json = serde_json::to_string(&entry)?;
+    if let Some(key) = signing_key() {
+        let signed = ActivityEntry { sig: Some(crate::audit::sign(&key, &json)), ..entry };
+        json = serde_json::to_string(&signed)?;
+    }
+    append_line(&ACTIVITY_FILE, &ACTIVITY_LOCK, &json)
+}
+
+/// Take the cross-process advisory lock on the log. `LOG_MUTEX` only serializes
+/// this process; the desktop app takes the same lock before reading or clearing.
+/// The lock is released when the returned file is dropped.
+fn lock_log(lock_path: &Path, exclusive: bool) -> Result<fs::File, std::io::Error> {
+    let file = OpenOptions::new().create(true).write(true).open(lock_path)?;
+    if exclusive {
+        fs2::FileExt::lock_exclusive(&file)?;
+    } else {
+        fs2::FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+}
+
+/// Append one JSONL line to the log, rotating it first if it grew too large
+fn append_line(log_path: &Path, lock_path: &Path, line: &str) -> Result<(), std::io::Error> {
+    let _lock = lock_log(lock_path, true)?;
+
+    // Check size for rotation
+    if let Ok(meta) = fs::metadata(log_path) {
+        if meta.len() > MAX_LOG_SIZE_BYTES {
+            rotate_log(log_path)?;
+        }
+    }
+
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&*ACTIVITY_FILE)?;
-        
-    writeln!(file, "{}", json)?;
+        .open(log_path)?;
+
+    writeln!(file, "{}", line)?;
     Ok(())
 }
 
-fn rotate_log() -> Result<(), std::io::Error> {
-    // Simple rotation: keep the last 500 lines
+/// The newest `limit` entries written by `agent`, newest first
+pub fn recent_entries(scope: &str, limit: usize, visible: impl Fn(&str) -> bool) -> Vec<ActivityEntry> {
+    let content = {
+        let _guard = LOG_MUTEX.lock().unwrap();
+        let _lock = lock_log(&ACTIVITY_LOCK, false);
+        fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default()
+    };
+    tail_for_agent(&content, scope, limit, visible)
+}
+
+/// Entries logged under scope identity `scope` from JSONL `content`, newest first,
+/// leaving out those on paths `visible` rejects. Earlier `recent_activity` reads are
+/// left out so the tail shows real work, not the agent's own look-backs.
+fn tail_for_agent(content: &str, scope: &str, limit: usize, visible: impl Fn(&str) -> bool) -> Vec<ActivityEntry> {
+    content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<ActivityEntry>(line).ok())
+        .filter(|entry| entry.scope.as_deref() == Some(scope) && entry.tool != "recent_activity")
+        .filter(|entry| entry.path.as_deref().is_none_or(|p| !is_logged_path(p) || visible(p)))
+        .take(limit)
+        .collect()
+}
+
+/// Whether a logged `path` is a real path; batch tools log summaries like "3 paths"
+fn is_logged_path(path: &str) -> bool {
+    path.starts_with(['/', '\\', '~']) || path.get(1..3).is_some_and(|s| s == ":\\" || s == ":/")
+}
+
+/// The newest entry credited to `agent`, for tests that check attribution
+#[cfg(test)]
+pub fn latest_entry_by(agent: &str) -> Option<ActivityEntry> {
     let content = fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default();
+    content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<ActivityEntry>(line).ok())
+        .find(|entry| entry.agent == agent)
+}
+
+/// A file the agent recently read or wrote
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RecentFile {
+    pub path: String,
+    pub timestamp: String,
+    pub category: String,
+}
+
+/// The `limit` distinct paths most recently read or wrote under scope identity
+/// `scope` that `visible` accepts, newest first
+pub fn recent_files(scope: &str, limit: usize, visible: impl Fn(&str) -> bool) -> Vec<RecentFile> {
+    let content = {
+        let _guard = LOG_MUTEX.lock().unwrap();
+        let _lock = lock_log(&ACTIVITY_LOCK, false);
+        fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default()
+    };
+    recent_files_in(&content, scope, limit, visible)
+}
+
+fn recent_files_in(content: &str, scope: &str, limit: usize, visible: impl Fn(&str) -> bool) -> Vec<RecentFile> {
+    let mut files: Vec<RecentFile> = Vec::new();
+    for entry in content.lines().rev().filter_map(|line| serde_json::from_str::<ActivityEntry>(line).ok()) {
+        if files.len() >= limit {
+            break;
+        }
+        if entry.scope.as_deref() != Some(scope) || (entry.category != "read" && entry.category != "write") {
+            continue;
+        }
+        let Some(path) = entry.path else { continue };
+        if is_logged_path(&path) && !files.iter().any(|f| f.path == path) && visible(&path) {
+            files.push(RecentFile { path, timestamp: entry.timestamp, category: entry.category });
+        }
+    }
+    files
+}
+
+fn rotate_log(log_path: &Path) -> Result<(), std::io::Error> {
+    // Simple rotation: keep the last 500 lines
+    let content = fs::read_to_string(log_path).unwrap_or_default();
     let lines: Vec<&str> = content.lines().collect();
     
     let keep_count = 500;
     if lines.len() > keep_count {
         let kept_lines = &lines[lines.len() - keep_count..];
         let new_content = kept_lines.join("\n") + "\n";
-        fs::write(&*ACTIVITY_FILE, new_content)?;
+        fs::write(log_path, new_content)?;
     }
     Ok(())
 }
@@ -124,3 +386,183 @@ pub fn log_connect() {
         &format!("{} linked via MCP", get_agent_name()),
     );
 }
+
+/// Record a client ending its MCP session (streamable HTTP `DELETE`)
+pub fn log_disconnect(session_id: Option<&str>) {
+    let summary = match session_id {
+        Some(id) => format!("{} disconnected (session {} ended)", get_agent_name(), id),
+        None => format!("{} disconnected (session ended)", get_agent_name()),
+    };
+    log_activity("system", "system", None, &summary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_line(tool: &str, agent: &str, summary: &str) -> String {
+        serde_json::to_string(&ActivityEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            tool: tool.to_string(),
+            category: "read".to_string(),
+            path: None,
+            agent: agent.to_string(),
+            summary: summary.to_string(),
+            bytes: None,
+            scope: Some(agent.to_string()),
+            sig: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_concurrent_appends_and_clears_keep_log_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("activity.jsonl");
+        let lock = dir.path().join("activity.lock");
+
+        let writers: Vec<_> = (0..8)
+            .map(|w| {
+                let (log, lock) = (log.clone(), lock.clone());
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        let line = entry_line("write_file", &format!("writer-{}", w), &"x".repeat(i * 40));
+                        append_line(&log, &lock, &line).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        // Clear the way the desktop app does, racing the writers
+        let clearer = {
+            let (log, lock) = (log.clone(), lock.clone());
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    let _lock = lock_log(&lock, true).unwrap();
+                    fs::write(&log, "").unwrap();
+                }
+            })
+        };
+
+        for handle in writers {
+            handle.join().unwrap();
+        }
+        clearer.join().unwrap();
+
+        let content = fs::read_to_string(&log).unwrap();
+        for line in content.lines() {
+            assert!(
+                serde_json::from_str::<ActivityEntry>(line).is_ok(),
+                "corrupted log line: {:.80}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytes_field_is_optional() {
+        let old_line = r#"{"id":"1","timestamp":"2024-01-01T00:00:00Z","tool":"read_file","category":"read","path":"/a","agent":"X","summary":"s"}"#;
+        let entry: ActivityEntry = serde_json::from_str(old_line).unwrap();
+        assert_eq!(entry.bytes, None);
+
+        let with_bytes = ActivityEntry { bytes: Some(42), ..entry.clone() };
+        let line = serde_json::to_string(&with_bytes).unwrap();
+        assert!(line.contains("\"bytes\":42"));
+        assert!(!serde_json::to_string(&entry).unwrap().contains("bytes"));
+        let parsed: ActivityEntry = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.bytes, Some(42));
+    }
+
+    #[test]
+    fn test_recent_files_in_dedupes_newest_first() {
+        let line = |agent: &str, category: &str, path: &str| {
+            let mut entry: ActivityEntry = serde_json::from_str(&entry_line("read_file", agent, "")).unwrap();
+            entry.category = category.to_string();
+            entry.path = Some(path.to_string());
+            serde_json::to_string(&entry).unwrap()
+        };
+        let content = [
+            line("claude", "read", "/a.txt"),
+            line("claude", "write", "/b.txt"),
+            line("other", "read", "/c.txt"),
+            line("claude", "read", "2 paths"),
+            line("claude", "delete", "/d.txt"),
+            line("claude", "write", "/a.txt"),
+        ]
+        .join("\n");
+
+        let files = recent_files_in(&content, "claude", 10, |_| true);
+        let paths: Vec<(&str, &str)> = files.iter().map(|f| (f.path.as_str(), f.category.as_str())).collect();
+        assert_eq!(paths, vec![("/a.txt", "write"), ("/b.txt", "write")]);
+        assert_eq!(recent_files_in(&content, "claude", 1, |_| true).len(), 1);
+
+        // Paths the caller can no longer see are skipped, not counted
+        let files = recent_files_in(&content, "claude", 1, |p| p != "/a.txt");
+        assert_eq!(files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), ["/b.txt"]);
+    }
+
+    #[test]
+    fn test_tail_for_agent() {
+        let content = [
+            entry_line("read_file", "Claude", "first"),
+            entry_line("read_file", "Cursor", "other agent"),
+            "not json".to_string(),
+            entry_line("write_file", "Claude", "second"),
+            entry_line("recent_activity", "Claude", "look-back"),
+            entry_line("grep_content", "Claude", "third"),
+        ]
+        .join("\n");
+
+        let summaries = |limit| {
+            tail_for_agent(&content, "Claude", limit, |_| true)
+                .into_iter()
+                .map(|e| e.summary)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summaries(10), ["third", "second", "first"]);
+        assert_eq!(summaries(2), ["third", "second"]);
+        assert!(tail_for_agent(&content, "Nobody", 10, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_tail_goes_by_scope_not_claimed_name() {
+        let mut spoofed: ActivityEntry = serde_json::from_str(&entry_line("read_file", "Claude", "spoofed")).unwrap();
+        spoofed.scope = Some("https://evil.example".to_string());
+        let mut hidden: ActivityEntry = serde_json::from_str(&entry_line("read_file", "Claude", "hidden")).unwrap();
+        hidden.path = Some("/private/a.txt".to_string());
+        let content = [
+            entry_line("read_file", "Claude", "own"),
+            serde_json::to_string(&spoofed).unwrap(),
+            serde_json::to_string(&hidden).unwrap(),
+        ]
+        .join("\n");
+
+        let tail = tail_for_agent(&content, "Claude", 10, |p| !p.starts_with("/private"));
+        assert_eq!(tail.iter().map(|e| e.summary.as_str()).collect::<Vec<_>>(), ["own"]);
+    }
+
+    #[test]
+    fn test_tests_log_outside_the_real_data_dir() {
+        log_activity("read_file", "read", Some("/tmp/a.txt"), "hermetic check");
+        assert!(ACTIVITY_FILE.starts_with(crate::test_support::data_dir()));
+        assert!(fs::read_to_string(&*ACTIVITY_FILE).unwrap().contains("hermetic check"));
+        if let Some(home) = dirs::home_dir() {
+            assert!(!ACTIVITY_DIR.starts_with(home.join(".omnidrive")));
+        }
+    }
+
+    #[test]
+    fn test_redact_home_prefix() {
+        let home = Path::new("/home/al");
+        assert_eq!(redact_home_prefix("/home/al/docs/a.txt", home), "~/docs/a.txt");
+        assert_eq!(redact_home_prefix("/home/al", home), "~");
+        assert_eq!(
+            redact_home_prefix("Moved /home/al/a to /home/al/b", home),
+            "Moved ~/a to ~/b"
+        );
+        assert_eq!(redact_home_prefix("/home/alice/a.txt", home), "/home/alice/a.txt");
+        assert_eq!(redact_home_prefix("/srv/data/a.txt", home), "/srv/data/a.txt");
+        assert_eq!(redact_home_prefix("/home/al/x:3:match", Path::new("/home/al/")), "~/x:3:match");
+    }
+}
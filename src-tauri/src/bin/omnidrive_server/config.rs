@@ -1,14 +1,23 @@
 //! Shared config types and reader for the MCP sidecar binary.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, Watcher};
+use tokio::sync::mpsc;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Permission {
     ReadOnly,
     ReadWrite,
+    /// New files may be created, but existing ones can't be overwritten, moved or deleted
+    CreateOnly,
+    /// Like `CreateOnly`, but existing files may also be appended to
+    AppendOnly,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +27,8 @@ pub struct SharedFolder {
     pub enabled: bool,
     #[serde(default = "default_true")]
     pub available: bool,
+    #[serde(default)]
+    pub case_insensitive_ignore: Option<bool>,
 }
 
 fn default_true() -> bool {
@@ -29,31 +40,225 @@ pub struct AppConfig {
     pub folders: Vec<SharedFolder>,
     #[serde(default = "default_max_file_size")]
     pub max_file_size_mb: u32,
+    #[serde(default = "default_max_heavy_operations")]
+    pub max_heavy_operations: usize,
+    #[serde(default)]
+    pub redact_home: bool,
+    #[serde(default)]
+    pub global_ignore: Vec<String>,
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    #[serde(default = "default_page_size")]
+    pub default_page_size: usize,
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: usize,
+    #[serde(default = "default_max_tree_page_size")]
+    pub max_tree_page_size: usize,
+    #[serde(default = "default_max_tree_entries")]
+    pub max_tree_entries: usize,
+    #[serde(default)]
+    pub extra_extensions: Vec<String>,
+    #[serde(default)]
+    pub sign_activity: bool,
+    #[serde(default)]
+    pub sse_stateful: bool,
+    #[serde(default)]
+    pub created_file_mode: Option<String>,
+    #[serde(default = "default_blocking_timeout_secs")]
+    pub blocking_timeout_secs: u64,
+    #[serde(default = "default_io_retry_attempts")]
+    pub io_retry_attempts: u32,
+    #[serde(default)]
+    pub require_confirmation_for_destructive: bool,
+    #[serde(default = "default_confirmation_timeout_secs")]
+    pub confirmation_timeout_secs: u64,
+    #[serde(default = "default_max_path_length")]
+    pub max_path_length: usize,
+    #[serde(default = "default_max_path_components")]
+    pub max_path_components: usize,
+    #[serde(default = "default_max_filename_length")]
+    pub max_filename_length: usize,
+    #[serde(default)]
+    pub server_instructions: Option<String>,
+    #[serde(default)]
+    pub replace_instructions: bool,
+    #[serde(default)]
+    pub case_insensitive_ignore: Option<bool>,
+    #[serde(default = "default_max_search_results")]
+    pub max_search_results: usize,
+    #[serde(default = "default_true")]
+    pub use_default_ignores: bool,
+    #[serde(default)]
+    pub agent_scopes: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub deny_unscoped_agents: bool,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub display_timezone: Option<String>,
+    #[serde(default = "default_display_time_format")]
+    pub display_time_format: String,
+    #[serde(default)]
+    pub read_cache_mb: usize,
+    #[serde(default = "default_true")]
+    pub default_overwrite: bool,
 }
 
 fn default_max_file_size() -> u32 {
     50
 }
 
+fn default_max_heavy_operations() -> usize {
+    4
+}
+
+fn default_max_response_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_page_size() -> usize {
+    50
+}
+
+fn default_max_page_size() -> usize {
+    100
+}
+
+fn default_max_tree_page_size() -> usize {
+    200
+}
+
+fn default_max_tree_entries() -> usize {
+    10_000
+}
+
+fn default_blocking_timeout_secs() -> u64 {
+    60
+}
+
+fn default_io_retry_attempts() -> u32 {
+    3
+}
+
+fn default_confirmation_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_path_length() -> usize {
+    4096
+}
+
+fn default_max_path_components() -> usize {
+    128
+}
+
+fn default_max_filename_length() -> usize {
+    255
+}
+
+fn default_max_search_results() -> usize {
+    200
+}
+
+/// How get_file_info and search previews show times unless `display_time_format` says otherwise
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn default_display_time_format() -> String {
+    DEFAULT_TIME_FORMAT.to_string()
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             folders: Vec::new(),
             max_file_size_mb: 50,
+            max_heavy_operations: default_max_heavy_operations(),
+            redact_home: false,
+            global_ignore: Vec::new(),
+            max_response_bytes: default_max_response_bytes(),
+            default_page_size: default_page_size(),
+            max_page_size: default_max_page_size(),
+            max_tree_page_size: default_max_tree_page_size(),
+            max_tree_entries: default_max_tree_entries(),
+            extra_extensions: Vec::new(),
+            sign_activity: false,
+            sse_stateful: false,
+            created_file_mode: None,
+            blocking_timeout_secs: default_blocking_timeout_secs(),
+            io_retry_attempts: default_io_retry_attempts(),
+            require_confirmation_for_destructive: false,
+            confirmation_timeout_secs: default_confirmation_timeout_secs(),
+            max_path_length: default_max_path_length(),
+            max_path_components: default_max_path_components(),
+            max_filename_length: default_max_filename_length(),
+            server_instructions: None,
+            replace_instructions: false,
+            case_insensitive_ignore: None,
+            max_search_results: default_max_search_results(),
+            use_default_ignores: true,
+            agent_scopes: HashMap::new(),
+            deny_unscoped_agents: false,
+            paused: false,
+            display_timezone: None,
+            display_time_format: default_display_time_format(),
+            read_cache_mb: 0,
+            default_overwrite: true,
         }
     }
 }
 
-/// Get the shared config file path
-pub fn get_config_path() -> PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    home.join(".omnidrive").join("config.json")
+/// Directory holding the shared config, profiles and activity log.
+/// Tests get a temporary one, so they never touch a real install.
+pub fn get_config_dir() -> PathBuf {
+    #[cfg(test)]
+    {
+        crate::test_support::data_dir()
+    }
+    #[cfg(not(test))]
+    {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        home.join(".omnidrive")
+    }
+}
+
+/// Get the shared config file path for the given profile (or the default config)
+pub fn get_config_path(profile: Option<&str>) -> PathBuf {
+    resolve_config_path(&get_config_dir(), profile)
 }
 
-/// Load config from the shared config file
-pub fn load_config() -> AppConfig {
-    let path = get_config_path();
-    match fs::read_to_string(&path) {
+/// Profile names become part of a file name, so keep them to a safe charset
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Pick `config.<profile>.json` in `dir` if it exists, otherwise fall back to `config.json`
+pub fn resolve_config_path(dir: &Path, profile: Option<&str>) -> PathBuf {
+    let default = dir.join("config.json");
+    let Some(name) = profile else { return default };
+
+    if !is_valid_profile_name(name) {
+        eprintln!("[OmniDrive] Invalid profile name {:?}, using default config", name);
+        return default;
+    }
+
+    let profile_path = dir.join(format!("config.{}.json", name));
+    if profile_path.is_file() {
+        profile_path
+    } else {
+        eprintln!(
+            "[OmniDrive] Profile {:?} not found at {:?}, using default config",
+            name, profile_path
+        );
+        default
+    }
+}
+
+/// Load config from the given config file
+pub fn load_config(path: &Path) -> AppConfig {
+    match fs::read_to_string(path) {
         Ok(contents) => {
             serde_json::from_str(&contents).unwrap_or_else(|e| {
                 eprintln!(
@@ -72,3 +277,197 @@ pub fn load_config() -> AppConfig {
         }
     }
 }
+
+/// Startup self-check: mark each enabled folder that is missing or unreadable as
+/// unavailable, returning one warning per such folder. Folders that check out are
+/// marked available again, so a reload picks up a remounted drive.
+pub fn check_folders(config: &mut AppConfig) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for folder in config.folders.iter_mut().filter(|f| f.enabled) {
+        let problem = match fs::canonicalize(&folder.path) {
+            Err(e) => Some(format!("cannot be resolved ({})", e)),
+            Ok(path) if !path.is_dir() => Some("is not a directory".to_string()),
+            Ok(path) => fs::read_dir(&path).err().map(|e| format!("is not readable ({})", e)),
+        };
+        folder.available = problem.is_none();
+        if let Some(problem) = problem {
+            warnings.push(format!("Shared folder {} {}", folder.path, problem));
+        }
+    }
+    warnings
+}
+
+/// First wait before re-creating a failed config watch; it doubles on each failure
+const WATCH_RETRY_MIN: Duration = Duration::from_secs(1);
+/// Longest wait between attempts to re-create the config watch
+const WATCH_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// What the config watcher saw
+enum WatchEvent {
+    Changed,
+    Failed(String),
+}
+
+/// Watch the config file at `path`, yielding an item whenever it changes. Watcher
+/// errors (e.g. a network home directory dropping out) are logged and the watch is
+/// re-created with exponential backoff, yielding an item once it is back since
+/// the file may have changed meanwhile. The feed ends when the receiver is dropped.
+pub fn watch_config(path: PathBuf) -> mpsc::Receiver<()> {
+    watch_config_with_backoff(path, WATCH_RETRY_MIN, WATCH_RETRY_MAX)
+}
+
+fn watch_config_with_backoff(path: PathBuf, min_delay: Duration, max_delay: Duration) -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut delay = min_delay;
+        let mut recovering = false;
+        loop {
+            let (events_tx, mut events) = mpsc::unbounded_channel();
+            match start_config_watch(&path, events_tx) {
+                Ok(watcher) => {
+                    if recovering {
+                        eprintln!("[OmniDrive] Config watcher re-established.");
+                        let _ = tx.try_send(());
+                    }
+                    delay = min_delay;
+                    while let Some(event) = events.recv().await {
+                        match event {
+                            // A reload already queued covers this change too
+                            WatchEvent::Changed => {
+                                let _ = tx.try_send(());
+                            }
+                            WatchEvent::Failed(e) => {
+                                eprintln!("[OmniDrive] Config watcher error: {}", e);
+                                break;
+                            }
+                        }
+                        if tx.is_closed() {
+                            return;
+                        }
+                    }
+                    drop(watcher);
+                }
+                Err(e) => eprintln!("[OmniDrive] Cannot watch config at {:?}: {}", path, e),
+            }
+            if tx.is_closed() {
+                return;
+            }
+            eprintln!("[OmniDrive] Retrying the config watch in {}ms.", delay.as_millis());
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(max_delay);
+            recovering = true;
+        }
+    });
+    rx
+}
+
+/// Watch the directory holding the config file. Only the file itself counts as a
+/// change: the directory also holds the activity log and other profiles.
+fn start_config_watch(path: &Path, events: mpsc::UnboundedSender<WatchEvent>) -> notify::Result<RecommendedWatcher> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    let file_name = path.file_name().map(|n| n.to_os_string());
+    let watched_dir = dir.clone();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let event = match res {
+            Err(e) => WatchEvent::Failed(e.to_string()),
+            // The directory itself went away, taking the watch with it
+            Ok(event) if event.kind.is_remove() && event.paths.contains(&watched_dir) => {
+                WatchEvent::Failed(format!("{:?} was removed", watched_dir))
+            }
+            Ok(event) => {
+                let touches_config = event.paths.iter().any(|p| p.file_name().map(|n| n.to_os_string()) == file_name);
+                if !(touches_config && (event.kind.is_modify() || event.kind.is_create())) {
+                    return;
+                }
+                WatchEvent::Changed
+            }
+        };
+        let _ = events.send(event);
+    })?;
+    watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_config_path_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("config.json");
+        let work = dir.path().join("config.work.json");
+        fs::write(&work, "{}").unwrap();
+
+        assert_eq!(resolve_config_path(dir.path(), None), default);
+        assert_eq!(resolve_config_path(dir.path(), Some("work")), work);
+        // Missing profiles fall back to the default config
+        assert_eq!(resolve_config_path(dir.path(), Some("personal")), default);
+        // Names that could escape the config dir are rejected
+        assert_eq!(resolve_config_path(dir.path(), Some("../work")), default);
+    }
+
+    #[test]
+    fn test_check_folders_marks_missing_folder_unavailable() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = |path: String, enabled: bool| SharedFolder {
+            path,
+            permission: Permission::ReadOnly,
+            enabled,
+            available: true,
+            case_insensitive_ignore: None,
+        };
+        let missing = dir.path().join("unmounted").to_string_lossy().to_string();
+        let mut config = AppConfig {
+            folders: vec![
+                folder(dir.path().to_string_lossy().to_string(), true),
+                folder(missing.clone(), true),
+                folder(dir.path().join("also-missing").to_string_lossy().to_string(), false),
+            ],
+            ..AppConfig::default()
+        };
+
+        let warnings = check_folders(&mut config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&missing));
+        assert!(config.folders[0].available);
+        assert!(!config.folders[1].available);
+        // Disabled folders are not checked
+        assert!(config.folders[2].available);
+
+        fs::create_dir(&missing).unwrap();
+        assert!(check_folders(&mut config).is_empty());
+        assert!(config.folders[1].available);
+    }
+
+    #[test]
+    fn test_is_valid_profile_name() {
+        assert!(is_valid_profile_name("work"));
+        assert!(is_valid_profile_name("client_a-2"));
+        assert!(!is_valid_profile_name(""));
+        assert!(!is_valid_profile_name("a.b"));
+        assert!(!is_valid_profile_name("a/b"));
+    }
+
+    #[tokio::test]
+    async fn test_config_watch_recovers_once_directory_is_reachable() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("home-share");
+        let config_path = config_dir.join("config.json");
+
+        // The directory is missing at first, so the initial watch fails and is retried
+        let mut changes = watch_config_with_backoff(config_path.clone(), Duration::from_millis(20), Duration::from_millis(100));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        fs::create_dir_all(&config_dir).unwrap();
+        let recovered = tokio::time::timeout(Duration::from_secs(5), changes.recv()).await;
+        assert!(matches!(recovered, Ok(Some(()))), "no reload once the watch came back");
+
+        // The re-created watch reports edits like the original would
+        while changes.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        fs::write(&config_path, "{}").unwrap();
+        let edited = tokio::time::timeout(Duration::from_secs(5), changes.recv()).await;
+        assert!(matches!(edited, Ok(Some(()))), "edit after recovery was missed");
+    }
+}
@@ -0,0 +1,220 @@
+//! Human-in-the-loop confirmation for destructive operations.
+//!
+//! When `require_confirmation_for_destructive` is on, the server drops a request
+//! file into `~/.omnidrive/confirmations/` and waits for the desktop app to write
+//! a matching response after asking the user. No answer before the timeout is
+//! treated as a denial.
+
+use chrono::Utc;
+use rmcp::ErrorData;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::activity;
+use crate::config::AppConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A pending request, as read by the app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmationRequest {
+    pub id: String,
+    pub agent: String,
+    pub tool: String,
+    pub path: String,
+    pub action: String,
+    pub requested_at: String,
+    pub expires_at: String,
+}
+
+/// The app's answer to a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmationResponse {
+    pub approved: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Approved,
+    Denied,
+    TimedOut,
+}
+
+/// The confirmation settings from the config
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    pub required: bool,
+    pub timeout: Duration,
+}
+
+impl From<&AppConfig> for Policy {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            required: config.require_confirmation_for_destructive,
+            timeout: Duration::from_secs(config.confirmation_timeout_secs),
+        }
+    }
+}
+
+pub fn confirmations_dir() -> PathBuf {
+    crate::config::get_config_dir().join("confirmations")
+}
+
+fn request_file(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+fn response_file(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.response.json", id))
+}
+
+/// Ask the user to approve `action` on `path` if the policy requires it.
+/// Returns an error describing the denial when the user declines or doesn't answer in time.
+pub async fn confirm_destructive(
+    policy: Policy,
+    tool: &str,
+    path: &str,
+    action: &str,
+) -> Result<(), ErrorData> {
+    if !policy.required {
+        return Ok(());
+    }
+
+    let timeout = policy.timeout;
+    let decision = request_in(&confirmations_dir(), tool, path, action, timeout)
+        .await
+        .map_err(|e| {
+            ErrorData::internal_error(
+                format!("Denied: could not ask the user to confirm {} of {}: {}", action, path, e),
+                None,
+            )
+        })?;
+
+    let reason = match decision {
+        Decision::Approved => return Ok(()),
+        Decision::Denied => "the user declined it in the OmniDrive app".to_string(),
+        Decision::TimedOut => format!(
+            "no answer within {}s (unanswered requests are denied)",
+            timeout.as_secs()
+        ),
+    };
+    activity::log_activity(tool, "delete", Some(path), &format!("Denied {}: {}", action, reason));
+
+    Err(ErrorData::invalid_params(
+        format!(
+            "Denied: {} of {} requires confirmation and {}. Nothing was changed.",
+            action, path, reason
+        ),
+        None,
+    ))
+}
+
+/// Write a request into `dir` and poll for the app's response until `timeout` elapses.
+/// Both files are removed afterwards.
+async fn request_in(
+    dir: &Path,
+    tool: &str,
+    path: &str,
+    action: &str,
+    timeout: Duration,
+) -> std::io::Result<Decision> {
+    fs::create_dir_all(dir)?;
+
+    let now = Utc::now();
+    let request = ConfirmationRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        agent: activity::get_agent_name(),
+        tool: tool.to_string(),
+        path: path.to_string(),
+        action: action.to_string(),
+        requested_at: now.to_rfc3339(),
+        expires_at: (now + chrono::Duration::from_std(timeout).unwrap_or_else(|_| chrono::Duration::zero())).to_rfc3339(),
+    };
+
+    // Write then rename, so the app never sees a half-written request
+    let request_path = request_file(dir, &request.id);
+    let tmp = dir.join(format!("{}.tmp", request.id));
+    fs::write(&tmp, serde_json::to_string_pretty(&request)?)?;
+    fs::rename(&tmp, &request_path)?;
+
+    let response_path = response_file(dir, &request.id);
+    let deadline = Instant::now() + timeout;
+    let decision = loop {
+        if let Ok(content) = fs::read_to_string(&response_path) {
+            if let Ok(response) = serde_json::from_str::<ConfirmationResponse>(&content) {
+                break if response.approved { Decision::Approved } else { Decision::Denied };
+            }
+        }
+        if Instant::now() >= deadline {
+            break Decision::TimedOut;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    };
+
+    let _ = fs::remove_file(&request_path);
+    let _ = fs::remove_file(&response_path);
+    Ok(decision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answer the first request that shows up in `dir`
+    fn answer_when_asked(dir: PathBuf, approved: bool) -> std::thread::JoinHandle<ConfirmationRequest> {
+        std::thread::spawn(move || loop {
+            let request = fs::read_dir(&dir).ok().and_then(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .find(|p| p.extension().is_some_and(|e| e == "json") && !p.to_string_lossy().ends_with(".response.json"))
+            });
+            if let Some(request) = request {
+                let request: ConfirmationRequest =
+                    serde_json::from_str(&fs::read_to_string(request).unwrap()).unwrap();
+                let response = serde_json::to_string(&ConfirmationResponse { approved }).unwrap();
+                fs::write(response_file(&dir, &request.id), response).unwrap();
+                return request;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        })
+    }
+
+    #[tokio::test]
+    async fn test_approval_and_denial_are_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for approved in [true, false] {
+            let answerer = answer_when_asked(dir.path().to_path_buf(), approved);
+            let decision = request_in(dir.path(), "delete_file", "/tmp/a.txt", "deletion", Duration::from_secs(5))
+                .await
+                .unwrap();
+            let request = answerer.join().unwrap();
+
+            assert_eq!(decision, if approved { Decision::Approved } else { Decision::Denied });
+            assert_eq!(request.tool, "delete_file");
+            assert_eq!(request.path, "/tmp/a.txt");
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0, "request files are cleaned up");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_answer_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let decision = request_in(dir.path(), "move_file", "/tmp/a.txt", "move", Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        assert_eq!(decision, Decision::TimedOut);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_by_default() {
+        let policy = Policy::from(&AppConfig::default());
+        assert!(!policy.required);
+        assert!(confirm_destructive(policy, "delete_file", "/tmp/a.txt", "deletion").await.is_ok());
+    }
+}
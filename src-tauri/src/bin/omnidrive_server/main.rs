@@ -1,47 +1,204 @@
 //! OmniDrive Server — Standalone MCP server binary
 
 mod sandbox;
+#[path = "../../file_filter/extensions.rs"]
+mod extensions;
+#[path = "../../audit.rs"]
+mod audit;
+#[path = "../../ignore.rs"]
+mod ignore;
 pub mod tools; 
 pub mod config;
 mod activity;
 mod sse;
+mod confirm;
+mod watch;
+mod read_cache;
+#[cfg(test)]
+mod test_support;
 
 use rmcp::{ServerHandler, ServiceExt, transport::stdio};
-use rmcp::handler::server::tool::ToolRouter;
+use rmcp::handler::server::tool::{ToolCallContext, ToolRouter};
 use rmcp::model::*;
-use rmcp::tool_handler;
+use rmcp::service::RequestContext;
+use rmcp::{ErrorData, RoleServer};
 
 use config::load_config;
 use config::AppConfig;
 
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use notify::Watcher;
+use tokio::sync::{RwLock, Semaphore};
 
 /// The OmniDrive server handler
 #[derive(Clone)]
 pub struct OmniDriveServer {
-    pub config: Arc<RwLock<AppConfig>>,
+    /// Replaced wholesale on reload. Tools take a snapshot via `config_snapshot`, so a
+    /// reload never waits on an in-flight operation and never changes one midway.
+    pub config: Arc<RwLock<Arc<AppConfig>>>,
+    /// Limits how many expensive tools (grep, zip, recursive listing) run at once
+    pub heavy_ops: Arc<Semaphore>,
+    /// The permit count `heavy_ops` is sized for, so a reload can resize it
+    pub heavy_ops_limit: Arc<AtomicUsize>,
+    /// This session's resource subscriptions (see `watch`)
+    pub subscriptions: Arc<watch::Subscriptions>,
+    /// File contents kept by read_file when `read_cache_mb` is set, shared by all sessions
+    pub read_cache: Arc<read_cache::ReadCache>,
     pub tool_router: ToolRouter<Self>,
 }
 
-#[tool_handler]
+const DEFAULT_INSTRUCTIONS: &str = "OmniDrive provides secure access to user-specified local files.\n\
+     Tools: list_directory, read_file, write_file, search_files, \
+     grep_content, read_lines, move_file, delete_file, copy_file, \
+     get_file_info, batch_read, zip_files, unzip_files, patch_file, \
+     read_csv, validate_json, convert_file, trim_whitespace, \
+     create_symlink, set_permissions, read_env, move_into, copy_into, \
+     recent_activity, grep_binary, list_tree, concat_files, \
+     path_exists, patch_files, disk_usage, generate_manifest, \
+     verify_manifest, recent_files, list_supported_types, query_json, \
+     patch_json, format_file, grep_and_read, diff_manifests, \
+     empty_directory, check_ignore, compare_content, \
+     find_duplicates, largest_files.\n\
+     Subscribe to the file:// URI of a shared file or folder to be \
+     notified when anything under it changes.";
+
+/// The instructions sent on initialize: the built-in text, with the configured
+/// `server_instructions` appended (or in its place when `replace_instructions` is set).
+/// Without a config (mid-reload) the built-in text is used.
+pub fn instructions(config: Option<&AppConfig>) -> String {
+    let custom = config
+        .and_then(|c| c.server_instructions.as_deref().map(|text| (text.trim(), c.replace_instructions)))
+        .filter(|(text, _)| !text.is_empty());
+    match custom {
+        Some((text, true)) => text.to_string(),
+        Some((text, false)) => format!("{}\n\n{}", DEFAULT_INSTRUCTIONS, text),
+        None => DEFAULT_INSTRUCTIONS.to_string(),
+    }
+}
+
+/// Why every tool fails while `paused` is set
+pub const PAUSED_MESSAGE: &str =
+    "Sharing is paused in the OmniDrive app. No files can be accessed until the user resumes sharing.";
+
 impl ServerHandler for OmniDriveServer {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
-            instructions: Some(
-                "OmniDrive provides secure access to user-specified local files.\n\
-                 Tools: list_directory, read_file, write_file, search_files, \
-                 grep_content, read_lines, move_file, delete_file, copy_file, \
-                 get_file_info, batch_read, zip_files, unzip_files, patch_file."
-                    .into(),
-            ),
+            instructions: Some(instructions(
+                self.config.try_read().ok().map(|c| c.clone()).as_deref(),
+            )),
             capabilities: ServerCapabilities::builder()
                 .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
                 .build(),
             ..Default::default()
         }
     }
+
+    /// Route to the tool, unless sharing is paused. The config is checked on every
+    /// call, so pausing takes effect as soon as the server reloads it.
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        activity::with_agent(request_agent(&context), async {
+            if self.config_snapshot().await.paused {
+                activity::log_activity(&request.name, "system", None, "Refused: sharing is paused");
+                return Err(ErrorData::invalid_request(PAUSED_MESSAGE, None));
+            }
+            self.tool_router.call(ToolCallContext::new(self, request, context)).await
+        })
+        .await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
+    /// Keep the client's initialize info with its session; `request_agent` credits the
+    /// session's activity to the client's self-reported name
+    async fn initialize(
+        &self,
+        request: InitializeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, ErrorData> {
+        if context.peer.peer_info().is_none() {
+            context.peer.set_peer_info(request);
+        }
+        Ok(self.get_info())
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), ErrorData> {
+        activity::with_agent(request_agent(&context), self.subscribe_as(request, context)).await
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), ErrorData> {
+        activity::with_agent(request_agent(&context), async {
+            if self.subscriptions.unsubscribe(&request.uri) {
+                let path = watch::uri_to_path(&request.uri);
+                activity::log_activity("unsubscribe", "read", path.as_deref(), "Unsubscribed from changes");
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl OmniDriveServer {
+    /// `subscribe`, run on behalf of the requesting agent
+    async fn subscribe_as(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), ErrorData> {
+        let path = watch::uri_to_path(&request.uri).ok_or_else(|| {
+            ErrorData::invalid_params(format!("Only file:// URIs can be subscribed to, got {}", request.uri), None)
+        })?;
+        let config = self.config_snapshot().await;
+        let target = sandbox::validate_path(&path, &config)?.canonical_path;
+        if !target.exists() {
+            return Err(ErrorData::invalid_params(format!("Path not found: {}", path), None));
+        }
+
+        self.subscriptions
+            .subscribe(&request.uri, &target, context.peer)
+            .map_err(|e| ErrorData::invalid_params(e, None))?;
+        activity::log_activity("subscribe", "read", Some(&path), "Subscribed to changes");
+        Ok(())
+    }
+}
+
+/// The agent behind a request, as found by the SSE layer for its HTTP request. Unless
+/// it names itself there, it goes by the client name its session sent on initialize;
+/// with neither, activity is credited to the launching client.
+fn request_agent(context: &RequestContext<RoleServer>) -> activity::RequestAgent {
+    let mut agent: activity::RequestAgent = context
+        .extensions
+        .get::<axum::http::request::Parts>()
+        .and_then(|parts| parts.extensions.get::<activity::RequestAgent>())
+        .cloned()
+        .unwrap_or_default();
+    if agent.name.is_none() {
+        agent.name = context
+            .peer
+            .peer_info()
+            .map(|info| info.client_info.name.trim().to_string())
+            .filter(|name| !name.is_empty());
+    }
+    agent
 }
 
 /// Detect the name of the parent process (the MCP client)
@@ -71,44 +228,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let parent_name = detect_parent_name();
     activity::set_agent_name(parent_name);
 
-    let app_config = load_config();
-    eprintln!("[OmniDrive] Loaded config: {} folders", app_config.folders.len());
-
-    let server = OmniDriveServer::new(app_config);
-    let server_config = server.config.clone();
-
-    // Set up file watcher for live config reloads
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
-    let config_path = config::get_config_path();
-
-    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
-        if let Ok(event) = res {
-            if event.kind.is_modify() || event.kind.is_create() {
-                let _ = tx.blocking_send(());
-            }
-        }
-    })?;
-
-    if let Some(parent) = config_path.parent() {
-        watcher.watch(parent, notify::RecursiveMode::NonRecursive)?;
-    }
-
-    // Background task to handle reloads
-    tokio::spawn(async move {
-        while let Some(_) = rx.recv().await {
-            // Debounce or small delay to ensure file is written fully
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-            
-            eprintln!("[OmniDrive] Config file change detected. Reloading...");
-            let new_config = load_config();
-            let mut config = server_config.write().await;
-            *config = new_config;
-            eprintln!("[OmniDrive] Config reloaded successfully ({} folders).", config.folders.len());
-        }
-    });
-
     let mut use_sse = false;
     let mut port: u16 = 3199;
+    let mut idle_timeout_secs: u64 = 0;
+    let mut max_body_bytes = sse::DEFAULT_MAX_BODY_BYTES;
+    let mut verbose = false;
+    let mut stateful = false;
+    let mut profile: Option<String> = None;
+    let mut cors_headers: Vec<String> = Vec::new();
     let mut allowed_origins = vec![
         "https://chatgpt.com".to_string(),
         "https://gemini.google.com".to_string(),
@@ -134,6 +261,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            "--profile" => {
+                i += 1;
+                if i < args.len() {
+                    profile = Some(args[i].clone());
+                }
+            }
+            "--verbose" => {
+                verbose = true;
+            }
+            "--stateful" => {
+                stateful = true;
+            }
+            "--idle-timeout-secs" => {
+                i += 1;
+                if i < args.len() {
+                    if let Ok(secs) = args[i].parse() {
+                        idle_timeout_secs = secs;
+                    }
+                }
+            }
+            "--max-body-bytes" => {
+                i += 1;
+                if i < args.len() {
+                    if let Ok(bytes) = args[i].parse() {
+                        max_body_bytes = bytes;
+                    }
+                }
+            }
+            "--cors-headers" => {
+                i += 1;
+                if i < args.len() {
+                    cors_headers = args[i]
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
+            }
             "--allowed-origins" => {
                 i += 1;
                 if i < args.len() {
@@ -148,10 +313,69 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         i += 1;
     }
 
+    let config_path = config::get_config_path(profile.as_deref());
+    let mut app_config = load_config(&config_path);
+    eprintln!(
+        "[OmniDrive] Loaded config from {:?}: {} folders",
+        config_path,
+        app_config.folders.len()
+    );
+    for warning in config::check_folders(&mut app_config) {
+        eprintln!("[OmniDrive] Warning: {}", warning);
+    }
+    activity::set_redact_home(app_config.redact_home);
+    activity::set_sign_activity(app_config.sign_activity);
+    tools::set_max_response_bytes(app_config.max_response_bytes);
+    tools::set_io_retry_attempts(app_config.io_retry_attempts);
+
+    let stateful = stateful || app_config.sse_stateful;
+
+    let server = OmniDriveServer::new(app_config);
+    let server_config = server.config.clone();
+    let reload_server = server.clone();
+
+    // Watch the config file for live reloads
+    let mut rx = config::watch_config(config_path.clone());
+
+    // Background task to handle reloads
+    let reload_path = config_path.clone();
+    tokio::spawn(async move {
+        while let Some(_) = rx.recv().await {
+            // Debounce or small delay to ensure file is written fully
+            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+            
+            eprintln!("[OmniDrive] Config file change detected. Reloading...");
+            let mut new_config = load_config(&reload_path);
+            for warning in config::check_folders(&mut new_config) {
+                eprintln!("[OmniDrive] Warning: {}", warning);
+            }
+            activity::set_redact_home(new_config.redact_home);
+            activity::set_sign_activity(new_config.sign_activity);
+            tools::set_max_response_bytes(new_config.max_response_bytes);
+            tools::set_io_retry_attempts(new_config.io_retry_attempts);
+            reload_server.set_max_heavy_operations(new_config.max_heavy_operations);
+            let folder_count = new_config.folders.len();
+            let paused = new_config.paused;
+            // The lock is only held for the swap; running tools keep their snapshot
+            *server_config.write().await = Arc::new(new_config);
+            eprintln!("[OmniDrive] Config reloaded successfully ({} folders).", folder_count);
+            if paused {
+                eprintln!("[OmniDrive] Sharing is paused. Tool calls will be refused.");
+            }
+        }
+    });
+
     if use_sse {
         activity::log_connect();
-        let _watcher = watcher; 
-        sse::start_sse_server(server, port, allowed_origins).await?;
+        sse::start_sse_server(server, sse::SseOptions {
+            port,
+            allowed_origins,
+            cors_headers,
+            stateful,
+            idle_timeout_secs,
+            verbose,
+            max_body_bytes,
+        }).await?;
     } else {
         eprintln!("[OmniDrive] Server ready. Listening on stdio.");
         
@@ -162,11 +386,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .await
             .inspect_err(|e| eprintln!("[OmniDrive] Error: {}", e))?;
 
-        // Keep watcher alive
-        let _watcher = watcher;
-
         service.waiting().await?;
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf};
+
+    /// A raw JSON-RPC client over an in-memory pipe, like a client on stdio
+    struct Client {
+        write: WriteHalf<DuplexStream>,
+        lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    }
+
+    impl Client {
+        fn connect(server: OmniDriveServer) -> Self {
+            let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(async move {
+                if let Ok(running) = server.serve(server_io).await {
+                    let _ = running.waiting().await;
+                }
+            });
+            let (read, write) = tokio::io::split(client_io);
+            Client { write, lines: BufReader::new(read).lines() }
+        }
+
+        async fn send(&mut self, message: serde_json::Value) {
+            self.write.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        }
+
+        /// Send a request and wait for the response with the same id
+        async fn request(&mut self, id: u64, method: &str, params: serde_json::Value) -> serde_json::Value {
+            self.send(serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await;
+            loop {
+                let line = self.lines.next_line().await.unwrap().expect("server closed the pipe");
+                let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                if message["id"] == id {
+                    return message;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paused_sharing_blocks_every_tool() {
+        let sandbox = test_support::TestSandbox::with_config(|c| c.paused = true);
+        sandbox.write("notes.txt", "hello");
+        let mut client = Client::connect(sandbox.server.clone());
+
+        let init = serde_json::json!({
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": { "name": "paused-test", "version": "0" }
+        });
+        assert!(client.request(0, "initialize", init).await.get("result").is_some());
+        client.send(serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+
+        let tools = sandbox.server.tool_router.list_all();
+        assert!(!tools.is_empty());
+        for (id, tool) in (1..).zip(&tools) {
+            let call = serde_json::json!({ "name": tool.name, "arguments": { "path": sandbox.path("notes.txt") } });
+            let response = client.request(id, "tools/call", call).await;
+            let message = response["error"]["message"].as_str().unwrap_or_default();
+            assert_eq!(message, PAUSED_MESSAGE, "{} was not blocked: {}", tool.name, response);
+        }
+        assert_eq!(std::fs::read_to_string(sandbox.root.join("notes.txt")).unwrap(), "hello");
+
+        // Unpausing lets the next call through without reconnecting
+        let resumed = AppConfig { paused: false, ..(*sandbox.server.config_snapshot().await).clone() };
+        *sandbox.server.config.write().await = Arc::new(resumed);
+        let call = serde_json::json!({ "name": "read_file", "arguments": { "path": sandbox.path("notes.txt") } });
+        let response = client.request(1000, "tools/call", call).await;
+        assert!(response.to_string().contains("hello"), "{}", response);
+    }
+}
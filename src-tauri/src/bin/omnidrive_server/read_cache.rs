@@ -0,0 +1,130 @@
+//! Optional in-memory cache of file contents for `read_file`, so an agent
+//! re-reading the same file doesn't go back to disk each time.
+//!
+//! Entries remember the file's modification time and size when it was read; a
+//! read that finds either changed misses and replaces the entry. The least
+//! recently used entries are evicted to stay within the `read_cache_mb` budget.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+struct Entry {
+    modified: SystemTime,
+    len: u64,
+    contents: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_path: HashMap<PathBuf, Entry>,
+    bytes: usize,
+    /// Bumped on every access; orders entries for eviction
+    clock: u64,
+}
+
+/// Cached file contents shared by every session of the server
+#[derive(Default)]
+pub struct ReadCache {
+    entries: Mutex<Entries>,
+}
+
+impl ReadCache {
+    /// The contents of `path`, from the cache when the file hasn't changed since it
+    /// was cached. A `budget` of 0 bytes disables the cache and drops its entries.
+    pub fn read(&self, path: &Path, budget: usize) -> io::Result<Arc<Vec<u8>>> {
+        if budget == 0 {
+            self.clear();
+            return fs::read(path).map(Arc::new);
+        }
+
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        if let Some(contents) = self.get(path, modified, metadata.len()) {
+            return Ok(contents);
+        }
+
+        let contents = Arc::new(fs::read(path)?);
+        // Only cache what was read if the file didn't change while reading it
+        if contents.len() as u64 == metadata.len() {
+            self.insert(path.to_path_buf(), modified, contents.clone(), budget);
+        }
+        Ok(contents)
+    }
+
+    fn get(&self, path: &Path, modified: SystemTime, len: u64) -> Option<Arc<Vec<u8>>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.by_path.get_mut(path)?;
+        if entry.modified != modified || entry.len != len {
+            return None;
+        }
+        entry.last_used = clock;
+        Some(entry.contents.clone())
+    }
+
+    /// Remember `contents`, evicting the least recently used entries to stay within
+    /// `budget` bytes. Files bigger than the whole budget are not cached.
+    fn insert(&self, path: PathBuf, modified: SystemTime, contents: Arc<Vec<u8>>, budget: usize) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(old) = entries.by_path.remove(&path) {
+            entries.bytes -= old.contents.len();
+        }
+        if contents.len() > budget {
+            return;
+        }
+
+        while entries.bytes + contents.len() > budget {
+            let Some(oldest) = entries.by_path.iter().min_by_key(|(_, e)| e.last_used).map(|(p, _)| p.clone()) else {
+                break;
+            };
+            if let Some(evicted) = entries.by_path.remove(&oldest) {
+                entries.bytes -= evicted.contents.len();
+            }
+        }
+
+        entries.clock += 1;
+        entries.bytes += contents.len();
+        let entry = Entry { modified, len: contents.len() as u64, contents, last_used: entries.clock };
+        entries.by_path.insert(path, entry);
+    }
+
+    fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.by_path.is_empty() {
+            *entries = Entries::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ReadCache::default();
+        let paths: Vec<PathBuf> = ["a", "b", "c"].iter().map(|n| dir.path().join(n)).collect();
+        for path in &paths {
+            fs::write(path, [0u8; 40]).unwrap();
+        }
+
+        cache.read(&paths[0], 100).unwrap();
+        cache.read(&paths[1], 100).unwrap();
+        // Touch a so b is now the oldest
+        cache.read(&paths[0], 100).unwrap();
+        cache.read(&paths[2], 100).unwrap();
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.bytes, 80);
+        assert!(entries.by_path.contains_key(&paths[0]));
+        assert!(!entries.by_path.contains_key(&paths[1]));
+        assert!(entries.by_path.contains_key(&paths[2]));
+    }
+}
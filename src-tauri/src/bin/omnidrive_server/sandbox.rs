@@ -1,21 +1,255 @@
 //! Path sandbox — validates all file paths are within allowed folders.
 //! Also supports `.mcpignore` files in shared folder roots for pattern-based exclusion.
 
+use std::fmt;
 use std::fs;
-use std::io::BufRead;
 use std::path::Path;
 
+use rmcp::ErrorData;
+
 use crate::config::{AppConfig, Permission, SharedFolder};
+use crate::ignore::{self, IgnoreRule};
+
+/// Why a path was rejected by the sandbox
+#[derive(Debug, Clone, PartialEq)]
+pub enum SandboxError {
+    /// The path is not inside any enabled shared folder
+    NotInFolder(String),
+    /// The path is in a shared folder that is not writable
+    ReadOnly { path: String, folder: String },
+    /// The path already exists in a create-only shared folder
+    CreateOnly { path: String, folder: String },
+    /// The path already exists in an append-only shared folder and the write isn't an append
+    AppendOnly { path: String, folder: String },
+    /// The path is excluded by `.mcpignore` or global ignore rules
+    Ignored(String),
+    /// The path contains `..` traversal components
+    Traversal(String),
+    /// A destructive operation targeted a path that doesn't exist
+    NotFound(String),
+    /// The path is in a shared folder that is missing or unreadable (e.g. an unmounted drive)
+    Unavailable { path: String, folder: String },
+    /// The path is inside OmniDrive's own data directory (config, activity log, keys)
+    Protected(String),
+    /// The path exceeds a configured length or depth limit
+    TooLong { path: String, reason: String },
+    /// A write targeted a shared folder's `.mcpignore`, which only the user may change
+    IgnoreRules(String),
+    /// A destructive operation on a directory would carry along ignored entries inside it
+    ContainsIgnored { path: String, ignored: String },
+    /// The path is in a shared folder outside the current agent's `agent_scopes`
+    OutOfScope { path: String, agent: String },
+    /// The write's parent directory resolves (e.g. through a symlink) outside its writable folder
+    ParentOutsideFolder { path: String, parent: String, folder: String },
+    /// The write targets a symlink whose destination may not be written
+    LinkTarget { path: String, target: String, reason: String },
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::NotInFolder(path) => {
+                write!(f, "Access denied: Path '{}' is not within any shared folder.", path)
+            }
+            SandboxError::ReadOnly { path, folder } => write!(
+                f,
+                "Write access denied: '{}' is in a read-only shared folder. \
+                 The folder '{}' must be set to Read/Write mode in OmniDrive.",
+                path, folder
+            ),
+            SandboxError::CreateOnly { path, folder } => write!(
+                f,
+                "Write access denied: '{}' already exists and '{}' is a create-only folder. \
+                 New files can be added there, but existing ones can't be overwritten, moved or deleted.",
+                path, folder
+            ),
+            SandboxError::AppendOnly { path, folder } => write!(
+                f,
+                "Write access denied: '{}' already exists and '{}' is an append-only folder. \
+                 Existing files can only be appended to (write_file with append=true), \
+                 not overwritten, truncated, moved or deleted.",
+                path, folder
+            ),
+            SandboxError::Ignored(path) => {
+                write!(f, "Access denied: '{}' is excluded by .mcpignore or global ignore rules.", path)
+            }
+            SandboxError::Traversal(path) => write!(
+                f,
+                "Access denied: Path traversal characters '..' are not allowed: {}",
+                path
+            ),
+            SandboxError::NotFound(path) => write!(
+                f,
+                "Path not found: '{}'. Cannot perform destructive operation on a non-existent path.",
+                path
+            ),
+            SandboxError::TooLong { path, reason } => write!(
+                f,
+                "Path rejected: {}. Path: '{}'",
+                reason, abbreviate(path)
+            ),
+            SandboxError::Protected(path) => write!(
+                f,
+                "Access denied: '{}' is inside OmniDrive's data directory, which is never shared.",
+                path
+            ),
+            SandboxError::IgnoreRules(path) => write!(
+                f,
+                "Write access denied: '{}' holds the folder's ignore rules, which only the user can change.",
+                path
+            ),
+            SandboxError::ContainsIgnored { path, ignored } => write!(
+                f,
+                "Access denied: '{}' contains '{}', which is excluded by .mcpignore or global ignore rules \
+                 and cannot be moved or deleted along with it.",
+                path, ignored
+            ),
+            SandboxError::ParentOutsideFolder { path, parent, folder } => write!(
+                f,
+                "Write access denied: the parent directory of '{}' resolves to '{}', which is outside \
+                 the writable folder '{}'. No directories were created.",
+                path, parent, folder
+            ),
+            SandboxError::OutOfScope { path, agent } => write!(
+                f,
+                "Access denied: '{}' is in a shared folder that '{}' is not allowed to use.",
+                path, agent
+            ),
+            SandboxError::Unavailable { path, folder } => write!(
+                f,
+                "Shared folder '{}' is currently unavailable (missing or not mounted), so '{}' cannot be accessed.",
+                folder, path
+            ),
+            SandboxError::LinkTarget { path, target, reason } => write!(
+                f,
+                "Write access denied: '{}' is a symbolic link to '{}'. {}",
+                path, target, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SandboxError {}
+
+impl From<SandboxError> for ErrorData {
+    fn from(err: SandboxError) -> Self {
+        let message = crate::activity::redact_home(&err.to_string());
+        match err {
+            SandboxError::NotFound(_) => ErrorData::resource_not_found(message, None),
+            _ => ErrorData::invalid_params(message, None),
+        }
+    }
+}
+
+/// Shorten a pathological path for error messages
+fn abbreviate(path: &str) -> String {
+    const SHOWN: usize = 80;
+    if path.chars().count() <= SHOWN {
+        return path.to_string();
+    }
+    let head: String = path.chars().take(SHOWN).collect();
+    format!("{}… ({} bytes)", head, path.len())
+}
+
+/// Check `target` against the configured length and depth limits
+fn check_path_limits(target: &Path, config: &AppConfig) -> Result<(), String> {
+    let length = target.as_os_str().len();
+    if length > config.max_path_length {
+        return Err(format!("it is {} bytes long (limit: {})", length, config.max_path_length));
+    }
+    let depth = target.components().count();
+    if depth > config.max_path_components {
+        return Err(format!("it is {} levels deep (limit: {})", depth, config.max_path_components));
+    }
+    if let Some(name) = target.iter().find(|name| name.len() > config.max_filename_length) {
+        return Err(format!(
+            "the name '{}' is {} bytes long (limit: {})",
+            abbreviate(&name.to_string_lossy()),
+            name.len(),
+            config.max_filename_length
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `target` lies in `dir`, comparing resolved paths so a symlink into
+/// `dir` (or a differently spelled path to it) is caught too
+fn is_inside(target: &Path, dir: &Path) -> bool {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    target.starts_with(&dir) || resolve_existing(target).starts_with(&dir)
+}
+
+/// Canonicalize the longest existing ancestor of `path` and re-append the rest,
+/// so paths that don't exist yet still resolve through symlinked parents
+fn resolve_existing(path: &Path) -> std::path::PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(resolved) = fs::canonicalize(existing) {
+            return rest.iter().rev().fold(resolved, |acc, part| acc.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Whether `target` is `prefix` itself or lies below it, matching on a path boundary
+fn has_path_prefix(target: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches(['/', '\\']);
+    match target.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\'),
+        None => false,
+    }
+}
 
 /// Result of a sandbox validation
+#[derive(Debug)]
 pub struct ValidatedPath {
     pub folder: SharedFolder,
     pub canonical_path: std::path::PathBuf,
 }
 
-/// Validate that a path is within an allowed, enabled folder.
+/// Validate that a path is within an allowed, enabled folder that the current agent may use.
 /// Returns the matching SharedFolder and the canonicalized path.
-pub fn validate_path(path: &str, config: &AppConfig) -> Result<ValidatedPath, String> {
+pub fn validate_path(path: &str, config: &AppConfig) -> Result<ValidatedPath, SandboxError> {
+    validate_path_as(path, config, &current_agent(config))
+}
+
+/// The agent making the request, as far as scoping is concerned (see
+/// `activity::scope_identity`). It only matters once scopes are configured.
+fn current_agent(config: &AppConfig) -> String {
+    if config.agent_scopes.is_empty() { String::new() } else { crate::activity::scope_identity() }
+}
+
+/// `validate_path` on behalf of `agent`
+fn validate_path_as(path: &str, config: &AppConfig, agent: &str) -> Result<ValidatedPath, SandboxError> {
+    let (validated, folder_root) = locate(path, config, agent)?;
+
+    // Check .mcpignore patterns
+    let case_insensitive = ignore_case_insensitive(&validated.folder, config);
+    if is_ignored(&validated.canonical_path, &folder_root, &config.global_ignore, case_insensitive) {
+        return Err(SandboxError::Ignored(path.to_string()));
+    }
+    Ok(validated)
+}
+
+/// The ignore rule that hides `path`, or `None` if it is visible. Every other
+/// `validate_path` check still applies, so this only explains shared paths.
+pub fn matching_ignore_rule(path: &str, config: &AppConfig) -> Result<Option<IgnoreRule>, SandboxError> {
+    let (validated, folder_root) = locate(path, config, &current_agent(config))?;
+    let case_insensitive = ignore_case_insensitive(&validated.folder, config);
+    let rules = ignore::load_rules(&folder_root, &config.global_ignore);
+    Ok(ignore::first_match(&rules, &validated.canonical_path, &folder_root, case_insensitive).cloned())
+}
+
+/// Find the shared folder holding `path`, applying every `validate_path` check
+/// except ignore rules. Also returns the folder's canonical root.
+fn locate(path: &str, config: &AppConfig, agent: &str) -> Result<(ValidatedPath, std::path::PathBuf), SandboxError> {
     let target = Path::new(path);
     
     // Convert to absolute path manually to avoid canonicalize() requirement for non-existent files
@@ -25,158 +259,226 @@ pub fn validate_path(path: &str, config: &AppConfig) -> Result<ValidatedPath, St
         std::env::current_dir().map(|d| d.join(target)).unwrap_or_else(|_| target.to_path_buf())
     };
 
+    check_path_limits(&target_abs, config).map_err(|reason| SandboxError::TooLong {
+        path: path.to_string(),
+        reason,
+    })?;
+
     let target_str = target_abs.to_string_lossy().to_string();
 
     // Prevent directory traversal
     if target_str.contains("..") {
-        return Err(format!("Access denied: Path traversal characters '..' are not allowed: {}", path));
+        return Err(SandboxError::Traversal(path.to_string()));
+    }
+
+    // OmniDrive's own state is off limits, even if a shared folder contains it
+    if is_inside(&target_abs, &crate::config::get_config_dir()) {
+        return Err(SandboxError::Protected(path.to_string()));
     }
 
     for folder in &config.folders {
         if !folder.enabled { continue; }
 
         // Canonicalize the shared folder path (this MUST exist)
-        if let Ok(folder_canonical) = fs::canonicalize(&folder.path) {
-            let folder_str = folder_canonical.to_string_lossy().to_string();
-
-            // Check if target starts with folder path
-            if target_str.starts_with(&folder_str) {
-                let remaining = &target_str[folder_str.len()..];
-                if remaining.is_empty() || remaining.starts_with('/') || remaining.starts_with('\\') {
-                    // Check .mcpignore patterns
-                    if is_ignored(&target_abs, &folder_canonical) {
-                        return Err(format!(
-                            "Access denied: '{}' is excluded by .mcpignore rules.", path
-                        ));
+        match fs::canonicalize(&folder.path) {
+            Ok(folder_canonical) => {
+                // Check if target is inside the folder
+                if has_path_prefix(&target_str, &folder_canonical.to_string_lossy()) {
+                    if !agent_may_access(config, agent, folder) {
+                        return Err(SandboxError::OutOfScope {
+                            path: path.to_string(),
+                            agent: agent.to_string(),
+                        });
                     }
-                    return Ok(ValidatedPath {
+
+                    let validated = ValidatedPath {
                         folder: folder.clone(),
                         canonical_path: target_abs,
-                    });
+                    };
+                    return Ok((validated, folder_canonical));
                 }
             }
+            // The folder can't be resolved right now (e.g. an unmounted drive). If the
+            // target sits under its configured path, say so instead of "not shared".
+            Err(_) if has_path_prefix(&target_str, &folder.path) => {
+                return Err(SandboxError::Unavailable {
+                    path: path.to_string(),
+                    folder: folder.path.clone(),
+                });
+            }
+            Err(_) => {}
         }
     }
 
-    Err(format!("Access denied: Path '{}' is not within any shared folder.", path))
+    Err(SandboxError::NotInFolder(path.to_string()))
+}
+
+/// Validate that a path is within a writable folder. Ignored paths are rejected
+/// by `validate_path`, and the folder's `.mcpignore` itself can't be written, so
+/// an agent can't lift the protection. In create-only and append-only folders
+/// only paths that don't exist yet are writable.
+pub fn validate_writable(path: &str, config: &AppConfig) -> Result<ValidatedPath, SandboxError> {
+    validate_write(path, config, false)
+}
+
+/// Validate that `content` may be appended to a path: like `validate_writable`,
+/// except that existing files in append-only folders are accepted too
+pub fn validate_appendable(path: &str, config: &AppConfig) -> Result<ValidatedPath, SandboxError> {
+    validate_write(path, config, true)
 }
 
-/// Validate that a path is within a writable folder
-pub fn validate_writable(path: &str, config: &AppConfig) -> Result<ValidatedPath, String> {
+fn validate_write(path: &str, config: &AppConfig, append: bool) -> Result<ValidatedPath, SandboxError> {
     let validated = validate_path(path, config)?;
+    let exists = validated.canonical_path.symlink_metadata().is_ok();
+    let folder = validated.folder.path.clone();
 
-    if validated.folder.permission != Permission::ReadWrite {
-        return Err(format!(
-            "Write access denied: '{}' is in a read-only shared folder. \
-             The folder '{}' must be set to Read/Write mode in OmniDrive.",
-            path, validated.folder.path
-        ));
+    match validated.folder.permission {
+        Permission::ReadWrite => {}
+        Permission::CreateOnly if exists => {
+            return Err(SandboxError::CreateOnly { path: path.to_string(), folder });
+        }
+        Permission::AppendOnly if exists && !append => {
+            return Err(SandboxError::AppendOnly { path: path.to_string(), folder });
+        }
+        Permission::CreateOnly | Permission::AppendOnly => {}
+        Permission::ReadOnly => {
+            return Err(SandboxError::ReadOnly { path: path.to_string(), folder });
+        }
     }
 
-    Ok(validated)
-}
+    if is_ignore_file(&validated) {
+        return Err(SandboxError::IgnoreRules(path.to_string()));
+    }
 
-/// Validate that a path exists and is within a writable folder (for destructive ops like delete/move)
-pub fn validate_destructive(path: &str, config: &AppConfig) -> Result<ValidatedPath, String> {
-    let validated = validate_writable(path, config)?;
+    // Directories created for the write must stay in this folder; a symlinked
+    // ancestor would otherwise lead the creation somewhere else
+    if let Some(parent) = parent_outside_folder(&validated) {
+        return Err(SandboxError::ParentOutsideFolder {
+            path: path.to_string(),
+            parent: parent.to_string_lossy().to_string(),
+            folder,
+        });
+    }
 
-    if !validated.canonical_path.exists() {
-        return Err(format!(
-            "Path not found: '{}'. Cannot perform destructive operation on a non-existent path.",
-            path
-        ));
+    // Writes (and chmod) follow a symlink at the path itself, so the file they would
+    // really change has to pass the same checks
+    if let Some(destination) = symlink_destination(&validated.canonical_path) {
+        let link_error = |target: &Path, reason: String| SandboxError::LinkTarget {
+            path: path.to_string(),
+            target: target.to_string_lossy().to_string(),
+            reason,
+        };
+        match destination {
+            Ok(real) => {
+                validate_write(&real.to_string_lossy(), config, append).map_err(|e| link_error(&real, e.to_string()))?;
+            }
+            Err(target) => {
+                return Err(link_error(&target, "The link's destination does not exist.".to_string()));
+            }
+        }
     }
 
     Ok(validated)
 }
 
-/// Check if a path should be ignored based on .mcpignore rules in the shared folder root
-fn is_ignored(target: &Path, folder_root: &Path) -> bool {
-    let ignore_file = folder_root.join(".mcpignore");
-    if !ignore_file.exists() {
-        return false;
+/// Where the symlink at `path` leads, or `None` if `path` isn't a symlink. A dangling
+/// link gives `Err` with the destination it names.
+fn symlink_destination(path: &Path) -> Option<Result<std::path::PathBuf, std::path::PathBuf>> {
+    if !path.symlink_metadata().ok()?.file_type().is_symlink() {
+        return None;
     }
+    Some(fs::canonicalize(path).map_err(|_| fs::read_link(path).unwrap_or_else(|_| path.to_path_buf())))
+}
 
-    let file = match fs::File::open(&ignore_file) {
-        Ok(f) => f,
-        Err(_) => return false,
-    };
+/// Where the target's parent directory really is, if that's outside its shared folder.
+/// Missing directories are resolved through their longest existing ancestor.
+fn parent_outside_folder(validated: &ValidatedPath) -> Option<std::path::PathBuf> {
+    let root = fs::canonicalize(&validated.folder.path).ok()?;
+    if resolve_existing(&validated.canonical_path) == root {
+        return None;
+    }
+    let parent = resolve_existing(validated.canonical_path.parent()?);
+    (!parent.starts_with(&root)).then_some(parent)
+}
 
-    let relative = match target.strip_prefix(folder_root) {
-        Ok(r) => r.to_string_lossy().to_string(),
-        Err(_) => return false,
-    };
+/// Validate that a path exists and is within a writable folder (for destructive ops like delete/move).
+/// A directory is rejected if anything inside it is ignored, since moving it would move that too.
+/// Nothing passes in a create-only or append-only folder, as the target must already exist.
+pub fn validate_destructive(path: &str, config: &AppConfig) -> Result<ValidatedPath, SandboxError> {
+    let validated = validate_writable(path, config)?;
 
-    let reader = std::io::BufReader::new(file);
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
-        };
-        let pattern = line.trim();
-        // Skip empty lines and comments
-        if pattern.is_empty() || pattern.starts_with('#') {
-            continue;
+    if !validated.canonical_path.exists() {
+        return Err(SandboxError::NotFound(path.to_string()));
+    }
+
+    if validated.canonical_path.is_dir() {
+        if let Some(ignored) = first_ignored_within(&validated, config) {
+            return Err(SandboxError::ContainsIgnored {
+                path: path.to_string(),
+                ignored: ignored.to_string_lossy().to_string(),
+            });
         }
+    }
 
-        // Match against the relative path — support glob patterns
-        let glob_str = if pattern.contains('/') {
-            pattern.to_string()
-        } else {
-            // Bare name like "node_modules" should match anywhere in the tree  
-            format!("**/{}", pattern)
-        };
+    Ok(validated)
+}
 
-        if let Ok(compiled) = glob::Pattern::new(&glob_str) {
-            if compiled.matches(&relative) {
-                return true;
-            }
-            // Also check if any parent directory matches (e.g. "node_modules" ignores all children)
-            let with_wildcard = format!("{}/**", glob_str);
-            if let Ok(compiled_deep) = glob::Pattern::new(&with_wildcard) {
-                if compiled_deep.matches(&relative) {
-                    return true;
-                }
-            }
-        }
+/// Whether `agent` may use `folder`. Without `agent_scopes` every agent may; otherwise
+/// a listed agent gets only its folders and an unlisted one gets all of them, or none
+/// with `deny_unscoped_agents`.
+fn agent_may_access(config: &AppConfig, agent: &str, folder: &SharedFolder) -> bool {
+    if config.agent_scopes.is_empty() {
+        return true;
+    }
+    match config.agent_scopes.get(agent) {
+        Some(folders) => folders.iter().any(|f| Path::new(f) == Path::new(&folder.path)),
+        None => !config.deny_unscoped_agents,
     }
+}
 
-    false
+/// Whether ignore rules in `folder` match case-insensitively: the folder's
+/// setting, else the global one, else the platform's filesystem convention
+fn ignore_case_insensitive(folder: &SharedFolder, config: &AppConfig) -> bool {
+    folder
+        .case_insensitive_ignore
+        .or(config.case_insensitive_ignore)
+        .unwrap_or(cfg!(any(windows, target_os = "macos")))
 }
 
-/// Check if a file extension is supported for sharing with AI agents
-pub fn is_supported_extension(filename: &str) -> bool {
-    let supported_extensions = [
-        // Code
-        "rs", "py", "js", "ts", "tsx", "jsx", "go", "java", "c", "cpp", "h", "hpp",
-        "rb", "php", "swift", "kt", "sh", "bat", "ps1", "r", "scala", "lua",
-        "dart", "zig", "nim", "ex", "exs", "clj", "hs", "ml", "fs", "cs",
-        // Text
-        "txt", "md", "csv", "log", "env", "gitignore", "dockerignore",
-        // Data
-        "json", "yaml", "yml", "toml", "xml", "html", "htm", "css", "scss",
-        "less", "sql", "graphql", "proto", "ini", "cfg", "conf",
-        // Documents
-        "pdf",
-        // Images
-        "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "ico",
-    ];
+/// Whether the path is the `.mcpignore` at its shared folder's root
+fn is_ignore_file(validated: &ValidatedPath) -> bool {
+    let Ok(root) = fs::canonicalize(&validated.folder.path) else {
+        return false;
+    };
+    let target = resolve_existing(&validated.canonical_path);
+    target == root.join(ignore::IGNORE_FILE_NAME)
+}
 
-    let extensionless_supported = [
-        "Makefile", "Dockerfile", "Jenkinsfile", "Vagrantfile",
-        "Gemfile", "Rakefile", "Procfile", "LICENSE", "README",
-        "CHANGELOG", "CONTRIBUTING", "AUTHORS",
-    ];
+/// The first ignored entry under a validated directory, if any
+fn first_ignored_within(validated: &ValidatedPath, config: &AppConfig) -> Option<std::path::PathBuf> {
+    let root = fs::canonicalize(&validated.folder.path).ok()?;
+    let case_insensitive = ignore_case_insensitive(&validated.folder, config);
+    let rules = ignore::load_rules(&root, &config.global_ignore);
+
+    walkdir::WalkDir::new(&validated.canonical_path)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .find(|p| ignore::first_match(&rules, p, &root, case_insensitive).is_some())
+}
 
-    if let Some(ext) = Path::new(filename).extension().and_then(|e| e.to_str()) {
-        supported_extensions.contains(&ext.to_lowercase().as_str())
-    } else {
-        let basename = Path::new(filename)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        extensionless_supported.contains(&basename)
-    }
+/// Check if a path should be ignored based on the global ignore list or
+/// .mcpignore rules in the shared folder root
+fn is_ignored(target: &Path, folder_root: &Path, global_ignore: &[String], case_insensitive: bool) -> bool {
+    let rules = ignore::load_rules(folder_root, global_ignore);
+    ignore::first_match(&rules, target, folder_root, case_insensitive).is_some()
+}
+
+/// Check if a file extension is supported for sharing with AI agents, either
+/// built in or listed in the config's `extra_extensions`
+pub fn is_supported_extension(filename: &str, extra_extensions: &[String]) -> bool {
+    crate::extensions::is_supported_filename(filename, extra_extensions)
 }
 
 /// Determine if a file should be returned as base64 (binary) or text
@@ -217,36 +519,311 @@ pub fn is_image(filename: &str) -> bool {
 mod tests {
     use super::*;
     use crate::config::AppConfig;
+    use crate::test_support::config_for;
 
     fn test_config() -> AppConfig {
-        AppConfig {
-            folders: vec![
-                SharedFolder {
-                    path: "/tmp/test-shared".to_string(),
-                    permission: Permission::ReadWrite,
-                    enabled: true,
-                    available: true,
-                },
-            ],
-            max_file_size_mb: 50,
+        config_for(Path::new("/tmp/test-shared"), Permission::ReadWrite)
+    }
+
+    /// A config sharing a fresh temp dir, plus the dir guard and its canonical path
+    fn temp_config(permission: Permission) -> (AppConfig, tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        (config_for(&root, permission), dir, root)
+    }
+
+    #[test]
+    fn test_unavailable_folder_gets_clear_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("usb-drive");
+        let mut config = config_for(&missing, Permission::ReadWrite);
+        config.folders[0].available = false;
+
+        let target = missing.join("notes.txt").to_string_lossy().to_string();
+        match validate_path(&target, &config) {
+            Err(SandboxError::Unavailable { folder, .. }) => assert_eq!(folder, missing.to_string_lossy()),
+            other => panic!("expected Unavailable, got {:?}", other.map(|v| v.canonical_path)),
+        }
+
+        // Paths outside the folder are still reported as not shared
+        let outside = dir.path().join("usb-drive-2/x.txt").to_string_lossy().to_string();
+        assert!(matches!(validate_path(&outside, &config), Err(SandboxError::NotInFolder(_))));
+    }
+
+    #[test]
+    fn test_folder_that_disappears_is_reported_unavailable() {
+        let (config, dir, root) = temp_config(Permission::ReadWrite);
+        let target = root.join("a.txt").to_string_lossy().to_string();
+        assert!(validate_path(&target, &config).is_ok());
+
+        // Removed after startup, so the self-check never flagged it
+        drop(dir);
+        assert!(config.folders[0].available);
+        let err = validate_path(&target, &config).err().unwrap();
+        assert!(matches!(err, SandboxError::Unavailable { .. }));
+        assert!(err.to_string().contains("currently unavailable"));
+    }
+
+    #[test]
+    fn test_data_dir_is_always_denied() {
+        let data_dir = crate::config::get_config_dir();
+        let home = data_dir.parent().unwrap();
+        let config = config_for(home, Permission::ReadWrite);
+
+        for name in ["config.json", "activity.jsonl", "activity.key", ""] {
+            let target = data_dir.join(name).to_string_lossy().to_string();
+            assert!(
+                matches!(validate_path(&target, &config), Err(SandboxError::Protected(_))),
+                "{} should be protected",
+                target
+            );
+            assert!(validate_writable(&target, &config).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_inside_sees_through_symlinks() {
+        let (_config, _dir, root) = temp_config(Permission::ReadWrite);
+        let protected = root.join("state");
+        fs::create_dir(&protected).unwrap();
+        std::os::unix::fs::symlink(&protected, root.join("link")).unwrap();
+
+        assert!(is_inside(&protected.join("a.json"), &protected));
+        assert!(is_inside(&root.join("link").join("new.json"), &protected));
+        assert!(!is_inside(&root.join("state-2/a.json"), &protected));
+    }
+
+    #[test]
+    fn test_agent_scopes_restrict_folders() {
+        let (mut config, _dir, root) = temp_config(Permission::ReadWrite);
+        let other_dir = tempfile::tempdir().unwrap();
+        let other = fs::canonicalize(other_dir.path()).unwrap();
+        config.folders.extend(config_for(&other, Permission::ReadWrite).folders);
+        config.agent_scopes.insert("Research Bot".to_string(), vec![format!("{}/", root.display())]);
+
+        let in_root = root.join("a.txt").to_string_lossy().to_string();
+        let in_other = other.join("b.txt").to_string_lossy().to_string();
+
+        assert!(validate_path_as(&in_root, &config, "Research Bot").is_ok());
+        let err = validate_path_as(&in_other, &config, "Research Bot").err().unwrap();
+        assert_eq!(err, SandboxError::OutOfScope { path: in_other.clone(), agent: "Research Bot".to_string() });
+
+        // Unlisted agents see everything unless the policy says otherwise
+        assert!(validate_path_as(&in_other, &config, "Claude Desktop").is_ok());
+        config.deny_unscoped_agents = true;
+        assert!(matches!(validate_path_as(&in_root, &config, "Claude Desktop"), Err(SandboxError::OutOfScope { .. })));
+        assert!(validate_path_as(&in_root, &config, "Research Bot").is_ok());
+
+        // Without any scopes the policy flag has nothing to act on
+        config.agent_scopes.clear();
+        assert!(validate_path_as(&in_other, &config, "Claude Desktop").is_ok());
+    }
+
+    #[test]
+    fn test_scopes_ignore_self_reported_agent_names() {
+        use crate::activity::{with_agent_sync, RequestAgent};
+
+        let (mut config, _dir, root) = temp_config(Permission::ReadWrite);
+        config.agent_scopes.insert("https://claude.ai".to_string(), vec![root.to_string_lossy().to_string()]);
+        config.deny_unscoped_agents = true;
+        let target = root.join("a.txt").to_string_lossy().to_string();
+        let as_agent = |name: Option<&str>, origin: &str| {
+            let agent = RequestAgent { name: name.map(str::to_string), origin: Some(origin.to_string()) };
+            with_agent_sync(Some(agent), || validate_path(&target, &config).is_ok())
+        };
+
+        assert!(as_agent(None, "https://claude.ai"));
+        assert!(as_agent(Some("Research Bot"), "https://claude.ai"));
+        // Claiming a scoped agent's name in x-omnidrive-agent or clientInfo gets nothing
+        assert!(!as_agent(Some("https://claude.ai"), ""));
+        assert!(!as_agent(Some("https://claude.ai"), "https://evil.example"));
+    }
+
+    #[test]
+    fn test_path_limits_reject_long_and_deep_paths() {
+        let (config, _dir, root) = temp_config(Permission::ReadWrite);
+
+        let long_name = root.join("x".repeat(300)).to_string_lossy().to_string();
+        let err = validate_path(&long_name, &config).unwrap_err();
+        assert!(matches!(err, SandboxError::TooLong { .. }));
+        assert!(err.to_string().contains("bytes long (limit: 255)"), "{}", err);
+        assert!(err.to_string().len() < 400, "error message abbreviates the path");
+
+        let deep = root.join(vec!["d"; 200].join("/")).to_string_lossy().to_string();
+        let err = validate_path(&deep, &config).unwrap_err();
+        assert!(err.to_string().contains("levels deep (limit: 128)"), "{}", err);
+
+        let mut strict = config.clone();
+        strict.max_path_length = 10;
+        let err = validate_path(&root.join("a.txt").to_string_lossy(), &strict).unwrap_err();
+        assert!(err.to_string().contains("(limit: 10)"), "{}", err);
+
+        assert!(validate_path(&root.join(vec!["d"; 20].join("/")).to_string_lossy(), &config).is_ok());
+    }
+
+    #[test]
+    fn test_ignore_case_sensitivity_is_configurable() {
+        let (mut config, _dir, root) = temp_config(Permission::ReadWrite);
+        fs::write(root.join(".mcpignore"), "*.LOG\n").unwrap();
+        let log = root.join("a.log").to_string_lossy().to_string();
+
+        config.case_insensitive_ignore = Some(true);
+        assert!(matches!(validate_path(&log, &config), Err(SandboxError::Ignored(_))));
+
+        config.case_insensitive_ignore = Some(false);
+        assert!(validate_path(&log, &config).is_ok());
+
+        // A folder's own setting wins over the global one
+        config.folders[0].case_insensitive_ignore = Some(true);
+        assert!(matches!(validate_path(&log, &config), Err(SandboxError::Ignored(_))));
+    }
+
+    #[test]
+    fn test_global_ignore_applies_to_every_folder() {
+        let (mut config, _dir, root) = temp_config(Permission::ReadWrite);
+        let other_dir = tempfile::tempdir().unwrap();
+        let other = fs::canonicalize(other_dir.path()).unwrap();
+        config.folders.extend(config_for(&other, Permission::ReadOnly).folders);
+        config.global_ignore = vec![".git".to_string(), "*.lock".to_string()];
+
+        for base in [&root, &other] {
+            fs::create_dir_all(base.join(".git")).unwrap();
+            fs::write(base.join(".git").join("HEAD"), "ref").unwrap();
+            fs::write(base.join("Cargo.lock"), "").unwrap();
+            fs::write(base.join("main.rs"), "").unwrap();
+
+            let head = base.join(".git").join("HEAD");
+            let lock = base.join("Cargo.lock");
+            let source = base.join("main.rs");
+            assert!(matches!(
+                validate_path(head.to_str().unwrap(), &config),
+                Err(SandboxError::Ignored(_))
+            ));
+            assert!(matches!(
+                validate_path(lock.to_str().unwrap(), &config),
+                Err(SandboxError::Ignored(_))
+            ));
+            assert!(validate_path(source.to_str().unwrap(), &config).is_ok());
         }
     }
 
+    #[test]
+    fn test_ignored_directory_rule_hides_directory() {
+        let (config, _dir, root) = temp_config(Permission::ReadWrite);
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("build").join("out.txt"), "artifact").unwrap();
+        fs::write(root.join(".mcpignore"), "build/\n").unwrap();
+
+        let build = root.join("build");
+        assert!(matches!(validate_path(&build.to_string_lossy(), &config), Err(SandboxError::Ignored(_))));
+        assert!(matches!(
+            validate_path(&build.join("out.txt").to_string_lossy(), &config),
+            Err(SandboxError::Ignored(_))
+        ));
+        assert!(validate_path(&root.join("src").to_string_lossy(), &config).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_into_read_only_folder_is_not_writable() {
+        let root = std::env::temp_dir().join(format!("omnidrive-link-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("rw")).unwrap();
+        fs::create_dir_all(root.join("ro")).unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+        let secret = root.join("ro").join("secret.txt");
+        fs::write(&secret, "original").unwrap();
+        let link = root.join("rw").join("x.txt");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+
+        let mut config = test_config();
+        config.folders[0].path = root.join("rw").to_string_lossy().to_string();
+        let mut read_only = config.folders[0].clone();
+        read_only.path = root.join("ro").to_string_lossy().to_string();
+        read_only.permission = Permission::ReadOnly;
+        config.folders.push(read_only);
+
+        // Reading through the link is fine; writing would change the read-only file
+        let link_str = link.to_string_lossy();
+        assert!(validate_path(&link_str, &config).is_ok());
+        let err = validate_writable(&link_str, &config).err().unwrap().to_string();
+        assert!(err.contains("symbolic link"), "{}", err);
+        assert!(err.contains("read-only"), "{}", err);
+
+        // A dangling link can't be written through either
+        fs::remove_file(&secret).unwrap();
+        let err = validate_writable(&link_str, &config).err().unwrap().to_string();
+        assert!(err.contains("does not exist"), "{}", err);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_sandbox_error_variants() {
+        let config = test_config();
+        assert!(matches!(
+            validate_path("/tmp/test-shared/../etc/passwd", &config),
+            Err(SandboxError::Traversal(_))
+        ));
+        assert!(matches!(
+            validate_path("/definitely/not/shared.txt", &config),
+            Err(SandboxError::NotInFolder(_))
+        ));
+
+        let (config, _dir, root) = temp_config(Permission::ReadWrite);
+        let missing = root.join("missing.txt").to_string_lossy().to_string();
+        assert_eq!(validate_destructive(&missing, &config).err(), Some(SandboxError::NotFound(missing.clone())));
+
+        let (config, _dir, root) = temp_config(Permission::ReadOnly);
+        let target = root.join("new.txt").to_string_lossy().to_string();
+        assert!(matches!(validate_writable(&target, &config), Err(SandboxError::ReadOnly { .. })));
+    }
+
+    #[test]
+    fn test_validate_path_inside_shared_folder() {
+        let (config, _dir, root) = temp_config(Permission::ReadWrite);
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let validated = validate_path(&root.join("a.txt").to_string_lossy(), &config).unwrap();
+        assert_eq!(validated.canonical_path, root.join("a.txt"));
+        assert_eq!(validated.folder.path, root.to_string_lossy());
+    }
+
     #[test]
     fn test_supported_extensions() {
-        assert!(is_supported_extension("test.rs"));
-        assert!(is_supported_extension("test.py"));
-        assert!(is_supported_extension("test.json"));
-        assert!(is_supported_extension("test.pdf"));
-        assert!(is_supported_extension("test.png"));
+        assert!(is_supported_extension("test.rs", &[]));
+        assert!(is_supported_extension("test.py", &[]));
+        assert!(is_supported_extension("test.json", &[]));
+        assert!(is_supported_extension("test.pdf", &[]));
+        assert!(is_supported_extension("test.png", &[]));
     }
 
     #[test]
     fn test_unsupported_extensions() {
-        assert!(!is_supported_extension("test.exe"));
-        assert!(!is_supported_extension("test.zip"));
-        assert!(!is_supported_extension("test.pptx"));
-        assert!(!is_supported_extension("test.mp4"));
+        assert!(!is_supported_extension("test.exe", &[]));
+        assert!(!is_supported_extension("test.zip", &[]));
+        assert!(!is_supported_extension("test.pptx", &[]));
+        assert!(!is_supported_extension("test.mp4", &[]));
+    }
+
+    #[test]
+    fn test_extra_extensions() {
+        let extra = vec!["tf".to_string(), ".Vue".to_string()];
+        assert!(!is_supported_extension("main.tf", &[]));
+        assert!(is_supported_extension("main.tf", &extra));
+        assert!(is_supported_extension("App.vue", &extra));
+        assert!(is_supported_extension("App.VUE", &extra));
+        assert!(!is_supported_extension("page.astro", &extra));
+    }
+
+    #[test]
+    fn test_agrees_with_desktop_scan_list() {
+        // These used to differ between the server and the desktop app's folder scan
+        for name in ["a.editorconfig", "a.sass", "a.dockerignore", ".gitignore", ".env"] {
+            assert!(is_supported_extension(name, &[]), "{} should be supported", name);
+        }
+        assert!(!is_supported_extension(".DS_Store", &[]));
     }
 
     #[test]
@@ -259,8 +836,8 @@ mod tests {
 
     #[test]
     fn test_extensionless_files() {
-        assert!(is_supported_extension("Makefile"));
-        assert!(is_supported_extension("Dockerfile"));
-        assert!(!is_supported_extension("randomname"));
+        assert!(is_supported_extension("Makefile", &[]));
+        assert!(is_supported_extension("Dockerfile", &[]));
+        assert!(!is_supported_extension("randomname", &[]));
     }
 }
@@ -1,25 +1,98 @@
-use axum::{Router, middleware::{self, Next}, response::Response, body::Body, http::{Request, StatusCode}};
+use axum::{Router, extract::{ConnectInfo, State}, middleware::{self, Next}, response::{IntoResponse, Response}, body::Body, http::{Request, StatusCode}, routing::post_service};
 use rmcp::transport::streamable_http_server::{
     StreamableHttpServerConfig, StreamableHttpService,
     session::local::LocalSessionManager,
 };
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tower_http::cors::{Any, CorsLayer, AllowOrigin};
-use axum::http::{header::{ACCEPT, CONTENT_TYPE, ORIGIN}, Method};
+use tower_http::compression::{
+    predicate::{And, NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
+use tower::ServiceBuilder;
+use tower_http::limit::RequestBodyLimitLayer;
+use axum::http::{header::{HeaderName, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN}, Method};
 use std::fs;
 use serde::{Deserialize, Serialize};
 
 use crate::OmniDriveServer;
 use crate::activity;
 
+/// Options controlling the SSE transport, parsed from the command line
+pub struct SseOptions {
+    pub port: u16,
+    pub allowed_origins: Vec<String>,
+    /// Request headers allowed by CORS on top of `REQUIRED_CORS_HEADERS`
+    pub cors_headers: Vec<String>,
+    /// Keep per-client sessions (see `http_service`)
+    pub stateful: bool,
+    /// Shut the server down after this many seconds without a request (0 = never)
+    pub idle_timeout_secs: u64,
+    /// Log every incoming request (method, path, origin, peer) to stderr
+    pub verbose: bool,
+    /// Reject request bodies larger than this with 413 Payload Too Large
+    pub max_body_bytes: usize,
+}
+
+/// Default for `--max-body-bytes`: room for a write_file call carrying a file at the
+/// default 50 MB size limit, JSON-escaped
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Headers the streamable HTTP transport needs from browser clients. Without
+/// the session headers, follow-up requests in a session fail the CORS preflight.
+const REQUIRED_CORS_HEADERS: &[&str] = &[
+    "mcp-session-id",
+    "mcp-protocol-version",
+    "last-event-id",
+    AGENT_HEADER,
+];
+
+/// Header a client can send to name itself in the activity log
+const AGENT_HEADER: &str = "x-omnidrive-agent";
+
+/// Build the CORS layer: the standard headers, the MCP session headers and any
+/// extra `--cors-headers`. The session id is exposed so browsers can read it.
+fn cors_layer(allowed_origins: &[String], extra_headers: &[String]) -> CorsLayer {
+    let mut headers = vec![CONTENT_TYPE, ACCEPT, AUTHORIZATION];
+    headers.extend(
+        REQUIRED_CORS_HEADERS
+            .iter()
+            .map(|h| HeaderName::from_static(h))
+            .chain(extra_headers.iter().filter_map(|h| {
+                let parsed = HeaderName::try_from(h.as_str()).ok();
+                if parsed.is_none() {
+                    eprintln!("[OmniDrive] Ignoring invalid CORS header name: {}", h);
+                }
+                parsed
+            })),
+    );
+
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers(headers)
+        .expose_headers([HeaderName::from_static("mcp-session-id")]);
+
+    if allowed_origins.is_empty() {
+        cors.allow_origin(Any)
+    } else {
+        let origins: Vec<axum::http::HeaderValue> = allowed_origins
+            .iter()
+            .filter_map(|o| o.parse().ok())
+            .collect();
+        cors.allow_origin(AllowOrigin::list(origins))
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct PairingConfig {
     approved_origins: Vec<String>,
 }
 
 fn get_pairings_path() -> std::path::PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
-    home.join(".omnidrive").join("pairings.json")
+    crate::config::get_config_dir().join("pairings.json")
 }
 
 fn is_origin_approved(origin: &str) -> bool {
@@ -52,11 +125,182 @@ async fn pairing_middleware(req: Request<Body>, next: Next) -> Result<Response,
     Ok(next.run(req).await)
 }
 
+/// Who a request comes from: the name the client supplies and its Origin
+fn agent_for_request(req: &Request<Body>) -> activity::RequestAgent {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .map(str::to_string)
+    };
+    activity::RequestAgent {
+        name: header(AGENT_HEADER).filter(|name| !name.is_empty()),
+        origin: Some(header(ORIGIN.as_str()).unwrap_or_default()),
+    }
+}
+
+/// Over SSE the client is a browser origin rather than our parent process, so credit
+/// activity to the requester. The agent rides in the request's extensions, where the
+/// MCP handlers pick it up, and scopes everything logged while the request runs.
+async fn agent_middleware(mut req: Request<Body>, next: Next) -> Response {
+    let agent = agent_for_request(&req);
+    req.extensions_mut().insert(agent.clone());
+    activity::with_agent(agent, next.run(req)).await
+}
+
+/// One-line description of a request for verbose logging
+fn describe_request(req: &Request<Body>) -> String {
+    let origin = req.headers().get(ORIGIN).and_then(|v| v.to_str().ok()).unwrap_or("-");
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    format!("{} {} origin={} peer={}", req.method(), req.uri().path(), origin, peer)
+}
+
+/// Clients end a streamable HTTP session with `DELETE /sse`; record it as a disconnect
+async fn session_end_middleware(req: Request<Body>, next: Next) -> Response {
+    let session = (req.method() == Method::DELETE).then(|| {
+        req.headers()
+            .get("mcp-session-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    });
+    let response = next.run(req).await;
+    if let Some(session) = session {
+        if response.status().is_success() {
+            activity::log_disconnect(session.as_deref());
+        }
+    }
+    response
+}
+
+/// Where verbose request lines go: stderr in the server, a buffer in tests
+#[derive(Clone)]
+struct RequestLog(Arc<dyn Fn(String) + Send + Sync>);
+
+impl RequestLog {
+    fn stderr() -> Self {
+        Self(Arc::new(|line| eprintln!("[OmniDrive] Request: {}", line)))
+    }
+}
+
+async fn request_log_middleware(State(log): State<RequestLog>, req: Request<Body>, next: Next) -> Response {
+    (log.0)(describe_request(&req));
+    next.run(req).await
+}
+
+/// Tracks when the last HTTP request arrived so an idle server can shut itself down
+#[derive(Clone)]
+struct IdleTracker {
+    started: Instant,
+    last_request_ms: Arc<AtomicU64>,
+}
+
+impl IdleTracker {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_request_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn touch(&self) {
+        let now_ms = self.started.elapsed().as_millis() as u64;
+        self.last_request_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_request_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+async fn idle_middleware(
+    State(tracker): State<IdleTracker>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    tracker.touch();
+    next.run(req).await
+}
+
+/// Refuse every request with 503 while sharing is paused. The config is read per
+/// request, so pausing or resuming applies as soon as the server reloads it.
+async fn paused_middleware(
+    State(server): State<OmniDriveServer>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if server.config_snapshot().await.paused {
+        return (StatusCode::SERVICE_UNAVAILABLE, crate::PAUSED_MESSAGE).into_response();
+    }
+    next.run(req).await
+}
+
+/// Resolves once no request has been seen for `timeout`
+async fn wait_until_idle(tracker: IdleTracker, timeout: Duration) {
+    loop {
+        let idle = tracker.idle_for();
+        if idle >= timeout {
+            return;
+        }
+        tokio::time::sleep(timeout - idle).await;
+    }
+}
+
+/// The MCP streamable HTTP service.
+///
+/// Stateless (the default) handles every POST independently: nothing is kept
+/// between requests, so the server survives restarts and idle shutdowns
+/// transparently, but there is no server-to-client stream and no resumption.
+/// Stateful mode assigns an `mcp-session-id` on initialize and keeps the session
+/// in memory (`LocalSessionManager`) until the client sends `DELETE`, enabling
+/// GET event streams and `last-event-id` resumption at the cost of per-client
+/// memory and sessions that are lost on restart.
+fn http_service(server: OmniDriveServer, stateful: bool) -> StreamableHttpService<OmniDriveServer, LocalSessionManager> {
+    let config = StreamableHttpServerConfig {
+        stateful_mode: stateful,
+        ..Default::default()
+    };
+    StreamableHttpService::new(
+        move || Ok(server.for_session()),
+        Default::default(),
+        config,
+    )
+}
+
+/// Gzip responses for clients that send `Accept-Encoding: gzip`. MCP replies to
+/// POST are `text/event-stream` bodies that end with the response, so unlike the
+/// default predicate this one does not skip event streams.
+fn compression_layer() -> CompressionLayer<CompressionPredicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(32)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES),
+    )
+}
+
+type CompressionPredicate = And<And<SizeAbove, NotForContentType>, NotForContentType>;
+
+/// Routes `/sse` to the MCP service. Only POST replies are compressed; the GET
+/// stream stays open for server notifications and must not sit in a gzip buffer.
+fn mcp_router(service: StreamableHttpService<OmniDriveServer, LocalSessionManager>) -> Router {
+    let compressed = ServiceBuilder::new().layer(compression_layer()).service(service.clone());
+    Router::new().route_service(
+        "/sse",
+        post_service(compressed).get_service(service.clone()).delete_service(service),
+    )
+}
+
 pub async fn start_sse_server(
     server: OmniDriveServer,
-    port: u16,
-    allowed_origins: Vec<String>,
+    options: SseOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let SseOptions { port, allowed_origins, cors_headers, stateful, idle_timeout_secs, verbose, max_body_bytes } = options;
+
     // Ensure the pairings file exists if it doesn't
     let pairings_path = get_pairings_path();
     if !pairings_path.exists() {
@@ -64,42 +308,514 @@ pub async fn start_sse_server(
         let _ = fs::write(&pairings_path, r#"{"approved_origins": []}"#);
     }
 
-    let config = StreamableHttpServerConfig {
-        stateful_mode: false,
-        ..Default::default()
-    };
-    
-    let http_service: StreamableHttpService<OmniDriveServer, LocalSessionManager> = 
-        StreamableHttpService::new(
-            move || Ok(server.clone()),
-            Default::default(),
-            config
-        );
-    
-    let mut cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([CONTENT_TYPE, ACCEPT]);
+    let http_service = http_service(server.clone(), stateful);
+    eprintln!(
+        "[OmniDrive] Session mode: {}",
+        if stateful { "stateful" } else { "stateless" }
+    );
 
-    if allowed_origins.is_empty() {
-        cors = cors.allow_origin(Any);
-    } else {
-        let origins: Vec<axum::http::HeaderValue> = allowed_origins
-            .iter()
-            .filter_map(|o| o.parse().ok())
-            .collect();
-        cors = cors.allow_origin(AllowOrigin::list(origins));
-    }
+    let cors = cors_layer(&allowed_origins, &cors_headers);
 
-    let app = Router::new()
-        .nest_service("/sse", http_service)
+    let idle_tracker = IdleTracker::new();
+
+    let mut app = mcp_router(http_service)
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(middleware::from_fn(session_end_middleware))
+        .layer(middleware::from_fn(agent_middleware))
         .layer(middleware::from_fn(pairing_middleware))
-        .layer(cors);
+        .layer(middleware::from_fn_with_state(server, paused_middleware));
+
+    if verbose {
+        app = app.layer(middleware::from_fn_with_state(RequestLog::stderr(), request_log_middleware));
+    }
+
+    let app = app
+        .layer(middleware::from_fn_with_state(idle_tracker.clone(), idle_middleware))
+        .layer(cors)
+        .into_make_service_with_connect_info::<SocketAddr>();
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     eprintln!("[OmniDrive] Starting SSE transport on http://{}/sse", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+
+    if idle_timeout_secs > 0 {
+        let timeout = Duration::from_secs(idle_timeout_secs);
+        eprintln!("[OmniDrive] SSE server will shut down after {}s of inactivity", idle_timeout_secs);
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                wait_until_idle(idle_tracker, timeout).await;
+                eprintln!("[OmniDrive] No requests for {}s. Shutting down SSE server.", idle_timeout_secs);
+                activity::log_activity(
+                    "system",
+                    "system",
+                    None,
+                    &format!("SSE server stopped after {}s idle", idle_timeout_secs),
+                );
+            })
+            .await?;
+    } else {
+        axum::serve(listener, app).await?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_describe_request_includes_peer_and_origin() {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri("/sse")
+            .header(ORIGIN, "https://claude.ai")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 51234))));
+
+        let line = describe_request(&req);
+        assert_eq!(line, "POST /sse origin=https://claude.ai peer=127.0.0.1:51234");
+    }
+
+    #[tokio::test]
+    async fn test_request_log_fires_for_allowed_request() {
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = lines.clone();
+        let log = RequestLog(Arc::new(move |line| sink.lock().unwrap().push(line)));
+        let app = Router::new()
+            .route("/sse", axum::routing::get(|| async { "ok" }))
+            .layer(middleware::from_fn(pairing_middleware))
+            .layer(middleware::from_fn_with_state(log, request_log_middleware));
+
+        // No Origin header, so the pairing check lets it through
+        let req = Request::builder().uri("/sse").body(Body::empty()).unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(*lines.lock().unwrap(), ["GET /sse origin=- peer=unknown"]);
+    }
+
+    #[tokio::test]
+    async fn test_activity_is_attributed_to_origin() {
+        let app = Router::new()
+            .route("/sse", axum::routing::post(|| async { activity::get_agent_name() }))
+            .layer(middleware::from_fn(agent_middleware));
+        let request = |headers: &[(&str, &str)]| {
+            let mut builder = Request::builder().method(Method::POST).uri("/sse");
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let agent_for = |req: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let body = app.oneshot(req).await.unwrap().into_body();
+                String::from_utf8(axum::body::to_bytes(body, 1024).await.unwrap().to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(agent_for(request(&[("origin", "https://claude.ai")])).await, "https://claude.ai");
+        assert_eq!(
+            agent_for(request(&[("origin", "https://claude.ai"), (AGENT_HEADER, "Research Bot")])).await,
+            "Research Bot"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_keep_their_own_agent() {
+        // Each handler reads the agent after the other request has started
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let app = Router::new()
+            .route(
+                "/sse",
+                axum::routing::post(move || {
+                    let barrier = barrier.clone();
+                    async move {
+                        barrier.wait().await;
+                        activity::get_agent_name()
+                    }
+                }),
+            )
+            .layer(middleware::from_fn(agent_middleware));
+        let agent_for = |name: &'static str| {
+            let app = app.clone();
+            async move {
+                let req = Request::builder().method(Method::POST).uri("/sse").header(AGENT_HEADER, name).body(Body::empty()).unwrap();
+                let body = app.oneshot(req).await.unwrap().into_body();
+                String::from_utf8(axum::body::to_bytes(body, 1024).await.unwrap().to_vec()).unwrap()
+            }
+        };
+
+        let (first, second) = tokio::join!(agent_for("agent-one"), agent_for("agent-two"));
+        assert_eq!((first.as_str(), second.as_str()), ("agent-one", "agent-two"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_credited_to_the_requesting_agent() {
+        let sandbox = crate::test_support::TestSandbox::new();
+        sandbox.write("a.txt", "hello");
+        let app = Router::new()
+            .nest_service("/sse", http_service(sandbox.server.clone(), false))
+            .layer(middleware::from_fn(agent_middleware));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/sse")
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json, text/event-stream")
+            .header(AGENT_HEADER, "tool-call-agent-test")
+            .body(Body::from(
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "tools/call",
+                    "params": { "name": "read_file", "arguments": { "path": sandbox.path("a.txt") } }
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("hello"));
+
+        let entry = activity::latest_entry_by("tool-call-agent-test");
+        assert_eq!(entry.map(|e| e.tool), Some("read_file".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_allows_session_and_extra_headers() {
+        let app = Router::new()
+            .route("/sse", axum::routing::post(|| async { "ok" }))
+            .layer(cors_layer(&["https://claude.ai".to_string()], &["x-client-trace".to_string()]));
+
+        let preflight = |headers: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/sse")
+                .header(ORIGIN, "https://claude.ai")
+                .header("access-control-request-method", "POST")
+                .header("access-control-request-headers", headers)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(preflight("content-type,mcp-session-id,mcp-protocol-version,last-event-id,x-client-trace"))
+            .await
+            .unwrap();
+        let allowed = response
+            .headers()
+            .get("access-control-allow-headers")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        for header in REQUIRED_CORS_HEADERS.iter().chain(&["content-type", "x-client-trace"]) {
+            assert!(allowed.contains(header), "{} missing from {}", header, allowed);
+        }
+        let methods = response.headers().get("access-control-allow-methods").unwrap().to_str().unwrap();
+        assert!(methods.contains("DELETE"), "DELETE missing from {}", methods);
+
+        let response = app
+            .oneshot(Request::builder().method(Method::POST).uri("/sse").header(ORIGIN, "https://claude.ai").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let exposed = response.headers().get("access-control-expose-headers").unwrap();
+        assert_eq!(exposed, "mcp-session-id");
+    }
+
+    #[tokio::test]
+    async fn test_stateful_mode_issues_session_ids() {
+        let initialize = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/sse")
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json, text/event-stream")
+                .body(Body::from(
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": 1,
+                        "method": "initialize",
+                        "params": {
+                            "protocolVersion": "2025-03-26",
+                            "capabilities": {},
+                            "clientInfo": { "name": "test", "version": "0" }
+                        }
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        for stateful in [false, true] {
+            let sandbox = crate::test_support::TestSandbox::new();
+            let app = Router::new()
+                .nest_service("/sse", http_service(sandbox.server.clone(), stateful))
+                .layer(middleware::from_fn(pairing_middleware));
+
+            let response = app.oneshot(initialize()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "stateful={}", stateful);
+            assert_eq!(
+                response.headers().contains_key("mcp-session-id"),
+                stateful,
+                "session id header with stateful={}",
+                stateful
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_client_name_becomes_agent() {
+        let sandbox = crate::test_support::TestSandbox::new();
+        sandbox.write("a.txt", "hello");
+        let app = Router::new().nest_service("/sse", http_service(sandbox.server.clone(), true));
+        let post = |session: Option<&str>, message: serde_json::Value| {
+            let mut builder = Request::builder()
+                .method(Method::POST)
+                .uri("/sse")
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json, text/event-stream");
+            if let Some(session) = session {
+                builder = builder.header("mcp-session-id", session);
+            }
+            builder.body(Body::from(message.to_string())).unwrap()
+        };
+
+        // Two sessions open side by side; each keeps the name it initialized with
+        let mut sessions = Vec::new();
+        for name in ["initialize-name-one", "initialize-name-two"] {
+            let response = app
+                .clone()
+                .oneshot(post(None, serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "initialize",
+                    "params": {
+                        "protocolVersion": "2025-03-26",
+                        "capabilities": {},
+                        "clientInfo": { "name": name, "version": "1.2.3" }
+                    }
+                })))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let session = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+            let initialized = serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+            app.clone().oneshot(post(Some(&session), initialized)).await.unwrap();
+            sessions.push((name, session));
+        }
+
+        for (name, session) in sessions.iter().rev() {
+            let call = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "tools/call",
+                "params": { "name": "read_file", "arguments": { "path": sandbox.path("a.txt") } }
+            });
+            let response = app.clone().oneshot(post(Some(session), call)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert!(String::from_utf8_lossy(&body).contains("hello"));
+
+            let entry = activity::latest_entry_by(name);
+            assert_eq!(entry.map(|e| e.tool), Some("read_file".to_string()), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_standalone_event_stream_is_not_gzipped() {
+        let sandbox = crate::test_support::TestSandbox::new();
+        let app = mcp_router(http_service(sandbox.server.clone(), true));
+        let initialize = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "stream-client", "version": "1.0" }
+            }
+        });
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/sse")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(ACCEPT, "application/json, text/event-stream")
+                    .body(Body::from(initialize.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let session = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(Method::GET)
+                    .uri("/sse")
+                    .header(ACCEPT, "text/event-stream")
+                    .header("accept-encoding", "gzip")
+                    .header("mcp-session-id", &session)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_large_read_file_reply_is_gzipped() {
+        use std::io::Read;
+
+        let sandbox = crate::test_support::TestSandbox::new();
+        sandbox.write("big.txt", "line of text\n".repeat(5000));
+        let app = mcp_router(http_service(sandbox.server.clone(), false));
+        let call = |encoding: Option<&str>| {
+            let mut builder = Request::builder()
+                .method(Method::POST)
+                .uri("/sse")
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json, text/event-stream");
+            if let Some(encoding) = encoding {
+                builder = builder.header("accept-encoding", encoding);
+            }
+            builder
+                .body(Body::from(
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": 1,
+                        "method": "tools/call",
+                        "params": { "name": "read_file", "arguments": { "path": sandbox.path("big.txt") } }
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(call(Some("gzip"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+        let compressed = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decoded).unwrap();
+        assert!(decoded.contains("line of text"));
+        assert!(compressed.len() < decoded.len() / 10);
+
+        let response = app.oneshot(call(None)).await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_body_is_rejected() {
+        let sandbox = crate::test_support::TestSandbox::new();
+        let app = Router::new()
+            .nest_service("/sse", http_service(sandbox.server.clone(), false))
+            .layer(RequestBodyLimitLayer::new(1024));
+        let initialize = |padding: usize| {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": {},
+                    "clientInfo": { "name": "x".repeat(padding), "version": "1.0" }
+                }
+            })
+            .to_string();
+            Request::builder()
+                .method(Method::POST)
+                .uri("/sse")
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json, text/event-stream")
+                .header("content-length", body.len())
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(initialize(4096)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = app.oneshot(initialize(8)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_paused_sharing_refuses_requests() {
+        let sandbox = crate::test_support::TestSandbox::with_config(|c| c.paused = true);
+        let app = Router::new()
+            .nest_service("/sse", http_service(sandbox.server.clone(), false))
+            .layer(middleware::from_fn_with_state(sandbox.server.clone(), paused_middleware));
+        let initialize = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/sse")
+                .header(CONTENT_TYPE, "application/json")
+                .header(ACCEPT, "application/json, text/event-stream")
+                .body(Body::from(
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": 1,
+                        "method": "initialize",
+                        "params": {
+                            "protocolVersion": "2025-03-26",
+                            "capabilities": {},
+                            "clientInfo": { "name": "test", "version": "0" }
+                        }
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(initialize()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Resuming takes effect on the next request
+        let resumed = crate::config::AppConfig { paused: false, ..(*sandbox.server.config_snapshot().await).clone() };
+        *sandbox.server.config.write().await = Arc::new(resumed);
+        let response = app.oneshot(initialize()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_idle_shutdown_fires_after_timeout() {
+        let tracker = IdleTracker::new();
+        tracker.touch();
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            wait_until_idle(tracker, Duration::from_millis(50)),
+        )
+        .await;
+        assert!(result.is_ok(), "idle wait should resolve once the timeout elapses");
+    }
+
+    #[tokio::test]
+    async fn test_requests_postpone_idle_shutdown() {
+        let tracker = IdleTracker::new();
+        let waiter = tokio::spawn(wait_until_idle(tracker.clone(), Duration::from_millis(200)));
+
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            tracker.touch();
+        }
+        assert!(!waiter.is_finished(), "activity should keep the server alive");
+
+        tokio::time::timeout(Duration::from_secs(2), waiter)
+            .await
+            .expect("idle wait should resolve after activity stops")
+            .unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Test-only harness — a temporary shared folder, a config pointing at it, and a server exposing it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rmcp::handler::server::wrapper::Parameters;
+use rmcp::model::CallToolResult;
+use tempfile::TempDir;
+
+use crate::config::{AppConfig, Permission, SharedFolder};
+use crate::OmniDriveServer;
+
+lazy_static::lazy_static! {
+    /// Holds the data directory that replaces `~/.omnidrive` for the whole test run
+    static ref DATA_HOME: TempDir = tempfile::Builder::new()
+        .prefix("omnidrive-test-home")
+        .tempdir()
+        .expect("failed to create temp data dir");
+}
+
+/// The data directory (activity log, confirmations, pairings) used in place of
+/// `~/.omnidrive` while testing
+pub fn data_dir() -> PathBuf {
+    DATA_HOME.path().join(".omnidrive")
+}
+
+/// A shared folder backed by a `TempDir` that is removed when the sandbox is dropped
+pub struct TestSandbox {
+    _dir: TempDir,
+    /// Canonical path of the shared folder root
+    pub root: PathBuf,
+    pub server: OmniDriveServer,
+}
+
+impl TestSandbox {
+    /// A read/write shared folder
+    pub fn new() -> Self {
+        Self::with_config(|_| {})
+    }
+
+    /// A shared folder with the given permission
+    pub fn with_permission(permission: Permission) -> Self {
+        Self::with_config(|config| config.folders[0].permission = permission)
+    }
+
+    /// A read/write shared folder whose config is adjusted by `customize` before the server starts
+    pub fn with_config(customize: impl FnOnce(&mut AppConfig)) -> Self {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        let root = fs::canonicalize(dir.path()).expect("failed to canonicalize temp dir");
+        let mut config = config_for(&root, Permission::ReadWrite);
+        customize(&mut config);
+        Self {
+            _dir: dir,
+            root,
+            server: OmniDriveServer::new(config),
+        }
+    }
+
+    /// Absolute path (as a string) of `relative` inside the shared folder
+    pub fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_string_lossy().to_string()
+    }
+
+    /// Write a file inside the shared folder, creating parent directories
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.root.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+/// A config sharing exactly one enabled folder
+pub fn config_for(root: &Path, permission: Permission) -> AppConfig {
+    AppConfig {
+        folders: vec![SharedFolder {
+            path: root.to_string_lossy().to_string(),
+            permission,
+            enabled: true,
+            available: true,
+            case_insensitive_ignore: None,
+        }],
+        ..AppConfig::default()
+    }
+}
+
+/// Build tool params from JSON so tests only spell out the fields they care about
+pub fn params<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Parameters<T> {
+    Parameters(serde_json::from_value(value).unwrap())
+}
+
+/// Concatenated text content of a tool result
+pub fn text_of(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|c| c.as_text())
+        .map(|t| t.text.clone())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,8 +1,8 @@
 use crate::OmniDriveServer;
 use crate::config::AppConfig;
 use crate::sandbox::{
-    validate_path, validate_writable, validate_destructive,
-    is_supported_extension, is_binary_file, is_pdf,
+    validate_path, validate_writable, validate_appendable, validate_destructive,
+    is_supported_extension, is_binary_file, is_pdf, matching_ignore_rule,
 };
 use rmcp::{tool, model::CallToolResult, model::Content, ErrorData};
 use rmcp::handler::server::wrapper::Parameters;
@@ -10,12 +10,22 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{Read, BufRead, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
 use base64::{Engine as _, engine::general_purpose};
 
+/// How long a heavy tool waits for a free slot before reporting the server as busy
+const HEAVY_OP_WAIT: Duration = Duration::from_secs(30);
+
+/// Largest `peek_bytes` (or `hex_bytes`) read_file accepts
+const PEEK_MAX_BYTES: usize = 64 * 1024;
+
+/// How much of a file read_file's hex preview covers by default
+const HEX_PREVIEW_DEFAULT_BYTES: usize = 256;
+
 fn success_log(
     tool: &str,
     category: &str,
@@ -23,8 +33,92 @@ fn success_log(
     summary: &str,
     contents: Vec<Content>,
 ) -> CallToolResult {
-    crate::activity::log_activity(tool, category, path, summary);
-    CallToolResult::success(contents)
+    log_and_wrap(tool, category, path, summary, None, contents)
+}
+
+/// Like `success_log`, also recording how many bytes were read or written
+fn success_log_bytes(
+    tool: &str,
+    category: &str,
+    path: Option<&str>,
+    summary: &str,
+    bytes: u64,
+    contents: Vec<Content>,
+) -> CallToolResult {
+    log_and_wrap(tool, category, path, summary, Some(bytes), contents)
+}
+
+fn log_and_wrap(
+    tool: &str,
+    category: &str,
+    path: Option<&str>,
+    summary: &str,
+    bytes: Option<u64>,
+    contents: Vec<Content>,
+) -> CallToolResult {
+    crate::activity::log_activity_bytes(tool, category, path, summary, bytes);
+    let limit = MAX_RESPONSE_BYTES.load(Ordering::Relaxed);
+    CallToolResult::success(cap_contents(contents, limit))
+}
+
+/// Mirrors `AppConfig::max_response_bytes`; set on startup and on every config reload
+static MAX_RESPONSE_BYTES: AtomicUsize = AtomicUsize::new(1024 * 1024);
+
+pub fn set_max_response_bytes(limit: usize) {
+    MAX_RESPONSE_BYTES.store(limit.max(1024), Ordering::Relaxed);
+}
+
+/// Mirrors `AppConfig::io_retry_attempts`; set on startup and on every config reload
+static IO_RETRY_ATTEMPTS: AtomicUsize = AtomicUsize::new(3);
+
+pub fn set_io_retry_attempts(attempts: u32) {
+    IO_RETRY_ATTEMPTS.store(attempts.max(1) as usize, Ordering::Relaxed);
+}
+
+/// Keep the combined text of a response within `limit` bytes. The block that
+/// crosses the limit is cut on a char boundary and gets a truncation footer;
+/// any text blocks after it are dropped and counted in the footer.
+fn cap_contents(contents: Vec<Content>, limit: usize) -> Vec<Content> {
+    let mut remaining = limit;
+    let mut omitted = 0;
+    let mut capped = Vec::with_capacity(contents.len());
+
+    for content in contents {
+        let Some(text) = content.as_text().map(|t| t.text.as_str()) else {
+            capped.push(content);
+            continue;
+        };
+        if omitted > 0 {
+            omitted += text.len();
+            continue;
+        }
+        if text.len() <= remaining {
+            remaining -= text.len();
+            capped.push(content);
+            continue;
+        }
+
+        let mut cut = remaining;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        omitted = text.len() - cut;
+        capped.push(Content::text(text[..cut].to_string()));
+        remaining = 0;
+    }
+
+    if omitted > 0 {
+        let footer = format!("\n[output truncated, {} bytes omitted]", omitted);
+        match capped.iter().rposition(|c| c.as_text().is_some()) {
+            Some(last) => {
+                let text = capped[last].as_text().map(|t| t.text.clone()).unwrap_or_default();
+                capped[last] = Content::text(text + &footer);
+            }
+            None => capped.push(Content::text(footer)),
+        }
+    }
+
+    capped
 }
 
 // ─── Tool Parameters ───
@@ -34,35 +128,76 @@ struct ListDirectoryParams {
     path: String,
     #[serde(default = "default_page")]
     page: usize,
-    #[serde(default = "default_page_size")]
-    page_size: usize,
+    /// Entries per page (default and maximum come from the server config)
+    page_size: Option<usize>,
     /// If true, list recursively with tree structure
     #[serde(default)]
     recursive: bool,
     /// Max depth when recursive=true (default 3)
     #[serde(default = "default_max_depth")]
     max_depth: usize,
+    /// Include octal permission bits and an rwx summary per entry (Unix only)
+    #[serde(default)]
+    show_mode: bool,
+    /// Skip dotfiles and dot-directories when recursive=true (default: true)
+    #[serde(default = "default_true")]
+    skip_hidden: bool,
+    /// Sort the flat listing by "name" (default), "size" or "modified"
+    sort_by: Option<String>,
+    /// Sort in descending order, e.g. largest or newest first (default: false)
+    #[serde(default)]
+    sort_desc: bool,
+    /// List directories before files regardless of the sort key (default: true)
+    #[serde(default = "default_true")]
+    dirs_first: bool,
+    /// Report the shared folder the directory belongs to and its permission (default: false)
+    #[serde(default)]
+    show_folder: bool,
 }
 
 fn default_page() -> usize { 1 }
-fn default_page_size() -> usize { 50 }
 fn default_max_depth() -> usize { 3 }
+fn default_true() -> bool { true }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct ReadFileParams {
     path: String,
+    /// Return only the first N bytes (text if valid UTF-8, otherwise base64), ignoring the size limit
+    peek_bytes: Option<usize>,
+    /// Remove line and block comments from source code to save tokens (default: false).
+    /// Only applied to languages with a known comment syntax; strings are left intact.
+    #[serde(default)]
+    strip_comments: bool,
+    /// For binary files of an unsupported type, return a hex dump (offset, hex, ASCII)
+    /// of the first hex_bytes bytes instead of an error (default: false)
+    #[serde(default)]
+    hex_preview: bool,
+    /// How many bytes the hex dump covers (default: 256, max: 65536)
+    hex_bytes: Option<usize>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct WriteFileParams {
     path: String,
     content: String,
+    /// Only write if the file's current SHA-256 (from get_file_info include_hash) matches
+    expected_sha256: Option<String>,
+    /// Add content to the end of the file instead of replacing it, creating the file if needed (default: false)
+    #[serde(default)]
+    append: bool,
+    /// Replace the file if it already exists (default: the server's default_overwrite, normally true)
+    overwrite: Option<bool>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct SearchFilesParams {
     pattern: String,
     root_path: Option<String>,
+    /// A shared folder root; matching paths inside it are returned relative to it
+    relative_to: Option<String>,
+    /// Add each file's size, modified time and first line under its path (default: false)
+    #[serde(default)]
+    preview: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -77,15 +212,30 @@ struct GrepContentParams {
     /// Case-insensitive matching (default: false)
     #[serde(default)]
     case_insensitive: bool,
-    /// Max results to return (default: 50)
+    /// Max results to return (default: 50, capped by the server's max_search_results)
     #[serde(default = "default_max_results")]
     max_results: usize,
     /// Only search files with these extensions (e.g. ["rs", "py"])
     #[serde(default)]
     include_extensions: Option<Vec<String>>,
+    /// How many directory levels below root_path to search (default: 20, max: 64)
+    max_depth: Option<usize>,
+    /// Also search files normally treated as binary, e.g. images and PDFs (default: false).
+    /// Lines that aren't valid UTF-8 are skipped either way.
+    #[serde(default)]
+    force_text: bool,
+    /// A shared folder root; matching paths inside it are returned relative to it
+    relative_to: Option<String>,
+    /// Skip dotfiles and dot-directories below root_path (default: true)
+    #[serde(default = "default_true")]
+    skip_hidden: bool,
+    /// Report at most this many matches per file, so one noisy file can't use up max_results
+    max_per_file: Option<usize>,
 }
 
 fn default_max_results() -> usize { 50 }
+const GREP_DEFAULT_DEPTH: usize = 20;
+const GREP_MAX_DEPTH: usize = 64;
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct ReadLinesParams {
@@ -97,8 +247,22 @@ struct ReadLinesParams {
     end_line: Option<usize>,
     /// Read the last N lines of the file (overrides start_line/end_line)
     tail: Option<usize>,
+    /// Several sections at once, e.g. [{"start_line": 1, "end_line": 20}, {"start_line": 300, "end_line": 340}].
+    /// Overlapping or adjacent ranges are merged. Overrides start_line, end_line and tail.
+    ranges: Option<Vec<LineRange>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct LineRange {
+    /// First line (1-indexed, inclusive)
+    start_line: usize,
+    /// Last line (1-indexed, inclusive)
+    end_line: usize,
 }
 
+/// Most lines one read_lines call returns across all of its ranges
+const READ_RANGES_MAX_LINES: usize = 2000;
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct MoveFileParams {
     source: String,
@@ -119,6 +283,12 @@ struct CopyFileParams {
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 struct GetFileInfoParams {
     path: String,
+    /// Include a SHA-256 of the file content (default: false)
+    #[serde(default)]
+    include_hash: bool,
+    /// Report the shared folder the path belongs to and its permission (default: false)
+    #[serde(default)]
+    show_folder: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -138,6 +308,15 @@ struct ZipFilesParams {
     paths: Vec<String>,
     /// Output zip file path
     output_path: String,
+    /// List the files that would be added and their total size without writing the archive
+    #[serde(default)]
+    dry_run: bool,
+    /// Glob patterns skipped while walking directories, matched against the path
+    /// relative to the walked directory (e.g. "**/*.log", "target/**")
+    exclude: Option<Vec<String>>,
+    /// Only archive files with these extensions while walking directories (e.g. ["rs", "toml"]).
+    /// exclude still applies on top of this.
+    include_extensions: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -146,6 +325,9 @@ struct UnzipFilesParams {
     archive_path: String,
     /// Directory to extract into
     destination: String,
+    /// List the entries that would be extracted without writing anything
+    #[serde(default)]
+    dry_run: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
@@ -181,6 +363,287 @@ struct PatchFileParams {
     /// Line-range replacement operations (applied after search_replace)
     #[serde(default)]
     line_replace: Vec<LinePatchOp>,
+    /// Only patch if the file's current SHA-256 (from get_file_info include_hash) matches
+    expected_sha256: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ReadCsvParams {
+    path: String,
+    /// First data row to return (1-indexed, header excluded; default: 1)
+    start_row: Option<usize>,
+    /// Max rows to return (default: 100, max: 1000)
+    max_rows: Option<usize>,
+    /// Single-character field delimiter (default: ",")
+    delimiter: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ValidateJsonParams {
+    path: String,
+    /// Include a pretty-printed copy of the document when valid (default: false)
+    #[serde(default)]
+    pretty: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ConvertFileParams {
+    path: String,
+    /// Target line ending: "lf" or "crlf"
+    line_ending: Option<String>,
+    /// Target encoding. Only "utf8" is supported.
+    encoding: Option<String>,
+    /// Encoding of the source file (e.g. "windows-1252", "utf-16le"). Detected from the BOM or assumed UTF-8 if omitted.
+    source_encoding: Option<String>,
+    /// Report what would change without writing (default: false)
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct TrimWhitespaceParams {
+    path: String,
+    /// Collapse trailing blank lines into exactly one final newline (default: false)
+    #[serde(default)]
+    ensure_final_newline: bool,
+    /// Report what would change without writing (default: false)
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CreateSymlinkParams {
+    /// Where to create the link (must be in a writable shared folder)
+    link_path: String,
+    /// What the link points to (must be inside a shared folder)
+    target: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct SetPermissionsParams {
+    path: String,
+    /// Octal permission bits, e.g. "755" or "0644"
+    mode: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ReadEnvParams {
+    path: String,
+    /// Return the values too; by default only the keys are shown (values are null)
+    #[serde(default)]
+    include_values: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct MoveIntoParams {
+    source: String,
+    /// Directory to move the source into; created if missing
+    dest_dir: String,
+    /// Replace an existing file with the same name (default: false)
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CopyIntoParams {
+    source: String,
+    /// Directory to copy the source into; created if missing
+    dest_dir: String,
+    /// Required to copy a directory and its contents (default: false)
+    #[serde(default)]
+    recursive: bool,
+    /// Replace an existing file with the same name (default: false). Directories are never merged.
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RecentActivityParams {
+    /// How many entries to return, newest first (default: 20, max: 100)
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct GrepBinaryParams {
+    /// Hex-encoded bytes to find, e.g. "89504e47" or "de ad be ef"
+    pattern: String,
+    /// A single file to scan
+    path: Option<String>,
+    /// A directory to scan recursively (instead of path)
+    root_path: Option<String>,
+    /// Max matches to return (default: 50, capped by the server's max_search_results)
+    max_results: Option<usize>,
+    /// Bytes of context shown on each side of a match (default: 8, max: 64)
+    context_bytes: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ListTreeParams {
+    /// Directory to describe
+    path: String,
+    /// How many levels below `path` to include (default 3, max 10)
+    #[serde(default = "default_max_depth")]
+    max_depth: usize,
+    /// Skip dotfiles and dot-directories (default: true)
+    #[serde(default = "default_true")]
+    skip_hidden: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ConcatFilesParams {
+    /// Source files, concatenated in this order
+    paths: Vec<String>,
+    /// File to write the result to
+    output_path: String,
+    /// Text inserted between consecutive sources, e.g. "\n" (default: none)
+    separator: Option<String>,
+    /// Replace output_path if it already exists (default: false)
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PathExistsParams {
+    path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PatchFilesParams {
+    /// One entry per file, with the same fields as patch_file
+    files: Vec<PatchFileParams>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct DiskUsageParams {
+    /// Any path inside a shared folder
+    path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct GenerateManifestParams {
+    /// Directory to describe
+    root_path: String,
+    /// Also write the manifest JSON to this file (requires Read/Write permission)
+    output_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct VerifyManifestParams {
+    /// Directory to check
+    root_path: String,
+    /// A manifest file written by generate_manifest
+    manifest_path: Option<String>,
+    /// The manifest JSON itself, instead of manifest_path
+    manifest: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct RecentFilesParams {
+    /// How many files to return, most recent first (default: 20, max: 100)
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct ListSupportedTypesParams {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct QueryJsonParams {
+    path: String,
+    /// RFC 6901 JSON Pointer to the value, e.g. "/servers/0/port" ("" selects the whole document)
+    pointer: String,
+    /// Values whose JSON is longer than this are summarized with a truncated preview (default: 4000)
+    max_chars: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct PatchJsonParams {
+    path: String,
+    /// RFC 7386 merge patch: keys set to null are removed, objects are merged recursively, anything else replaces
+    merge_patch: serde_json::Value,
+    /// Report what would change without writing (default: false)
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct FormatFileParams {
+    path: String,
+    /// Report what would change without writing (default: false)
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct GrepAndReadParams {
+    /// Text or regex to search for
+    pattern: String,
+    /// Root directory to search within
+    root_path: String,
+    /// Treat pattern as regex (default: false, literal string match)
+    #[serde(default)]
+    is_regex: bool,
+    /// Case-insensitive matching (default: false)
+    #[serde(default)]
+    case_insensitive: bool,
+    /// Lines shown before and after each file's first match (default: 3, max: 50)
+    context_lines: Option<usize>,
+    /// Max files to show (default: 10, capped by the server's max_search_results)
+    max_files: Option<usize>,
+    /// Only search files with these extensions (e.g. ["rs", "py"])
+    #[serde(default)]
+    include_extensions: Option<Vec<String>>,
+    /// A shared folder root; matching paths inside it are returned relative to it
+    relative_to: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct DiffManifestsParams {
+    /// The earlier manifest file, written by generate_manifest
+    old_manifest_path: String,
+    /// The later manifest file to compare against
+    new_manifest_path: Option<String>,
+    /// A directory to compare in its current state, instead of new_manifest_path
+    root_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct EmptyDirectoryParams {
+    /// The directory to empty; the directory itself is kept
+    path: String,
+    /// Must be true: everything inside the directory is deleted permanently
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CheckIgnoreParams {
+    /// The path to check; it doesn't need to exist
+    path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct CompareContentParams {
+    path: String,
+    /// The text to compare the file against
+    content: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct FindDuplicatesParams {
+    /// The directory to search (recursively)
+    root_path: String,
+    /// Ignore files smaller than this many bytes (default: 1, so empty files are skipped)
+    min_size: Option<u64>,
+    /// Most groups to return, largest wasted space first (default: 50)
+    max_groups: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+struct LargestFilesParams {
+    /// The directory to search (recursively)
+    root_path: String,
+    /// How many files to return (default: 10, max: 1000)
+    count: Option<usize>,
 }
 
 // ─── Tool Implementations ───
@@ -188,32 +651,48 @@ struct PatchFileParams {
 #[rmcp::tool_router]
 impl OmniDriveServer {
     pub fn new(config: AppConfig) -> Self {
+        let heavy_ops_limit = config.max_heavy_operations.max(1);
         Self {
-            config: Arc::new(RwLock::new(config)),
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            heavy_ops: Arc::new(Semaphore::new(heavy_ops_limit)),
+            heavy_ops_limit: Arc::new(AtomicUsize::new(heavy_ops_limit)),
+            subscriptions: Arc::default(),
+            read_cache: Arc::default(),
             tool_router: Self::tool_router(),
         }
     }
 
+    /// A handle for a new client session: shares the config and limits, but starts
+    /// with no resource subscriptions of its own
+    pub fn for_session(&self) -> Self {
+        Self {
+            subscriptions: Arc::default(),
+            ..self.clone()
+        }
+    }
+
     // ────────────────────────────────────────────────────────
     // 1. list_directory (enhanced with recursive option)
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "List files in a directory. Returns names, types, and sizes. Paginated. Set recursive=true with max_depth to get a tree structure.")]
+    #[tool(description = "List files in a directory. Returns names, types, and sizes. Paginated. Set recursive=true with max_depth to get a tree structure. Use sort_by (name/size/modified) and sort_desc to find the largest or newest files.")]
     async fn list_directory(&self, params: Parameters<ListDirectoryParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_path(&args.path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_path(&args.path, &config)?;
         let dir_path = validated.canonical_path;
 
         if !dir_path.is_dir() {
-            return Err(ErrorData::internal_error(format!("Path is not a directory: {}", args.path), None));
+            return Err(ErrorData::invalid_params(format!("Path is not a directory: {}", args.path), None));
         }
 
         if args.recursive {
-            return self.list_directory_recursive(&dir_path, &args, &config).await;
+            return self.list_directory_recursive(&dir_path, &validated.folder, &args, &config).await;
         }
 
+        let sort_key = SortKey::parse(args.sort_by.as_deref()).map_err(|e| ErrorData::invalid_params(e, None))?;
+
         let mut entries = Vec::new();
         match fs::read_dir(&dir_path) {
             Ok(dir_entries) => {
@@ -222,7 +701,7 @@ impl OmniDriveServer {
                     let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
                     let is_dir = path.is_dir();
 
-                    if !is_dir && !is_supported_extension(&name) {
+                    if !is_dir && !is_supported_extension(&name, &config.extra_extensions) {
                         continue;
                     }
 
@@ -232,23 +711,23 @@ impl OmniDriveServer {
                         continue;
                     }
 
-                    let size = if is_dir { 0 } else { entry.metadata().map(|m| m.len()).unwrap_or(0) };
-                    entries.push((name, is_dir, size));
+                    let meta = entry.metadata().ok();
+                    let size = if is_dir { 0 } else { meta.as_ref().map(|m| m.len()).unwrap_or(0) };
+                    let mode = meta.as_ref().and_then(unix_mode);
+                    let modified = match sort_key {
+                        SortKey::Modified => meta.as_ref().and_then(|m| m.modified().ok()),
+                        _ => None,
+                    };
+                    entries.push(ListEntry { name, is_dir, size, mode, modified });
                 }
             }
             Err(e) => return Err(ErrorData::internal_error(format!("Failed to read directory: {}", e), None)),
         }
 
-        entries.sort_by(|a, b| {
-            match (a.1, b.1) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.0.to_lowercase().cmp(&b.0.to_lowercase()),
-            }
-        });
+        sort_entries(&mut entries, sort_key, args.sort_desc, args.dirs_first);
 
         let total_items = entries.len();
-        let page_size = args.page_size.clamp(1, 100);
+        let page_size = effective_page_size(args.page_size, config.default_page_size, config.max_page_size);
         let page = args.page.max(1);
         let start_idx = (page - 1) * page_size;
 
@@ -265,15 +744,29 @@ impl OmniDriveServer {
 
         let mut output = String::new();
         output.push_str(&format!("Directory listing for: {}\n", args.path));
+        if args.show_folder {
+            output.push_str(&format!("{}\n", folder_line(&validated.folder)));
+        }
         output.push_str(&format!("Page {} of {} ({} items)\n\n",
             page, (total_items + page_size - 1) / page_size, total_items));
-        output.push_str("Type  | Size       | Name\n");
-        output.push_str("------+------------+---------------------------------------------\n");
+        let show_mode = args.show_mode && cfg!(unix);
+        if show_mode {
+            output.push_str("Type  | Size       | Mode           | Name\n");
+            output.push_str("------+------------+----------------+-----------------------------\n");
+        } else {
+            output.push_str("Type  | Size       | Name\n");
+            output.push_str("------+------------+---------------------------------------------\n");
+        }
 
-        for (name, is_dir, size) in paged_entries {
-            let type_str = if is_dir { "<DIR>" } else { "FIL" };
-            let size_str = if is_dir { "-".to_string() } else { format_size(size) };
-            output.push_str(&format!("{:<5} | {:<10} | {}\n", type_str, size_str, name));
+        for entry in paged_entries {
+            let type_str = if entry.is_dir { "<DIR>" } else { "FIL" };
+            let size_str = if entry.is_dir { "-".to_string() } else { format_size(entry.size) };
+            if show_mode {
+                let mode_str = entry.mode.map(format_mode).unwrap_or_else(|| "-".to_string());
+                output.push_str(&format!("{:<5} | {:<10} | {:<14} | {}\n", type_str, size_str, mode_str, entry.name));
+            } else {
+                output.push_str(&format!("{:<5} | {:<10} | {}\n", type_str, size_str, entry.name));
+            }
         }
 
         Ok(success_log("list_directory", "read", Some(&args.path.clone()), "Listed directory items", vec![Content::text(output)]))
@@ -283,61 +776,127 @@ impl OmniDriveServer {
     // 2. read_file
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Read file content. Supports text, images (base64), and PDFs (text extract).")]
+    #[tool(description = "Read file content. Supports text, images (base64), and PDFs (text extract). Set hex_preview=true to get a hex dump of other binary files.")]
     async fn read_file(&self, params: Parameters<ReadFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_path(&args.path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_path(&args.path, &config)?;
         let file_path = validated.canonical_path;
 
         if !file_path.exists() || !file_path.is_file() {
-            return Err(ErrorData::internal_error(format!("File not found: {}", args.path), None));
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
         }
 
         let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+
+        if let Some(peek_bytes) = args.peek_bytes {
+            if peek_bytes == 0 || peek_bytes > PEEK_MAX_BYTES {
+                return Err(ErrorData::invalid_params(
+                    format!("peek_bytes must be between 1 and {}", PEEK_MAX_BYTES),
+                    None,
+                ));
+            }
+            let mut head = Vec::with_capacity(peek_bytes);
+            fs::File::open(&file_path)
+                .and_then(|f| f.take(peek_bytes as u64).read_to_end(&mut head))
+                .map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+
+            let output = format!(
+                "[Peek: first {} of {} bytes]\n{}",
+                head.len(),
+                metadata.len(),
+                describe_peek(&head)
+            );
+            return Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), &format!("Peeked at {} bytes", head.len()), head.len() as u64, vec![Content::text(output)]));
+        }
+
         let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
         if size_mb > config.max_file_size_mb as f64 {
-             return Err(ErrorData::internal_error(
-                format!("File too large: {:.2} MB (limit: {} MB). Use read_lines tool for partial reads.", size_mb, config.max_file_size_mb),
-                None,
+            return Ok(file_too_large(
+                "read_file",
+                &args.path,
+                metadata.len(),
+                config.max_file_size_mb,
+                " Use read_lines tool or peek_bytes for partial reads.",
             ));
         }
 
         let filename = file_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
         if is_pdf(filename) {
-             match pdf_extract::extract_text(&file_path) {
+            // pdf_extract is synchronous and can spin on pathological files
+            let pdf_path = file_path.clone();
+            let timeout = Duration::from_secs(config.blocking_timeout_secs.max(1));
+            match run_blocking("PDF text extraction", timeout, move || pdf_extract::extract_text(&pdf_path).map_err(|e| e.to_string())).await? {
                 Ok(text) =>
 
-        Ok(success_log("read_file", "read", Some(&args.path.clone()), "Read file contents", vec![Content::text(text)])),
+        Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), "Read file contents", metadata.len(), vec![Content::text(text)])),
                 Err(e) => Err(ErrorData::internal_error(format!("Failed to extract PDF text: {}", e), None)),
             }
         } else if is_binary_file(filename) {
-             let mut file = fs::File::open(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
-             let mut buffer = Vec::new();
-             file.read_to_end(&mut buffer).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
-
-             let encoded = general_purpose::STANDARD.encode(&buffer);
              let mime_type = mime_guess::from_path(&file_path).first_or_text_plain();
+             let header = format!("[Image/Binary content evaluated as base64]\ndata:{};base64,", mime_type);
+             // A base64 payload cut by the response cap is useless, so refuse it whole instead
+             let encoded_len = header.len() as u64 + metadata.len().div_ceil(3) * 4;
+             let response_limit = config.max_response_bytes.max(1024) as u64;
+             if encoded_len > response_limit {
+                 return Ok(encoded_too_large("read_file", &args.path, metadata.len(), encoded_len, response_limit));
+             }
+             let (read_path, cache, budget) = (file_path.clone(), self.read_cache.clone(), read_cache_budget(&config));
+             let buffer = blocking_io(move || {
+                 cache.read(&read_path, budget).map_err(|e| ErrorData::internal_error(e.to_string(), None))
+             })
+             .await?;
+
+             let encoded = general_purpose::STANDARD.encode(&*buffer);
+
+        Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), "Read file contents", metadata.len(), vec![Content::text(header + &encoded)]))
+        } else if is_supported_extension(filename, &config.extra_extensions) {
+             let (read_path, cache, budget) = (file_path.clone(), self.read_cache.clone(), read_cache_budget(&config));
+             let text = blocking_io(move || {
+                 Ok(cache.read(&read_path, budget).ok().and_then(|bytes| String::from_utf8(bytes.to_vec()).ok()))
+             });
+             match text.await? {
+                Some(content) => {
+                    let content = match comment_syntax(filename).filter(|_| args.strip_comments) {
+                        Some(syntax) => strip_comments(&content, &syntax),
+                        None => content,
+                    };
 
-        Ok(success_log("read_file", "read", Some(&args.path.clone()), "Read file contents", vec![Content::text(format!(
-                 "[Image/Binary content evaluated as base64]\ndata:{};base64,{}",
-                 mime_type, encoded
-             ))]))
-        } else if is_supported_extension(filename) {
-             match fs::read_to_string(&file_path) {
-                Ok(content) =>
-
-        Ok(success_log("read_file", "read", Some(&args.path.clone()), "Read file contents", vec![Content::text(content)])),
-                Err(_) => {
+        Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), "Read file contents", metadata.len(), vec![Content::text(content)]))
+                }
+                None => {
                     let content_lossy = fs::read_to_string(&file_path).unwrap_or_default();
 
-        Ok(success_log("read_file", "read", Some(&args.path.clone()), "Read file contents", vec![Content::text(content_lossy)]))
+        Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), "Read file contents", metadata.len(), vec![Content::text(content_lossy)]))
                 }
             }
+        } else if args.hex_preview {
+            let limit = args.hex_bytes.unwrap_or(HEX_PREVIEW_DEFAULT_BYTES);
+            if limit == 0 || limit > PEEK_MAX_BYTES {
+                return Err(ErrorData::invalid_params(
+                    format!("hex_bytes must be between 1 and {}", PEEK_MAX_BYTES),
+                    None,
+                ));
+            }
+            let mut head = Vec::with_capacity(limit);
+            fs::File::open(&file_path)
+                .and_then(|f| f.take(limit as u64).read_to_end(&mut head))
+                .map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+
+            let output = format!(
+                "[Hex preview: first {} of {} bytes]\n{}",
+                head.len(),
+                metadata.len(),
+                hex_dump(&head)
+            );
+            Ok(success_log_bytes("read_file", "read", Some(&args.path.clone()), &format!("Hex preview of {} bytes", head.len()), head.len() as u64, vec![Content::text(output)]))
         } else {
-            Err(ErrorData::internal_error(format!("Unsupported file type: {}", filename), None))
+            Err(ErrorData::invalid_params(
+                format!("Unsupported file type: {}. Set hex_preview=true to inspect its bytes.", filename),
+                None,
+            ))
         }
     }
 
@@ -345,22 +904,54 @@ impl OmniDriveServer {
     // 3. write_file
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Create or overwrite a file. Requires Read/Write permission.")]
+    #[tool(description = "Create or overwrite a file, or set append=true to add to its end. Set overwrite=false to fail instead of replacing an existing file (the server may make that the default, in which case pass overwrite=true to replace one). Pass expected_sha256 (from get_file_info include_hash) to abort if the file changed since you read it. Requires Read/Write permission; in append-only folders existing files accept only appends.")]
     async fn write_file(&self, params: Parameters<WriteFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_writable(&args.path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = if args.append {
+            validate_appendable(&args.path, &config)?
+        } else {
+            validate_writable(&args.path, &config)?
+        };
         let file_path = validated.canonical_path;
 
+        check_expected_sha256(&file_path, args.expected_sha256.as_deref(), &args.path)?;
+
+        let overwrite = args.overwrite.unwrap_or(config.default_overwrite);
+        if !args.append && !overwrite && file_path.exists() {
+            return Err(ErrorData::invalid_params(
+                format!("File already exists: {}. Set overwrite=true to replace it.", args.path),
+                None,
+            ));
+        }
+
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent).map_err(|e| ErrorData::internal_error(format!("Failed to create parent dirs: {}", e), None))?;
         }
 
-        match fs::write(&file_path, args.content) {
-            Ok(_) =>
+        let created = !file_path.exists();
+        let bytes = args.content.len() as u64;
+
+        if args.append {
+            // Only the open is retried: repeating a partial append would duplicate content
+            let mut file = with_retry(|| fs::OpenOptions::new().create(true).append(true).open(&file_path))
+                .map_err(|e| ErrorData::internal_error(format!("Failed to open file for appending: {}", e), None))?;
+            file.write_all(args.content.as_bytes())
+                .map_err(|e| ErrorData::internal_error(format!("Failed to append to file: {}", e), None))?;
+            if created {
+                apply_created_file_mode(&file_path, &config)?;
+            }
+            return Ok(success_log_bytes("write_file", "write", Some(&args.path.clone()), &format!("Appended to file: {}", args.path), bytes, vec![Content::text(format!("Successfully appended {} bytes to {}", bytes, args.path))]));
+        }
 
-        Ok(success_log("write_file", "write", Some(&args.path.clone()), &format!("Wrote file: {}", args.path), vec![Content::text(format!("Successfully wrote to {}", args.path))])),
+        match with_retry(|| fs::write(&file_path, &args.content)) {
+            Ok(_) => {
+                if created {
+                    apply_created_file_mode(&file_path, &config)?;
+                }
+                Ok(success_log_bytes("write_file", "write", Some(&args.path.clone()), &format!("Wrote file: {}", args.path), bytes, vec![Content::text(format!("Successfully wrote to {}", args.path))]))
+            }
             Err(e) => Err(ErrorData::internal_error(format!("Failed to write file: {}", e), None)),
         }
     }
@@ -369,47 +960,65 @@ impl OmniDriveServer {
     // 4. search_files
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Search for files by glob pattern across shared folders.")]
+    #[tool(description = "Search for files by glob pattern across shared folders. Set preview=true to see each file's size, modified time and first line.")]
     async fn search_files(&self, params: Parameters<SearchFilesParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
-
-        let mut results = Vec::new();
-        let pattern_str = args.pattern.trim();
-
-        for folder in &config.folders {
-            if !folder.enabled { continue; }
-            if let Some(ref root) = args.root_path {
-                 if !folder.path.starts_with(root) && !root.starts_with(&folder.path) {
-                     continue;
-                 }
-            }
+        let config = self.config_snapshot().await;
+
+        let relative_root = resolve_relative_root(args.relative_to.as_deref(), &config)?;
+        let max_results = search_result_cap(None, config.max_search_results, &config);
+
+        // Globbing walks whole folders and previews read files; keep it off the async workers
+        let (walk_config, walk_args) = (config.clone(), args.clone());
+        let mut results = blocking_io(move || {
+            let (config, args) = (walk_config, walk_args);
+            let mut results = Vec::new();
+            let pattern_str = args.pattern.trim();
+
+            for folder in &config.folders {
+                if !folder.enabled { continue; }
+                if let Some(ref root) = args.root_path {
+                     if !folder.path.starts_with(root) && !root.starts_with(&folder.path) {
+                         continue;
+                     }
+                }
 
-            let glob_pattern = if pattern_str.contains('/') || pattern_str.contains('\\') {
-                format!("{}/{}", folder.path, pattern_str)
-            } else {
-                format!("{}/**/{}", folder.path, pattern_str)
-            };
+                let glob_pattern = if pattern_str.contains('/') || pattern_str.contains('\\') {
+                    format!("{}/{}", folder.path, pattern_str)
+                } else {
+                    format!("{}/**/{}", folder.path, pattern_str)
+                };
 
-            match glob::glob(&glob_pattern) {
-                Ok(paths) => {
-                    for entry in paths {
-                        if let Ok(path) = entry {
-                            let path_str = path.to_string_lossy().to_string();
-                            if path.is_file() && validate_path(&path_str, &config).is_ok() {
-                                results.push(path_str);
+                match glob::glob(&glob_pattern) {
+                    Ok(paths) => {
+                        for entry in paths {
+                            if let Ok(path) = entry {
+                                let path_str = path.to_string_lossy().to_string();
+                                if path.is_file()
+                                    && !in_default_ignored_dir(&path, std::path::Path::new(&folder.path), &config)
+                                    && validate_path(&path_str, &config).is_ok()
+                                {
+                                    let mut line = display_path(&path, relative_root.as_ref());
+                                    if args.preview && results.len() < max_results {
+                                        line.push_str("\n    ");
+                                        line.push_str(&file_preview(&path, &config));
+                                    }
+                                    results.push(line);
+                                }
                             }
                         }
-                    }
-                },
-                Err(e) => eprintln!("Glob error: {}", e),
+                    },
+                    Err(e) => eprintln!("Glob error: {}", e),
+                }
             }
-        }
+            Ok(results)
+        })
+        .await?;
 
-        if results.len() > 100 {
+        if results.len() > max_results {
             let total = results.len();
-            results.truncate(100);
-            results.push(format!("... and {} more results", total - 100));
+            results.truncate(max_results);
+            results.push(format!("... and {} more results", total - max_results));
         }
 
         if results.is_empty() {
@@ -428,93 +1037,76 @@ impl OmniDriveServer {
     #[tool(description = "Search for a string or regex pattern inside file contents. Returns matching file paths, line numbers, and line content.")]
     async fn grep_content(&self, params: Parameters<GrepContentParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_path(&args.root_path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_path(&args.root_path, &config)?;
         let root = validated.canonical_path;
 
         if !root.is_dir() {
-            return Err(ErrorData::internal_error(
+            return Err(ErrorData::invalid_params(
                 format!("root_path must be a directory: {}", args.root_path), None,
             ));
         }
 
-        let max_results = if args.max_results == 0 { 50 } else { args.max_results.min(200) };
+        let max_results = search_result_cap((args.max_results != 0).then_some(args.max_results), 50, &config);
+        let relative_root = resolve_relative_root(args.relative_to.as_deref(), &config)?;
 
-        // Build the regex matcher
-        let re = if args.is_regex {
-            let pattern = if args.case_insensitive {
-                format!("(?i){}", args.pattern)
-            } else {
-                args.pattern.clone()
-            };
-            regex::Regex::new(&pattern).map_err(|e| {
-                ErrorData::internal_error(format!("Invalid regex '{}': {}", args.pattern, e), None)
-            })?
-        } else {
-            let escaped = regex::escape(&args.pattern);
-            let pattern = if args.case_insensitive {
-                format!("(?i){}", escaped)
-            } else {
-                escaped
-            };
-            regex::Regex::new(&pattern).map_err(|e| {
-                ErrorData::internal_error(format!("Internal regex error: {}", e), None)
-            })?
-        };
-
-        let mut results = Vec::new();
-        let walker = walkdir::WalkDir::new(&root)
-            .max_depth(20)
-            .into_iter()
-            .filter_map(|e| e.ok());
-
-        'outer: for entry in walker {
-            let path = entry.path();
-            if !path.is_file() { continue; }
-
-            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            if !is_supported_extension(filename) || is_binary_file(filename) { continue; }
-
-            // Extension filter
-            if let Some(ref exts) = args.include_extensions {
-                let file_ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                if !exts.iter().any(|e| e.eq_ignore_ascii_case(file_ext)) {
-                    continue;
-                }
-            }
+        let re = build_matcher(&args.pattern, args.is_regex, args.case_insensitive)?;
 
-            // Validate path is within sandbox
-            let path_str = path.to_string_lossy().to_string();
-            if validate_path(&path_str, &config).is_err() { continue; }
-
-            // Check file size — skip very large files
-            if let Ok(meta) = fs::metadata(path) {
-                if meta.len() > (config.max_file_size_mb as u64) * 1024 * 1024 {
-                    continue;
-                }
-            }
-
-            // Read and search
-            if let Ok(file) = fs::File::open(path) {
-                let reader = std::io::BufReader::new(file);
-                for (line_num, line) in reader.lines().enumerate() {
-                    if let Ok(line) = line {
-                        if re.is_match(&line) {
-                            results.push(format!(
-                                "{}:{}:{}",
-                                path_str,
-                                line_num + 1,
-                                line.chars().take(200).collect::<String>()
-                            ));
-                            if results.len() >= max_results {
-                                break 'outer;
+        // The walk and reads are synchronous; keep them off the async workers
+        let max_per_file = args.max_per_file.unwrap_or(usize::MAX).max(1);
+        let filter = GrepFilter {
+            max_depth: args.max_depth.unwrap_or(GREP_DEFAULT_DEPTH).clamp(1, GREP_MAX_DEPTH),
+            skip_hidden: args.skip_hidden,
+            force_text: args.force_text,
+            include_extensions: args.include_extensions.clone(),
+        };
+        let walk_config = config.clone();
+        let (results, match_count) = blocking_io(move || {
+            let config = walk_config;
+            let mut results = Vec::new();
+            let mut match_count = 0;
+
+            'outer: for path in grep_candidates(&root, &filter, &config) {
+                let path = path.as_path();
+
+                // Read and search, skipping individual lines that aren't valid UTF-8
+                if let Ok(file) = fs::File::open(path) {
+                    let reader = std::io::BufReader::new(file);
+                    let shown_path = display_path(path, relative_root.as_ref());
+                    let mut file_matches = 0;
+                    for (line_num, raw) in reader.split(b'\n').enumerate() {
+                        let Ok(raw) = raw else { break };
+                        if let Ok(line) = std::str::from_utf8(&raw) {
+                            let line = line.strip_suffix('\r').unwrap_or(line);
+                            if re.is_match(line) {
+                                if file_matches >= max_per_file {
+                                    results.push(format!(
+                                        "{}: … more matches not shown (max_per_file={})",
+                                        shown_path, max_per_file
+                                    ));
+                                    break;
+                                }
+                                results.push(format!(
+                                    "{}:{}:{}",
+                                    shown_path,
+                                    line_num + 1,
+                                    line.chars().take(200).collect::<String>()
+                                ));
+                                file_matches += 1;
+                                match_count += 1;
+                                if match_count >= max_results {
+                                    break 'outer;
+                                }
                             }
                         }
                     }
                 }
             }
-        }
+            Ok((results, match_count))
+        })
+        .await?;
 
         if results.is_empty() {
 
@@ -522,7 +1114,7 @@ impl OmniDriveServer {
                 format!("No matches found for '{}' in {}", args.pattern, args.root_path),
             )]))
         } else {
-            let header = format!("Found {} match(es) for '{}':\n\n", results.len(), args.pattern);
+            let header = format!("Found {} match(es) for '{}':\n\n", match_count, args.pattern);
 
         Ok(success_log("grep_content", "read", Some(&args.root_path.clone()), &format!("Grepped for {}", args.pattern), vec![Content::text(
                 format!("{}{}", header, results.join("\n")),
@@ -534,25 +1126,61 @@ impl OmniDriveServer {
     // 6. read_lines — read head/tail/range of a file
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Read specific lines from a file. Use start_line/end_line for a range, tail=N for last N lines, or omit all for first 100 lines. Returns line-numbered content and total line count.")]
+    #[tool(description = "Read specific lines from a file. Use start_line/end_line for a range, tail=N for last N lines, ranges=[{start_line, end_line}, ...] for several sections in one call, or omit all for first 100 lines. Returns line-numbered content and total line count.")]
     async fn read_lines(&self, params: Parameters<ReadLinesParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_path(&args.path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_path(&args.path, &config)?;
         let file_path = validated.canonical_path;
 
         if !file_path.exists() || !file_path.is_file() {
-            return Err(ErrorData::internal_error(format!("File not found: {}", args.path), None));
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
         }
 
-        let content = fs::read_to_string(&file_path).map_err(|e| {
-            ErrorData::internal_error(format!("Cannot read file (binary?): {}. Use read_file for binary content.", e), None)
+        let bytes = fs::read(&file_path).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to read file: {}", e), None)
+        })?;
+        let content = String::from_utf8(bytes).map_err(|e| {
+            ErrorData::invalid_params(
+                format!(
+                    "Cannot read file as UTF-8: {}. The file is binary or uses another encoding; use read_file for binary content, or convert_file with source_encoding (e.g. \"windows-1252\") to re-encode it as UTF-8.",
+                    describe_utf8_error(e.as_bytes(), &e.utf8_error())
+                ),
+                None,
+            )
         })?;
 
         let all_lines: Vec<&str> = content.lines().collect();
         let total_lines = all_lines.len();
 
+        if let Some(ranges) = &args.ranges {
+            let merged = merge_line_ranges(ranges, total_lines);
+            if merged.is_empty() {
+                return Err(ErrorData::invalid_params(
+                    format!("No requested range overlaps the file's {} line(s)", total_lines),
+                    None,
+                ));
+            }
+
+            let mut output = format!("File: {} ({} total lines)\n", args.path, total_lines);
+            let mut budget = READ_RANGES_MAX_LINES;
+            for (start, end) in &merged {
+                if budget == 0 {
+                    output.push_str(&format!("\n[stopped at {} lines; request the remaining ranges separately]\n", READ_RANGES_MAX_LINES));
+                    break;
+                }
+                let shown_end = (*end).min(start + budget - 1);
+                output.push_str(&format!("\nLines {}-{}:\n", start, shown_end));
+                for (i, line) in all_lines[start - 1..shown_end].iter().enumerate() {
+                    output.push_str(&format!("{:>6} | {}\n", start + i, line));
+                }
+                budget -= shown_end - start + 1;
+            }
+
+            return Ok(success_log("read_lines", "read", Some(&args.path.clone()), &format!("Read {} line range(s)", merged.len()), vec![Content::text(output)]));
+        }
+
         let (start, end) = if let Some(tail_n) = args.tail {
             let n = tail_n.min(total_lines);
             (total_lines.saturating_sub(n), total_lines)
@@ -580,18 +1208,16 @@ impl OmniDriveServer {
     #[tool(description = "Move or rename a file or directory. Both source and destination must be in writable shared folders.")]
     async fn move_file(&self, params: Parameters<MoveFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let src_validated = validate_destructive(&args.source, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
-        let dst_validated = validate_writable(&args.destination, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let src_validated = validate_destructive(&args.source, &config)?;
+        let dst_validated = validate_writable(&args.destination, &config)?;
 
         let src = src_validated.canonical_path;
         let dst = dst_validated.canonical_path;
 
         if dst.exists() {
-            return Err(ErrorData::internal_error(
+            return Err(ErrorData::invalid_params(
                 format!("Destination already exists: {}. Delete it first or choose a different name.", args.destination),
                 None,
             ));
@@ -604,34 +1230,11 @@ impl OmniDriveServer {
             })?;
         }
 
-        // Try rename first (same filesystem), fall back to copy+delete
-        match fs::rename(&src, &dst) {
-            Ok(_) =>
-
-        Ok(success_log("move_file", "delete", Some(&args.source.clone()), &format!("Moved to {}", args.destination), vec![Content::text(
-                format!("Moved {} → {}", args.source, args.destination),
-            )])),
-            Err(_) => {
-                // Cross-device move: copy then delete
-                if src.is_file() {
-                    fs::copy(&src, &dst).map_err(|e| {
-                        ErrorData::internal_error(format!("Failed to copy during move: {}", e), None)
-                    })?;
-                    fs::remove_file(&src).map_err(|e| {
-                        ErrorData::internal_error(format!("Copied but failed to remove source: {}", e), None)
-                    })?;
-                } else {
-                    return Err(ErrorData::internal_error(
-                        "Cross-device directory moves are not supported. Copy manually and delete the source.",
-                        None,
-                    ));
-                }
+        let note = if move_path(&src, &dst)? { " (cross-device)" } else { "" };
 
         Ok(success_log("move_file", "delete", Some(&args.source.clone()), &format!("Moved to {}", args.destination), vec![Content::text(
-                    format!("Moved {} → {} (cross-device)", args.source, args.destination),
-                )]))
-            }
-        }
+            format!("Moved {} → {}{}", args.source, args.destination, note),
+        )]))
     }
 
     // ────────────────────────────────────────────────────────
@@ -641,12 +1244,14 @@ impl OmniDriveServer {
     #[tool(description = "Delete a file or empty directory. Requires Read/Write permission. Non-empty directories cannot be deleted (safety measure).")]
     async fn delete_file(&self, params: Parameters<DeleteFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_destructive(&args.path, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_destructive(&args.path, &config)?;
         let target = validated.canonical_path;
 
+        let policy = crate::confirm::Policy::from(&*config);
+        crate::confirm::confirm_destructive(policy, "delete_file", &args.path, "deletion").await?;
+
         if target.is_file() {
             fs::remove_file(&target).map_err(|e| {
                 ErrorData::internal_error(format!("Failed to delete file: {}", e), None)
@@ -674,34 +1279,24 @@ impl OmniDriveServer {
     #[tool(description = "Copy a file. Source must be readable, destination must be in a writable shared folder.")]
     async fn copy_file(&self, params: Parameters<CopyFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let src_validated = validate_path(&args.source, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
-        let dst_validated = validate_writable(&args.destination, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let src_validated = validate_path(&args.source, &config)?;
+        let dst_validated = validate_writable(&args.destination, &config)?;
 
         let src = src_validated.canonical_path;
         let dst = dst_validated.canonical_path;
 
         if !src.is_file() {
-            return Err(ErrorData::internal_error(
+            return Err(ErrorData::invalid_params(
                 format!("Source is not a file: {}. Only files can be copied.", args.source),
                 None,
             ));
         }
 
-        if let Some(parent) = dst.parent() {
-            fs::create_dir_all(parent).map_err(|e| {
-                ErrorData::internal_error(format!("Failed to create destination directory: {}", e), None)
-            })?;
-        }
-
-        let bytes_copied = fs::copy(&src, &dst).map_err(|e| {
-            ErrorData::internal_error(format!("Failed to copy file: {}", e), None)
-        })?;
+        let bytes_copied = copy_one_file(&src, &dst)?;
 
-        Ok(success_log("copy_file", "write", Some(&args.destination.clone()), &format!("Copied from {}", args.source), vec![Content::text(
+        Ok(success_log_bytes("copy_file", "write", Some(&args.destination.clone()), &format!("Copied from {}", args.source), bytes_copied, vec![Content::text(
             format!("Copied {} → {} ({})", args.source, args.destination, format_size(bytes_copied)),
         )]))
     }
@@ -710,16 +1305,16 @@ impl OmniDriveServer {
     // 10. get_file_info — metadata without reading content
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Get file or directory metadata (size, modified date, type, MIME) without reading content.")]
+    #[tool(description = "Get file or directory metadata (size, modified date, type, MIME, whether you may write to it) without reading content.")]
     async fn get_file_info(&self, params: Parameters<GetFileInfoParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_path(&args.path, &config).map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_path(&args.path, &config)?;
         let target = validated.canonical_path;
 
         if !target.exists() {
-            return Err(ErrorData::internal_error(format!("Path not found: {}", args.path), None));
+            return Err(ErrorData::invalid_params(format!("Path not found: {}", args.path), None));
         }
 
         let meta = fs::metadata(&target).map_err(|e| {
@@ -728,31 +1323,54 @@ impl OmniDriveServer {
 
         let file_type = if meta.is_file() { "file" } else if meta.is_dir() { "directory" } else { "symlink/other" };
         let size = meta.len();
-        let modified = meta.modified().ok().map(|t| {
-            let dt: chrono::DateTime<chrono::Local> = t.into();
-            dt.format("%Y-%m-%d %H:%M:%S").to_string()
-        }).unwrap_or_else(|| "unknown".to_string());
-
-        let permission = &validated.folder.permission;
-        let perm_str = match permission {
-            crate::config::Permission::ReadOnly => "read-only",
-            crate::config::Permission::ReadWrite => "read-write",
-        };
+        let modified = meta.modified().ok().map(|t| format_timestamp(t, &config)).unwrap_or_else(|| "unknown".to_string());
+
+        let perm_str = permission_label(&validated.folder.permission);
 
         let mut output = String::new();
         output.push_str(&format!("Path: {}\n", args.path));
         output.push_str(&format!("Type: {}\n", file_type));
         output.push_str(&format!("Size: {} ({} bytes)\n", format_size(size), size));
         output.push_str(&format!("Modified: {}\n", modified));
+        // Not every platform/filesystem records these, so omit them when unavailable
+        if let Ok(created) = meta.created() {
+            output.push_str(&format!("Created: {}\n", format_timestamp(created, &config)));
+        }
+        if let Ok(accessed) = meta.accessed() {
+            output.push_str(&format!("Accessed: {}\n", format_timestamp(accessed, &config)));
+        }
+        if args.show_folder {
+            output.push_str(&format!("Folder: {}\n", validated.folder.path));
+        }
         output.push_str(&format!("Permission: {}\n", perm_str));
+        output.push_str(&format!("Writable: {}\n", validate_writable(&args.path, &config).is_ok()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            output.push_str(&format!("Mode: {:04o}\n", meta.permissions().mode() & 0o7777));
+        }
 
         if meta.is_file() {
             let mime = mime_guess::from_path(&target).first_or_text_plain();
             output.push_str(&format!("MIME type: {}\n", mime));
 
+            if args.include_hash {
+                if size > (config.max_file_size_mb as u64) * 1024 * 1024 {
+                    output.push_str(&format!("SHA-256: skipped (file exceeds {} MB limit)\n", config.max_file_size_mb));
+                } else {
+                    let hash_path = target.clone();
+                    let timeout = Duration::from_secs(config.blocking_timeout_secs.max(1));
+                    match run_blocking("Hashing", timeout, move || sha256_file(&hash_path)).await {
+                        Ok(Ok(hash)) => output.push_str(&format!("SHA-256: {}\n", hash)),
+                        Ok(Err(e)) => output.push_str(&format!("SHA-256: unavailable ({})\n", e)),
+                        Err(e) => output.push_str(&format!("SHA-256: unavailable ({})\n", e.message)),
+                    }
+                }
+            }
+
             // Count lines for text files
             let filename = target.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            if is_supported_extension(filename) && !is_binary_file(filename) {
+            if is_supported_extension(filename, &config.extra_extensions) && !is_binary_file(filename) {
                 if let Ok(content) = fs::read_to_string(&target) {
                     output.push_str(&format!("Line count: {}\n", content.lines().count()));
                 }
@@ -775,51 +1393,56 @@ impl OmniDriveServer {
     #[tool(description = "Read multiple files in a single call. Returns content for each file or per-file errors. Stops if cumulative size exceeds max_total_size_mb.")]
     async fn batch_read(&self, params: Parameters<BatchReadParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
         if args.paths.is_empty() {
-            return Err(ErrorData::internal_error("paths array is empty. Provide at least one file path.", None));
+            return Err(ErrorData::invalid_params("paths array is empty. Provide at least one file path.", None));
         }
         if args.paths.len() > 50 {
-            return Err(ErrorData::internal_error("Too many paths (max 50). Split into multiple calls.", None));
+            return Err(ErrorData::invalid_params("Too many paths (max 50). Split into multiple calls.", None));
         }
 
         let max_bytes = (args.max_total_size_mb * 1024.0 * 1024.0) as u64;
         let mut total_bytes: u64 = 0;
         let mut results = Vec::new();
+        let mut failures = Vec::new();
+        let mut fail = |results: &mut Vec<String>, path: &str, code: &str, label: &str, message: String| {
+            results.push(format!("--- {} ---\n{}: {}\n", path, label, message));
+            failures.push(serde_json::json!({ "path": path, "code": code, "message": message }));
+        };
 
         for path_str in &args.paths {
             match validate_path(path_str, &config) {
                 Err(e) => {
-                    results.push(format!("--- {} ---\nERROR: {}\n", path_str, e));
+                    fail(&mut results, path_str, "access_denied", "ERROR", e.to_string());
                     continue;
                 }
                 Ok(validated) => {
                     let file_path = validated.canonical_path;
                     if !file_path.is_file() {
-                        results.push(format!("--- {} ---\nERROR: Not a file\n", path_str));
+                        fail(&mut results, path_str, "not_a_file", "ERROR", "Not a file".to_string());
                         continue;
                     }
 
                     let meta = match fs::metadata(&file_path) {
                         Ok(m) => m,
                         Err(e) => {
-                            results.push(format!("--- {} ---\nERROR: {}\n", path_str, e));
+                            fail(&mut results, path_str, "io_error", "ERROR", e.to_string());
                             continue;
                         }
                     };
 
                     if total_bytes + meta.len() > max_bytes {
-                        results.push(format!(
-                            "--- {} ---\nSKIPPED: Would exceed max_total_size_mb ({:.1} MB). Use a separate call.\n",
-                            path_str, args.max_total_size_mb
+                        fail(&mut results, path_str, "batch_size_exceeded", "SKIPPED", format!(
+                            "Would exceed max_total_size_mb ({:.1} MB). Use a separate call.",
+                            args.max_total_size_mb
                         ));
                         continue;
                     }
 
                     let filename = file_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
                     if is_binary_file(filename) {
-                        results.push(format!("--- {} ---\nSKIPPED: Binary file. Use read_file for binary content.\n", path_str));
+                        fail(&mut results, path_str, "binary_file", "SKIPPED", "Binary file. Use read_file for binary content.".to_string());
                         continue;
                     }
 
@@ -829,7 +1452,7 @@ impl OmniDriveServer {
                             results.push(format!("--- {} ---\n{}\n", path_str, content));
                         }
                         Err(e) => {
-                            results.push(format!("--- {} ---\nERROR: {}\n", path_str, e));
+                            fail(&mut results, path_str, "io_error", "ERROR", e.to_string());
                         }
                     }
                 }
@@ -838,102 +1461,104 @@ impl OmniDriveServer {
 
         let header = format!("Batch read: {} file(s), {}\n\n", args.paths.len(), format_size(total_bytes));
 
-        Ok(success_log("batch_read", "read", Some(&format!("{} paths", args.paths.len())), "Batch read files", vec![Content::text(format!("{}{}", header, results.join("\n")))]))
+        let mut result = success_log_bytes("batch_read", "read", Some(&format!("{} paths", args.paths.len())), "Batch read files", total_bytes, vec![Content::text(format!("{}{}", header, results.join("\n")))]);
+        if !failures.is_empty() {
+            // A partial batch is a soft error; it only counts as failed when nothing could be read
+            let read = args.paths.len() - failures.len();
+            result.is_error = Some(read == 0);
+            result.structured_content = Some(serde_json::json!({
+                "error": {
+                    "code": "partial_batch",
+                    "message": format!("{} of {} file(s) could not be read", failures.len(), args.paths.len()),
+                    "read": read,
+                    "failed": failures,
+                }
+            }));
+        }
+        Ok(result)
     }
 
     // ────────────────────────────────────────────────────────
     // 12. zip_files — create a zip archive
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Create a zip archive from one or more files. All source paths must be readable, output path must be writable.")]
+    #[tool(description = "Create a zip archive from one or more files. All source paths must be readable, output path must be writable. Use exclude (glob patterns like \"**/*.log\" or \"target/**\") to skip files in directories, and include_extensions to keep only certain file types (exclude wins). Set dry_run=true to list the files and total size without writing the archive.")]
     async fn zip_files(&self, params: Parameters<ZipFilesParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
 
         if args.paths.is_empty() {
-            return Err(ErrorData::internal_error("paths array is empty.", None));
+            return Err(ErrorData::invalid_params("paths array is empty.", None));
         }
 
-        let out_validated = validate_writable(&args.output_path, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let out_validated = validate_writable(&args.output_path, &config)?;
         let out_path = out_validated.canonical_path;
 
+        let filter = ZipFilter::new(
+            args.exclude.as_deref().unwrap_or_default(),
+            args.include_extensions.as_deref(),
+        )
+        .map_err(|e| ErrorData::invalid_params(e, None))?;
+        let paths = args.paths.clone();
+        let walk_config = config.clone();
+        let (sources, excluded) = blocking_io(move || collect_zip_sources(&paths, &walk_config, &filter)).await?;
+        let excluded_note = if excluded > 0 { format!(", {} excluded by pattern", excluded) } else { String::new() };
+
+        if args.dry_run {
+            let total: u64 = sources.iter().map(|s| s.size).sum();
+            let mut output = format!(
+                "Dry run: would create {} with {} files ({} uncompressed{})\n",
+                args.output_path,
+                sources.len(),
+                format_size(total),
+                excluded_note
+            );
+            for source in &sources {
+                output.push_str(&format!("  {} ({})\n", source.name, format_size(source.size)));
+            }
+            return Ok(success_log("zip_files", "read", Some(&args.output_path.clone()), "Previewed zip archive", vec![Content::text(output)]));
+        }
+
         if let Some(parent) = out_path.parent() {
             fs::create_dir_all(parent).map_err(|e| {
                 ErrorData::internal_error(format!("Failed to create output directory: {}", e), None)
             })?;
         }
 
-        let file = fs::File::create(&out_path).map_err(|e| {
-            ErrorData::internal_error(format!("Failed to create zip file: {}", e), None)
-        })?;
-        let mut zip_writer = zip::ZipWriter::new(file);
-        let options = zip::write::SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated);
-
-        let mut file_count = 0u32;
-
-        for path_str in &args.paths {
-            let validated = validate_path(path_str, &config)
-                .map_err(|e| ErrorData::internal_error(e, None))?;
-            let src_path = validated.canonical_path;
+        let zip_path = out_path.clone();
+        let file_count = blocking_io(move || {
+            let file = fs::File::create(&zip_path).map_err(|e| {
+                ErrorData::internal_error(format!("Failed to create zip file: {}", e), None)
+            })?;
+            let mut zip_writer = zip::ZipWriter::new(file);
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
 
-            if src_path.is_file() {
-                let name = src_path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
-                zip_writer.start_file(name, options).map_err(|e| {
+            for source in &sources {
+                zip_writer.start_file(source.name.as_str(), options).map_err(|e| {
                     ErrorData::internal_error(format!("Zip error: {}", e), None)
                 })?;
-                let mut f = fs::File::open(&src_path).map_err(|e| {
-                    ErrorData::internal_error(format!("Failed to open {}: {}", path_str, e), None)
-                })?;
-                let mut buf = Vec::new();
-                f.read_to_end(&mut buf).map_err(|e| {
-                    ErrorData::internal_error(format!("Failed to read {}: {}", path_str, e), None)
+                let mut f = fs::File::open(&source.path).map_err(|e| {
+                    ErrorData::internal_error(format!("Failed to open {}: {}", source.path.display(), e), None)
                 })?;
-                zip_writer.write_all(&buf).map_err(|e| {
+                std::io::copy(&mut f, &mut zip_writer).map_err(|e| {
                     ErrorData::internal_error(format!("Zip write error: {}", e), None)
                 })?;
-                file_count += 1;
-            } else if src_path.is_dir() {
-                // Walk directory and add all files
-                let walker = walkdir::WalkDir::new(&src_path).into_iter().filter_map(|e| e.ok());
-                for entry in walker {
-                    let entry_path = entry.path();
-                    if entry_path.is_file() {
-                        let rel = entry_path.strip_prefix(&src_path).unwrap_or(entry_path);
-                        let name = rel.to_string_lossy().to_string();
-
-                        // Validate each file in sandbox
-                        let entry_str = entry_path.to_string_lossy().to_string();
-                        if validate_path(&entry_str, &config).is_err() { continue; }
-
-                        zip_writer.start_file(&name, options).map_err(|e| {
-                            ErrorData::internal_error(format!("Zip error: {}", e), None)
-                        })?;
-                        let mut f = fs::File::open(entry_path).map_err(|e| {
-                            ErrorData::internal_error(format!("Failed to open: {}", e), None)
-                        })?;
-                        let mut buf = Vec::new();
-                        f.read_to_end(&mut buf).map_err(|e| {
-                            ErrorData::internal_error(format!("Failed to read: {}", e), None)
-                        })?;
-                        zip_writer.write_all(&buf).map_err(|e| {
-                            ErrorData::internal_error(format!("Zip write error: {}", e), None)
-                        })?;
-                        file_count += 1;
-                    }
-                }
             }
-        }
 
-        zip_writer.finish().map_err(|e| {
-            ErrorData::internal_error(format!("Failed to finalize zip: {}", e), None)
-        })?;
+            zip_writer.finish().map_err(|e| {
+                ErrorData::internal_error(format!("Failed to finalize zip: {}", e), None)
+            })?;
+
+            Ok(sources.len())
+        })
+        .await?;
 
         let zip_size = fs::metadata(&out_path).map(|m| format_size(m.len())).unwrap_or_default();
 
         Ok(success_log("zip_files", "write", Some(&args.output_path.clone()), "Created zip archive", vec![Content::text(
-            format!("Created zip archive: {} ({} files, {})", args.output_path, file_count, zip_size),
+            format!("Created zip archive: {} ({} files, {}{})", args.output_path, file_count, zip_size, excluded_note),
         )]))
     }
 
@@ -941,15 +1566,14 @@ impl OmniDriveServer {
     // 13. unzip_files — extract a zip archive
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Extract a zip archive to a directory. Archive must be readable, destination must be writable.")]
+    #[tool(description = "Extract a zip archive to a directory. Archive must be readable, destination must be writable. Set dry_run=true to list the entries that would be extracted without writing anything.")]
     async fn unzip_files(&self, params: Parameters<UnzipFilesParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
 
-        let arc_validated = validate_path(&args.archive_path, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
-        let dst_validated = validate_writable(&args.destination, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let arc_validated = validate_path(&args.archive_path, &config)?;
+        let dst_validated = validate_writable(&args.destination, &config)?;
 
         let archive_path = arc_validated.canonical_path;
         let dest_path = dst_validated.canonical_path;
@@ -959,14 +1583,19 @@ impl OmniDriveServer {
         })?;
 
         let mut archive = zip::ZipArchive::new(file).map_err(|e| {
-            ErrorData::internal_error(format!("Invalid zip archive: {}", e), None)
+            ErrorData::invalid_params(format!("Invalid zip archive: {}", e), None)
         })?;
 
-        fs::create_dir_all(&dest_path).map_err(|e| {
-            ErrorData::internal_error(format!("Failed to create destination: {}", e), None)
-        })?;
+        if !args.dry_run {
+            fs::create_dir_all(&dest_path).map_err(|e| {
+                ErrorData::internal_error(format!("Failed to create destination: {}", e), None)
+            })?;
+        }
 
         let mut extracted = 0u32;
+        let mut planned = Vec::new();
+        let mut skipped = Vec::new();
+        let mut denied = Vec::new();
         for i in 0..archive.len() {
             let mut entry = archive.by_index(i).map_err(|e| {
                 ErrorData::internal_error(format!("Zip read error: {}", e), None)
@@ -977,6 +1606,28 @@ impl OmniDriveServer {
             // Security: ensure extracted path stays within destination
             if !out_path.starts_with(&dest_path) {
                 eprintln!("[omnidrive] Skipping suspicious zip entry: {}", entry.name());
+                skipped.push(entry.name().to_string());
+                continue;
+            }
+
+            // Each entry gets the same checks as any other write, so an archive can't
+            // drop files into ignored paths or replace the folder's .mcpignore
+            let out_str = out_path.to_string_lossy();
+            let allowed = if entry.is_dir() {
+                validate_path(&out_str, &config).is_ok()
+            } else {
+                validate_writable(&out_str, &config).is_ok()
+            };
+            if !allowed {
+                denied.push(entry.name().to_string());
+                continue;
+            }
+
+            if args.dry_run {
+                if !entry.is_dir() {
+                    let rel = out_path.strip_prefix(&dest_path).unwrap_or(&out_path);
+                    planned.push(format!("  {} ({})", rel.display(), format_size(entry.size())));
+                }
                 continue;
             }
 
@@ -996,214 +1647,6166 @@ impl OmniDriveServer {
             }
         }
 
-        Ok(success_log("unzip_files", "write", Some(&args.destination.clone()), "Extracted zip archive", vec![Content::text(
-            format!("Extracted {} files to {}", extracted, args.destination),
-        )]))
+        if args.dry_run {
+            let mut output = format!("Dry run: would extract {} files to {}\n{}\n", planned.len(), args.destination, planned.join("\n"));
+            if !skipped.is_empty() {
+                output.push_str(&format!("\nWould skip {} entries that escape the destination: {}\n", skipped.len(), skipped.join(", ")));
+            }
+            if !denied.is_empty() {
+                output.push_str(&format!("\nWould skip {} entries that may not be written (ignored or protected): {}\n", denied.len(), denied.join(", ")));
+            }
+            return Ok(success_log("unzip_files", "read", Some(&args.archive_path.clone()), "Previewed zip extraction", vec![Content::text(output)]));
+        }
+
+        let mut output = format!("Extracted {} files to {}", extracted, args.destination);
+        if !denied.is_empty() {
+            output.push_str(&format!("\nSkipped {} entries that may not be written (ignored or protected): {}", denied.len(), denied.join(", ")));
+        }
+        Ok(success_log("unzip_files", "write", Some(&args.destination.clone()), "Extracted zip archive", vec![Content::text(output)]))
     }
 
     // ────────────────────────────────────────────────────────
     // 14. patch_file — targeted search-and-replace editing
     // ────────────────────────────────────────────────────────
 
-    #[tool(description = "Apply targeted edits to a file without rewriting it entirely. Supports search-and-replace (literal or regex) and line-range replacement. Requires Read/Write permission.")]
+    #[tool(description = "Apply targeted edits to a file without rewriting it entirely. Supports search-and-replace (literal or regex) and line-range replacement. Pass expected_sha256 to abort if the file changed since you read it. Requires Read/Write permission.")]
     async fn patch_file(&self, params: Parameters<PatchFileParams>) -> Result<CallToolResult, ErrorData> {
         let args = params.0;
-        let config = self.config.read().await;
+        let config = self.config_snapshot().await;
 
-        let validated = validate_writable(&args.path, &config)
-            .map_err(|e| ErrorData::internal_error(e, None))?;
+        let validated = validate_writable(&args.path, &config)?;
         let file_path = validated.canonical_path;
 
         if !file_path.is_file() {
-            return Err(ErrorData::internal_error(format!("File not found: {}", args.path), None));
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
         }
 
         if args.search_replace.is_empty() && args.line_replace.is_empty() {
-            return Err(ErrorData::internal_error(
+            return Err(ErrorData::invalid_params(
                 "No operations provided. Provide at least one search_replace or line_replace operation.",
                 None,
             ));
         }
 
-        let mut content = fs::read_to_string(&file_path).map_err(|e| {
-            ErrorData::internal_error(format!("Cannot read file: {}", e), None)
+        check_expected_sha256(&file_path, args.expected_sha256.as_deref(), &args.path)?;
+
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file: {}", e), None)
         })?;
 
-        let mut summary = Vec::new();
+        let (content, summary) = apply_patch_ops(content, &args.search_replace, &args.line_replace)?;
 
-        // Apply search-and-replace operations
-        for (i, op) in args.search_replace.iter().enumerate() {
-            if op.regex {
-                let re = regex::Regex::new(&op.search).map_err(|e| {
-                    ErrorData::internal_error(format!("Invalid regex in operation {}: {}", i + 1, e), None)
-                })?;
+        // Write back
+        fs::write(&file_path, &content).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to write patched file: {}", e), None)
+        })?;
 
-                let limit = op.count.unwrap_or(0);
-                let (new_content, replacements) = if limit > 0 {
-                    let mut count = 0usize;
-                    let result = re.replace_all(&content, |caps: &regex::Captures| {
-                        count += 1;
-                        if count <= limit {
-                            op.replace.clone()
-                        } else {
-                            caps[0].to_string()
-                        }
-                    });
-                    (result.to_string(), count.min(limit))
-                } else {
-                    let matches = re.find_iter(&content).count();
-                    let result = re.replace_all(&content, op.replace.as_str());
-                    (result.to_string(), matches)
-                };
+        let result = format!(
+            "Patched {}\n\n{}\n\nFinal size: {}",
+            args.path,
+            summary.join("\n"),
+            format_size(content.len() as u64),
+        );
 
-                summary.push(format!("Op {}: regex '{}' → {} replacement(s)", i + 1, op.search, replacements));
-                content = new_content;
-            } else {
-                let limit = op.count.unwrap_or(0);
-                let mut count = 0usize;
-                let mut new_content = String::new();
-                let mut remaining = content.as_str();
-
-                while let Some(pos) = remaining.find(&op.search) {
-                    if limit > 0 && count >= limit { break; }
-                    new_content.push_str(&remaining[..pos]);
-                    new_content.push_str(&op.replace);
-                    remaining = &remaining[pos + op.search.len()..];
-                    count += 1;
-                }
-                new_content.push_str(remaining);
+        Ok(success_log("patch_file", "write", Some(&args.path.clone()), "Patched file contents", vec![Content::text(result)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 15. read_csv — structured slice of a CSV file
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Read a slice of a CSV file as rows. Returns JSON with the header, the requested rows (as arrays of strings), and whether more rows follow. Use start_row/max_rows to page through large files.")]
+    async fn read_csv(&self, params: Parameters<ReadCsvParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
 
-                summary.push(format!("Op {}: '{}' → {} replacement(s)", i + 1, op.search, count));
-                content = new_content;
+        let delimiter = match args.delimiter.as_deref() {
+            None | Some("") => b',',
+            Some(d) if d.len() == 1 && d.is_ascii() => d.as_bytes()[0],
+            Some(d) => {
+                return Err(ErrorData::invalid_params(
+                    format!("Invalid delimiter '{}': must be a single ASCII character", d),
+                    None,
+                ));
             }
+        };
+        let start_row = args.start_row.unwrap_or(1).max(1);
+        let max_rows = args.max_rows.unwrap_or(100).clamp(1, 1000);
+
+        // Rows are streamed, so only the requested window is held in memory
+        let file = fs::File::open(&file_path).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to open file: {}", e), None)
+        })?;
+        let slice = read_csv_slice(file, delimiter, start_row, max_rows).map_err(|e| {
+            ErrorData::invalid_params(format!("Failed to parse CSV: {}", e), None)
+        })?;
+
+        let returned = slice.rows.len();
+        let json = serde_json::to_string_pretty(&slice).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to serialize rows: {}", e), None)
+        })?;
+
+        Ok(success_log("read_csv", "read", Some(&args.path.clone()), &format!("Read {} CSV row(s)", returned), vec![Content::text(json)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 16. validate_json — parse check with error location
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Check whether a file is valid JSON. Returns { valid, error, pretty? } where error includes the line/column of the first problem. Set pretty=true to also get a pretty-printed copy.")]
+    async fn validate_json(&self, params: Parameters<ValidateJsonParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
         }
 
-        // Apply line-range replacements (on the already-modified content)
-        if !args.line_replace.is_empty() {
-            let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("validate_json", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
 
-            // Sort by start_line descending so we can replace without shifting indices
-            let mut line_ops = args.line_replace.clone();
-            line_ops.sort_by(|a, b| b.start_line.cmp(&a.start_line));
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
 
-            for (i, op) in line_ops.iter().enumerate() {
-                if op.start_line == 0 || op.end_line == 0 || op.start_line > op.end_line {
-                    return Err(ErrorData::internal_error(
-                        format!("Invalid line range in line_replace op: {}-{} (1-indexed, start <= end)", op.start_line, op.end_line),
-                        None,
-                    ));
+        let result = match serde_json::from_str::<serde_json::Value>(&content) {
+            Ok(value) => {
+                let mut result = serde_json::json!({ "valid": true, "error": null });
+                if args.pretty {
+                    let pretty = serde_json::to_string_pretty(&value).unwrap_or_default();
+                    result["pretty"] = serde_json::Value::String(pretty);
                 }
-                let start = (op.start_line - 1).min(lines.len());
-                let end = op.end_line.min(lines.len());
+                result
+            }
+            Err(e) => serde_json::json!({
+                "valid": false,
+                "error": {
+                    "message": e.to_string(),
+                    "line": e.line(),
+                    "column": e.column(),
+                },
+            }),
+        };
+
+        let summary = if result["valid"] == true { "Validated JSON (valid)" } else { "Validated JSON (invalid)" };
+        let output = serde_json::to_string_pretty(&result).unwrap_or_default();
+
+        Ok(success_log("validate_json", "read", Some(&args.path.clone()), summary, vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 17. convert_file — normalize line endings / encoding
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Rewrite a text file with normalized line endings (lf/crlf) and/or re-encoded to UTF-8. Use source_encoding for legacy files (e.g. windows-1252). Set dry_run=true to preview. Requires Read/Write permission.")]
+    async fn convert_file(&self, params: Parameters<ConvertFileParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_writable(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
 
-                let new_lines: Vec<String> = op.content.lines().map(|l| l.to_string()).collect();
-                let removed = end - start;
-                lines.splice(start..end, new_lines.clone());
+        if args.line_ending.is_none() && args.encoding.is_none() {
+            return Err(ErrorData::invalid_params(
+                "Nothing to do. Provide line_ending and/or encoding.",
+                None,
+            ));
+        }
 
-                summary.push(format!(
-                    "Line op {}: replaced lines {}-{} ({} lines → {} lines)",
-                    i + 1, op.start_line, op.end_line, removed, new_lines.len()
+        let target_ending = match args.line_ending.as_deref() {
+            None => None,
+            Some(e) => Some(LineEnding::parse(e).ok_or_else(|| {
+                ErrorData::invalid_params(format!("Invalid line_ending '{}': use \"lf\" or \"crlf\"", e), None)
+            })?),
+        };
+        if let Some(enc) = args.encoding.as_deref() {
+            if !matches!(enc.to_lowercase().as_str(), "utf8" | "utf-8") {
+                return Err(ErrorData::invalid_params(
+                    format!("Unsupported target encoding '{}': only utf8 is supported", enc),
+                    None,
                 ));
             }
+        }
 
-            content = lines.join("\n");
-            // Preserve trailing newline if original had one
-            if !content.ends_with('\n') {
-                content.push('\n');
-            }
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("convert_file", &args.path, metadata.len(), config.max_file_size_mb, ""));
         }
 
-        // Write back
-        fs::write(&file_path, &content).map_err(|e| {
-            ErrorData::internal_error(format!("Failed to write patched file: {}", e), None)
+        let bytes = fs::read(&file_path).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to read file: {}", e), None)
         })?;
+        let (text, source_name) = decode_text(&bytes, args.source_encoding.as_deref())
+            .map_err(|e| ErrorData::invalid_params(e, None))?;
+
+        let mut changes = Vec::new();
+        if source_name != "UTF-8" {
+            changes.push(format!("encoding {} → UTF-8", source_name));
+        } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            changes.push("removed UTF-8 BOM".to_string());
+        }
 
-        let result = format!(
-            "Patched {}\n\n{}\n\nFinal size: {}",
-            args.path,
-            summary.join("\n"),
-            format_size(content.len() as u64),
-        );
-
-        Ok(success_log("patch_file", "write", Some(&args.path.clone()), "Patched file contents", vec![Content::text(result)]))
-    }
-}
+        let converted = match target_ending {
+            Some(ending) => {
+                let current = detect_line_ending(&text);
+                let normalized = normalize_line_endings(&text, ending);
+                if normalized != text {
+                    changes.push(format!("line endings {} → {}", current.label(), ending.label()));
+                }
+                normalized
+            }
+            None => text,
+        };
 
-// ─── Helper: list_directory recursive ───
+        if changes.is_empty() {
+            return Ok(success_log("convert_file", "read", Some(&args.path.clone()), "Checked file conversion (no changes)", vec![Content::text(
+                format!("{} already matches the requested format. Nothing to change.", args.path),
+            )]));
+        }
 
-impl OmniDriveServer {
-    async fn list_directory_recursive(
-        &self,
-        dir_path: &std::path::Path,
-        args: &ListDirectoryParams,
-        config: &AppConfig,
-    ) -> Result<CallToolResult, ErrorData> {
-        let max_depth = args.max_depth.clamp(1, 10);
-        let mut entries = Vec::new();
+        if args.dry_run {
+            return Ok(success_log("convert_file", "read", Some(&args.path.clone()), "Previewed file conversion", vec![Content::text(
+                format!("[dry run] Would convert {}: {}", args.path, changes.join(", ")),
+            )]));
+        }
 
-        let walker = walkdir::WalkDir::new(dir_path)
-            .max_depth(max_depth)
-            .sort_by_file_name()
-            .into_iter()
-            .filter_map(|e| e.ok());
+        fs::write(&file_path, converted.as_bytes()).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to write converted file: {}", e), None)
+        })?;
 
-        for entry in walker {
-            let path = entry.path();
-            let depth = entry.depth();
-            if depth == 0 { continue; } // Skip root
+        Ok(success_log("convert_file", "write", Some(&args.path.clone()), &format!("Converted file: {}", changes.join(", ")), vec![Content::text(
+            format!("Converted {}: {}", args.path, changes.join(", ")),
+        )]))
+    }
 
-            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            let is_dir = path.is_dir();
+    // ────────────────────────────────────────────────────────
+    // 18. trim_whitespace — strip trailing whitespace per line
+    // ────────────────────────────────────────────────────────
 
-            if !is_dir && !is_supported_extension(name) { continue; }
+    #[tool(description = "Remove trailing whitespace from every line of a text file, keeping its dominant line ending. Optionally ensure exactly one final newline. Set dry_run=true to preview. Requires Read/Write permission.")]
+    async fn trim_whitespace(&self, params: Parameters<TrimWhitespaceParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
 
-            // Check .mcpignore
-            let path_str = path.to_string_lossy().to_string();
-            if validate_path(&path_str, config).is_err() { continue; }
+        let validated = validate_writable(&args.path, &config)?;
+        let file_path = validated.canonical_path;
 
-            let size = if is_dir { 0 } else { entry.metadata().map(|m| m.len()).unwrap_or(0) };
-            let indent = "  ".repeat(depth - 1);
-            let type_marker = if is_dir { "📁" } else { "📄" };
-            let size_str = if is_dir { String::new() } else { format!(" ({})", format_size(size)) };
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
 
-            entries.push(format!("{}{} {}{}", indent, type_marker, name, size_str));
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("trim_whitespace", &args.path, metadata.len(), config.max_file_size_mb, ""));
         }
 
-        // Paginate the flat list of tree entries
-        let total_items = entries.len();
-        let page_size = args.page_size.clamp(1, 200);
-        let page = args.page.max(1);
-        let start_idx = (page - 1) * page_size;
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
 
-        if start_idx >= total_items && total_items > 0 {
-            return
+        let (trimmed, lines_changed) = trim_trailing_whitespace(&content, args.ensure_final_newline);
 
-        Ok(success_log("list_directory_recursive", "read", Some(&args.path.clone()), "Listed directory recursively", vec![Content::text(format!(
-                "Page {} is out of range. Total items: {} ({} pages)",
-                page, total_items, (total_items + page_size - 1) / page_size
-            ))]));
+        if trimmed == content {
+            return Ok(success_log("trim_whitespace", "read", Some(&args.path.clone()), "Checked trailing whitespace (clean)", vec![Content::text(
+                format!("{} has no trailing whitespace to remove.", args.path),
+            )]));
         }
 
-        let paged: Vec<&String> = entries.iter().skip(start_idx).take(page_size).collect();
-
-        let mut output = String::new();
-        output.push_str(&format!("Tree: {} (depth: {}, page {}/{})\n",
-            args.path, max_depth, page, (total_items + page_size - 1) / page_size.max(1)));
-        output.push_str(&format!("{} items total\n\n", total_items));
-        for line in paged {
-            output.push_str(line);
-            output.push('\n');
+        if args.dry_run {
+            return Ok(success_log("trim_whitespace", "read", Some(&args.path.clone()), "Previewed whitespace trim", vec![Content::text(
+                format!("[dry run] Would trim trailing whitespace on {} line(s) in {}", lines_changed, args.path),
+            )]));
         }
 
-        Ok(success_log("list_directory_recursive", "read", Some(&args.path.clone()), "Listed directory recursively", vec![Content::text(output)]))
+        fs::write(&file_path, &trimmed).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to write file: {}", e), None)
+        })?;
+
+        Ok(success_log("trim_whitespace", "write", Some(&args.path.clone()), &format!("Trimmed whitespace on {} line(s)", lines_changed), vec![Content::text(
+            format!("Trimmed trailing whitespace on {} line(s) in {}", lines_changed, args.path),
+        )]))
     }
-}
 
-// ─── Helpers ───
+    // ────────────────────────────────────────────────────────
+    // 19. create_symlink — symbolic link within the sandbox
+    // ────────────────────────────────────────────────────────
 
-fn format_size(bytes: u64) -> String {
-    if bytes < 1024 { format!("{} B", bytes) }
-    else if bytes < 1024 * 1024 { format!("{:.1} KB", bytes as f64 / 1024.0) }
-    else if bytes < 1024 * 1024 * 1024 { format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)) }
+    #[tool(description = "Create a symbolic link at link_path pointing to target. The link must be in a writable shared folder and the target must be inside a shared folder. Fails if link_path already exists.")]
+    async fn create_symlink(&self, params: Parameters<CreateSymlinkParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let link_validated = validate_writable(&args.link_path, &config)?;
+        // The target must stay inside the sandbox, otherwise the link becomes an escape hatch
+        let target_validated = validate_path(&args.target, &config)?;
+
+        let link = link_validated.canonical_path;
+        let target = target_validated.canonical_path;
+
+        if fs::symlink_metadata(&link).is_ok() {
+            return Err(ErrorData::invalid_params(
+                format!("link_path already exists: {}. Delete it first or choose a different name.", args.link_path),
+                None,
+            ));
+        }
+        if !target.exists() {
+            return Err(ErrorData::invalid_params(format!("Target not found: {}", args.target), None));
+        }
+
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                ErrorData::internal_error(format!("Failed to create parent dirs: {}", e), None)
+            })?;
+        }
+
+        create_symlink_at(&target, &link).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to create symlink: {}", e), None)
+        })?;
+
+        Ok(success_log("create_symlink", "write", Some(&args.link_path.clone()), &format!("Linked to {}", args.target), vec![Content::text(
+            format!("Created symlink {} → {}", args.link_path, args.target),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 20. set_permissions — chmod (Unix only)
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Set Unix permission bits on a file or directory, e.g. mode=\"755\" to make a script executable. Returns the old and new modes. Requires Read/Write permission. Unix only.")]
+    async fn set_permissions(&self, params: Parameters<SetPermissionsParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_destructive(&args.path, &config)?;
+        let target = validated.canonical_path;
+
+        let mode = parse_octal_mode(&args.mode).map_err(|e| ErrorData::invalid_params(e, None))?;
+        let (old_mode, new_mode) = apply_mode(&target, mode).map_err(|e| ErrorData::internal_error(e, None))?;
+
+        Ok(success_log("set_permissions", "write", Some(&args.path.clone()), &format!("Set mode {:04o}", new_mode), vec![Content::text(
+            format!("Changed mode of {}: {} → {}", args.path, format_mode(old_mode), format_mode(new_mode)),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 21. read_env — parse a .env file into key/value pairs
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Parse a .env file into a JSON object of KEY → value, handling quotes, comments and `export` prefixes. Values are redacted (null) unless include_values=true.")]
+    async fn read_env(&self, params: Parameters<ReadEnvParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
+
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("read_env", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
+
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
+
+        let vars = parse_env(&content);
+        let mut object = serde_json::Map::new();
+        for (key, value) in &vars {
+            let value = if args.include_values {
+                serde_json::Value::String(value.clone())
+            } else {
+                serde_json::Value::Null
+            };
+            object.insert(key.clone(), value);
+        }
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "values_redacted": !args.include_values,
+            "vars": object,
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("read_env", "read", Some(&args.path.clone()), &format!("Read {} env var(s)", vars.len()), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 22. move_into — move into a directory, keeping the name
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Move a file or directory into dest_dir, keeping its name (like `mv file dir/`). Creates dest_dir if needed. Refuses to replace an existing file of the same name unless overwrite=true. Requires Read/Write permission.")]
+    async fn move_into(&self, params: Parameters<MoveIntoParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let src = validate_destructive(&args.source, &config)?.canonical_path;
+        let dest_dir = validate_writable(&args.dest_dir, &config)?.canonical_path;
+
+        let name = src.file_name().ok_or_else(|| {
+            ErrorData::invalid_params(format!("Source has no file name: {}", args.source), None)
+        })?;
+        if dest_dir.starts_with(&src) {
+            return Err(ErrorData::invalid_params(
+                format!("Cannot move {} into itself", args.source),
+                None,
+            ));
+        }
+        if dest_dir.exists() && !dest_dir.is_dir() {
+            return Err(ErrorData::invalid_params(
+                format!("Destination is not a directory: {}", args.dest_dir),
+                None,
+            ));
+        }
+
+        let dst_str = dest_dir.join(name).to_string_lossy().to_string();
+        let dst = validate_writable(&dst_str, &config)?.canonical_path;
+
+        if dst == src {
+            return Err(ErrorData::invalid_params(
+                format!("{} is already in {}", args.source, args.dest_dir),
+                None,
+            ));
+        }
+        if dst.exists() {
+            if !args.overwrite {
+                return Err(ErrorData::invalid_params(
+                    format!("Destination already exists: {}. Set overwrite=true to replace it.", dst_str),
+                    None,
+                ));
+            }
+            if dst.is_dir() {
+                return Err(ErrorData::invalid_params(
+                    format!("Destination is an existing directory and cannot be overwritten: {}", dst_str),
+                    None,
+                ));
+            }
+            if src.is_dir() {
+                return Err(ErrorData::invalid_params(
+                    format!("Cannot replace the file {} with a directory", dst_str),
+                    None,
+                ));
+            }
+            // The move itself replaces the file, so it survives if the move fails
+            let policy = crate::confirm::Policy::from(&*config);
+            crate::confirm::confirm_destructive(policy, "move_into", &dst_str, "overwrite").await?;
+        }
+
+        fs::create_dir_all(&dest_dir).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to create destination directory: {}", e), None)
+        })?;
+
+        let note = if move_path(&src, &dst)? { " (cross-device)" } else { "" };
+
+        Ok(success_log("move_into", "delete", Some(&args.source.clone()), &format!("Moved into {}", args.dest_dir), vec![Content::text(
+            format!("Moved {} → {}{}", args.source, dst_str, note),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 23. copy_into — copy into a directory, keeping the name
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Copy a file (or a directory with recursive=true) into dest_dir, keeping its name (like `cp -r src dir/`). Creates dest_dir if needed. Refuses to replace an existing file unless overwrite=true. Reports bytes copied. Destination must be writable.")]
+    async fn copy_into(&self, params: Parameters<CopyIntoParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = if args.recursive { Some(self.acquire_heavy_slot().await?) } else { None };
+        let config = self.config_snapshot().await;
+
+        let src = validate_path(&args.source, &config)?.canonical_path;
+        let dest_dir = validate_writable(&args.dest_dir, &config)?.canonical_path;
+
+        if !src.exists() {
+            return Err(ErrorData::resource_not_found(format!("Source not found: {}", args.source), None));
+        }
+        let name = src.file_name().ok_or_else(|| {
+            ErrorData::invalid_params(format!("Source has no file name: {}", args.source), None)
+        })?;
+        if dest_dir.exists() && !dest_dir.is_dir() {
+            return Err(ErrorData::invalid_params(
+                format!("Destination is not a directory: {}", args.dest_dir),
+                None,
+            ));
+        }
+
+        let dst_str = dest_dir.join(name).to_string_lossy().to_string();
+        let dst = validate_writable(&dst_str, &config)?.canonical_path;
+
+        if src.is_dir() {
+            if !args.recursive {
+                return Err(ErrorData::invalid_params(
+                    format!("{} is a directory. Set recursive=true to copy it.", args.source),
+                    None,
+                ));
+            }
+            if dest_dir.starts_with(&src) {
+                return Err(ErrorData::invalid_params(
+                    format!("Cannot copy {} into itself", args.source),
+                    None,
+                ));
+            }
+            if dst.exists() {
+                return Err(ErrorData::invalid_params(
+                    format!("Destination already exists: {}. Directories are never merged.", dst_str),
+                    None,
+                ));
+            }
+
+            let (from, to, copy_config) = (src.clone(), dst.clone(), config.clone());
+            let (files, bytes) = blocking_io(move || copy_dir_recursive(&from, &to, &copy_config)).await?;
+
+            return Ok(success_log_bytes("copy_into", "write", Some(&dst_str), &format!("Copied from {}", args.source), bytes, vec![Content::text(
+                format!("Copied {} → {} ({} file(s), {})", args.source, dst_str, files, format_size(bytes)),
+            )]));
+        }
+
+        if dst == src {
+            return Err(ErrorData::invalid_params(
+                format!("{} is already in {}", args.source, args.dest_dir),
+                None,
+            ));
+        }
+        if dst.exists() && (!args.overwrite || dst.is_dir()) {
+            return Err(ErrorData::invalid_params(
+                format!("Destination already exists: {}. Set overwrite=true to replace a file.", dst_str),
+                None,
+            ));
+        }
+
+        let (from, to) = (src.clone(), dst.clone());
+        let bytes_copied = blocking_io(move || copy_one_file(&from, &to)).await?;
+
+        Ok(success_log_bytes("copy_into", "write", Some(&dst_str), &format!("Copied from {}", args.source), bytes_copied, vec![Content::text(
+            format!("Copied {} → {} ({})", args.source, dst_str, format_size(bytes_copied)),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 24. recent_activity — this agent's tail of the activity log
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "List your own most recent OmniDrive actions (newest first) from the activity log, as JSON. Only entries recorded for the current agent are returned.")]
+    async fn recent_activity(&self, params: Parameters<RecentActivityParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let limit = args.limit.unwrap_or(20).clamp(1, 100);
+        let config = self.config_snapshot().await;
+
+        // Read before logging, so this call never shows up in its own result
+        let entries = crate::activity::recent_entries(&crate::activity::scope_identity(), limit, |path| {
+            validate_path(&crate::activity::expand_home(path), &config).is_ok()
+        });
+        let output = serde_json::to_string_pretty(&entries).unwrap_or_default();
+
+        Ok(success_log("recent_activity", "read", None, &format!("Reviewed {} recent action(s)", entries.len()), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 25. grep_binary — find a byte sequence in files
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Search files for a hex-encoded byte sequence (e.g. pattern=\"89504e47\"). Give either path (one file) or root_path (a directory, searched recursively). Returns each match's file offset with a hex context window; the matched bytes are shown in [brackets].")]
+    async fn grep_binary(&self, params: Parameters<GrepBinaryParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let needle = parse_hex_pattern(&args.pattern).map_err(|e| ErrorData::invalid_params(e, None))?;
+        let max_results = search_result_cap(args.max_results, 50, &*self.config_snapshot().await);
+        let context = args.context_bytes.unwrap_or(8).min(64);
+        let max_bytes = |config: &AppConfig| (config.max_file_size_mb as u64) * 1024 * 1024;
+        let _permit = if args.root_path.is_some() { Some(self.acquire_heavy_slot().await?) } else { None };
+        let config = self.config_snapshot().await;
+
+        let (target, start, walk) = match (&args.path, &args.root_path) {
+            (Some(path), None) => {
+                let file = validate_path(path, &config)?.canonical_path;
+                if !file.is_file() {
+                    return Err(ErrorData::invalid_params(format!("File not found: {}", path), None));
+                }
+                let size = fs::metadata(&file).map_err(|e| ErrorData::internal_error(e.to_string(), None))?.len();
+                if size > max_bytes(&config) {
+                    return Ok(file_too_large("grep_binary", path, size, config.max_file_size_mb, ""));
+                }
+                (path.clone(), file, false)
+            }
+            (None, Some(root_path)) => {
+                let root = validate_path(root_path, &config)?.canonical_path;
+                if !root.is_dir() {
+                    return Err(ErrorData::invalid_params(
+                        format!("root_path must be a directory: {}", root_path), None,
+                    ));
+                }
+                (root_path.clone(), root, true)
+            }
+            _ => {
+                return Err(ErrorData::invalid_params("Provide exactly one of path or root_path", None));
+            }
+        };
+
+        // Walking and reading whole files is synchronous; keep it off the async workers
+        let walk_config = config.clone();
+        let results = blocking_io(move || {
+            let config = &*walk_config;
+            let files: Vec<std::path::PathBuf> = if walk {
+                walkdir::WalkDir::new(&start)
+                    .max_depth(GREP_DEFAULT_DEPTH)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .filter_entry(|e| {
+                        e.depth() == 0
+                            || (!is_default_ignored_entry(e, config) && validate_path(&e.path().to_string_lossy(), config).is_ok())
+                    })
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .filter(|e| e.metadata().map(|m| m.len() <= max_bytes(config)).unwrap_or(false))
+                    .map(|e| e.into_path())
+                    .collect()
+            } else {
+                vec![start]
+            };
+
+            let mut results = Vec::new();
+            'files: for file in &files {
+                let Ok(data) = fs::read(file) else { continue };
+                for offset in find_all(&data, &needle) {
+                    results.push(format!(
+                        "{}@0x{:08x}: {}",
+                        display_path(file, None),
+                        offset,
+                        hex_context(&data, offset, needle.len(), context)
+                    ));
+                    if results.len() >= max_results {
+                        break 'files;
+                    }
+                }
+            }
+            Ok(results)
+        })
+        .await?;
+
+        let output = if results.is_empty() {
+            format!("No matches for bytes {} in {}", args.pattern, target)
+        } else {
+            format!("Found {} match(es) for bytes {}:\n\n{}", results.len(), args.pattern, results.join("\n"))
+        };
+
+        Ok(success_log("grep_binary", "read", Some(&target), &format!("Searched for bytes {}", args.pattern), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 26. list_tree — nested JSON directory tree
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Describe a directory as nested JSON: { name, type, size, children: [...] } down to max_depth. Directories at the depth limit have no children key. Total nodes are capped by the server config (see truncated in the result).")]
+    async fn list_tree(&self, params: Parameters<ListTreeParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let dir_path = validated.canonical_path;
+
+        if !dir_path.is_dir() {
+            return Err(ErrorData::invalid_params(format!("Path is not a directory: {}", args.path), None));
+        }
+
+        let _permit = self.acquire_heavy_slot().await?;
+        let mut walk = TreeWalk {
+            max_depth: args.max_depth.clamp(1, 10),
+            skip_hidden: args.skip_hidden,
+            nodes_left: config.max_tree_entries.max(1),
+            truncated: false,
+        };
+        let (root, walk_config) = (dir_path.clone(), config.clone());
+        let (children, walk) = blocking_io(move || {
+            let children = walk.children(&root, 1, &walk_config);
+            Ok((children, walk))
+        })
+        .await?;
+        let nodes = config.max_tree_entries.max(1) - walk.nodes_left;
+
+        let name = dir_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| args.path.clone());
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "max_depth": walk.max_depth,
+            "nodes": nodes,
+            "truncated": walk.truncated,
+            "tree": { "name": name, "type": "directory", "size": 0, "children": children },
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("list_tree", "read", Some(&args.path.clone()), &format!("Listed tree ({} nodes)", nodes), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 27. concat_files — join several files into one
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Concatenate files, in the given order, into output_path, optionally inserting a separator between them. Sources are streamed rather than loaded into memory. Requires Read/Write permission on the output folder.")]
+    async fn concat_files(&self, params: Parameters<ConcatFilesParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        if args.paths.is_empty() {
+            return Err(ErrorData::invalid_params("No source paths provided", None));
+        }
+
+        let output = validate_writable(&args.output_path, &config)?.canonical_path;
+
+        let mut sources = Vec::with_capacity(args.paths.len());
+        for path in &args.paths {
+            let src = validate_path(path, &config)?.canonical_path;
+            if !src.is_file() {
+                return Err(ErrorData::invalid_params(format!("File not found: {}", path), None));
+            }
+            if src == output {
+                return Err(ErrorData::invalid_params(
+                    format!("output_path {} is also one of the sources", args.output_path),
+                    None,
+                ));
+            }
+            sources.push(src);
+        }
+
+        if output.exists() && (!args.overwrite || output.is_dir()) {
+            return Err(ErrorData::invalid_params(
+                format!("Destination already exists: {}. Set overwrite=true to replace a file.", args.output_path),
+                None,
+            ));
+        }
+
+        let _permit = self.acquire_heavy_slot().await?;
+        let created = !output.exists();
+        let separator = args.separator.unwrap_or_default();
+        let (count, target) = (sources.len(), output.clone());
+        let bytes = blocking_io(move || {
+            concat_into(&sources, &target, separator.as_bytes()).map_err(|e| {
+                ErrorData::internal_error(format!("Failed to concatenate files: {}", e), None)
+            })
+        })
+        .await?;
+        if created {
+            apply_created_file_mode(&output, &config)?;
+        }
+
+        Ok(success_log_bytes("concat_files", "write", Some(&args.output_path.clone()), &format!("Concatenated {} file(s)", count), bytes, vec![Content::text(
+            format!("Concatenated {} file(s) into {} ({})", count, args.output_path, format_size(bytes)),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 28. path_exists — cheap existence and writability check
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Check whether a path exists, what it is, and whether you may write to it (based on the shared folder's permission and ignore rules), as JSON. Use before writing to avoid failed writes in read-only folders.")]
+    async fn path_exists(&self, params: Parameters<PathExistsParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let target = validated.canonical_path;
+
+        let kind = match fs::metadata(&target) {
+            Ok(meta) if meta.is_dir() => Some("directory"),
+            Ok(_) => Some("file"),
+            Err(_) => None,
+        };
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "path": args.path,
+            "exists": kind.is_some(),
+            "type": kind,
+            "writable": validate_writable(&args.path, &config).is_ok(),
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("path_exists", "read", Some(&args.path.clone()), "Checked path", vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 29. patch_files — coordinated edits across several files
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Apply patch_file-style edits to several files in one call. Every patch is computed first and nothing is written unless all succeed; if a write fails, files already written are restored. Requires Read/Write permission.")]
+    async fn patch_files(&self, params: Parameters<PatchFilesParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        if args.files.is_empty() {
+            return Err(ErrorData::invalid_params("No files provided", None));
+        }
+
+        // Compute every patch before touching disk
+        let mut plan: Vec<PlannedWrite> = Vec::with_capacity(args.files.len());
+        let mut reports = Vec::with_capacity(args.files.len());
+        for file in &args.files {
+            let in_file = |e: ErrorData| ErrorData::invalid_params(format!("{}: {}", file.path, e.message), None);
+
+            let file_path = validate_writable(&file.path, &config).map_err(|e| in_file(e.into()))?.canonical_path;
+            if !file_path.is_file() {
+                return Err(ErrorData::invalid_params(format!("File not found: {}", file.path), None));
+            }
+            if plan.iter().any(|p| p.path == file_path) {
+                return Err(ErrorData::invalid_params(
+                    format!("{} is listed more than once; combine its operations into one entry", file.path),
+                    None,
+                ));
+            }
+            if file.search_replace.is_empty() && file.line_replace.is_empty() {
+                return Err(ErrorData::invalid_params(format!("{}: no operations provided", file.path), None));
+            }
+            check_expected_sha256(&file_path, file.expected_sha256.as_deref(), &file.path)?;
+
+            let original = fs::read_to_string(&file_path).map_err(|e| {
+                ErrorData::invalid_params(format!("Cannot read file {}: {}", file.path, e), None)
+            })?;
+            let (patched, summary) = apply_patch_ops(original.clone(), &file.search_replace, &file.line_replace)
+                .map_err(in_file)?;
+
+            reports.push(format!("Patched {} ({})\n  {}", file.path, format_size(patched.len() as u64), summary.join("\n  ")));
+            plan.push(PlannedWrite { path: file_path, original, patched });
+        }
+
+        write_all_or_rollback(&plan).map_err(|(i, e)| {
+            ErrorData::internal_error(
+                format!(
+                    "Failed to write {}: {}. {} file(s) already written were restored; no changes were kept.",
+                    args.files[i].path, e, i
+                ),
+                None,
+            )
+        })?;
+
+        let bytes = plan.iter().map(|p| p.patched.len() as u64).sum();
+        Ok(success_log_bytes("patch_files", "write", Some(&format!("{} files", plan.len())), &format!("Patched {} file(s)", plan.len()), bytes, vec![Content::text(
+            reports.join("\n\n"),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 30. disk_usage — space left on the disk holding a path
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Report total, used and available space on the disk containing a path inside a shared folder, as JSON with bytes and human-readable sizes. Check before writing large files or unzipping.")]
+    async fn disk_usage(&self, params: Parameters<DiskUsageParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        // The path itself may not exist yet (e.g. a planned output); measure its folder
+        let target = fs::canonicalize(&validated.canonical_path)
+            .or_else(|_| fs::canonicalize(&validated.folder.path))
+            .map_err(|e| ErrorData::internal_error(format!("Failed to resolve path: {}", e), None))?;
+
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        let disk = disks
+            .list()
+            .iter()
+            .filter(|d| target.starts_with(d.mount_point()))
+            .max_by_key(|d| d.mount_point().as_os_str().len())
+            .ok_or_else(|| ErrorData::internal_error(format!("No disk found for {}", args.path), None))?;
+
+        let total = disk.total_space();
+        let available = disk.available_space();
+        let used = total.saturating_sub(available);
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "path": args.path,
+            "mount_point": crate::activity::redact_home(&disk.mount_point().to_string_lossy()),
+            "total_bytes": total,
+            "used_bytes": used,
+            "available_bytes": available,
+            "total": format_size(total),
+            "used": format_size(used),
+            "available": format_size(available),
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("disk_usage", "read", Some(&args.path.clone()), &format!("Checked disk space ({} available)", format_size(available)), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 31. generate_manifest — size and SHA-256 of every file in a tree
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Walk a directory and build a JSON manifest mapping each relative file path to its size and SHA-256, for integrity checks or diffing snapshots. Optionally writes it to output_path. Ignored files are skipped and the file count is capped by the server config.")]
+    async fn generate_manifest(&self, params: Parameters<GenerateManifestParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
+
+        let root = validate_path(&args.root_path, &config)?.canonical_path;
+        if !root.is_dir() {
+            return Err(ErrorData::invalid_params(format!("root_path must be a directory: {}", args.root_path), None));
+        }
+        let output = match &args.output_path {
+            Some(path) => Some(validate_writable(path, &config)?.canonical_path),
+            None => None,
+        };
+
+        let (walk_config, walk_root, skip) = (config.clone(), root.clone(), output.clone());
+        let (files, truncated) = blocking_io(move || build_manifest(&walk_root, &walk_config, skip.as_deref())).await?;
+
+        let manifest = Manifest {
+            version: 1,
+            generated: chrono::Utc::now().to_rfc3339(),
+            truncated,
+            files,
+        };
+        let json = serde_json::to_string_pretty(&manifest).unwrap_or_default();
+
+        if let (Some(path), Some(display)) = (&output, &args.output_path) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| ErrorData::internal_error(format!("Failed to create output directory: {}", e), None))?;
+            }
+            with_retry(|| fs::write(path, &json))
+                .map_err(|e| ErrorData::internal_error(format!("Failed to write manifest: {}", e), None))?;
+            let summary = format!(
+                "Wrote manifest of {} file(s) to {}{}",
+                manifest.files.len(),
+                display,
+                if truncated { format!(" (truncated at {} files)", config.max_tree_entries.max(1)) } else { String::new() }
+            );
+            return Ok(success_log("generate_manifest", "write", Some(&args.root_path.clone()), &summary, vec![Content::text(summary.clone())]));
+        }
+
+        Ok(success_log("generate_manifest", "read", Some(&args.root_path.clone()), &format!("Built manifest of {} file(s)", manifest.files.len()), vec![Content::text(json)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 32. verify_manifest — check a tree against a manifest
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Check a directory against a manifest from generate_manifest (given as manifest_path or inline manifest JSON). Reports files that changed, went missing, or were added.")]
+    async fn verify_manifest(&self, params: Parameters<VerifyManifestParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
+
+        let root = validate_path(&args.root_path, &config)?.canonical_path;
+        if !root.is_dir() {
+            return Err(ErrorData::invalid_params(format!("root_path must be a directory: {}", args.root_path), None));
+        }
+
+        let (expected, manifest_file) = match (&args.manifest_path, &args.manifest) {
+            (Some(path), None) => {
+                let (manifest, file) = read_manifest(path, &config)?;
+                (manifest, Some(file))
+            }
+            (None, Some(inline)) => {
+                let manifest = serde_json::from_str(inline)
+                    .map_err(|e| ErrorData::invalid_params(format!("Invalid manifest: {}", e), None))?;
+                (manifest, None)
+            }
+            _ => return Err(ErrorData::invalid_params("Provide exactly one of manifest_path or manifest", None)),
+        };
+
+        let (walk_config, walk_root) = (config.clone(), root.clone());
+        let (actual, _) = blocking_io(move || build_manifest(&walk_root, &walk_config, manifest_file.as_deref())).await?;
+
+        let mut changed = Vec::new();
+        let mut missing = Vec::new();
+        for (path, entry) in &expected.files {
+            match actual.get(path) {
+                None => missing.push(path.clone()),
+                Some(found) if found != entry => changed.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        // A truncated manifest can't say which extra files are new
+        let added: Vec<String> = if expected.truncated {
+            Vec::new()
+        } else {
+            actual.keys().filter(|p| !expected.files.contains_key(*p)).cloned().collect()
+        };
+
+        let intact = changed.is_empty() && missing.is_empty() && added.is_empty();
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "intact": intact,
+            "checked": expected.files.len(),
+            "changed": changed,
+            "missing": missing,
+            "added": added,
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("verify_manifest", "read", Some(&args.root_path.clone()), if intact { "Manifest verified" } else { "Manifest mismatch" }, vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 33. recent_files — files this agent recently read or wrote
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "List the distinct files you most recently read or wrote through OmniDrive (newest first), with the time and kind of your last access, as JSON. Useful for resuming earlier work.")]
+    async fn recent_files(&self, params: Parameters<RecentFilesParams>) -> Result<CallToolResult, ErrorData> {
+        let limit = params.0.limit.unwrap_or(20).clamp(1, 100);
+        let config = self.config_snapshot().await;
+        let files = crate::activity::recent_files(&crate::activity::scope_identity(), limit, |path| {
+            validate_path(&crate::activity::expand_home(path), &config).is_ok()
+        });
+        let output = serde_json::to_string_pretty(&files).unwrap_or_default();
+
+        Ok(success_log("recent_files", "read", None, &format!("Listed {} recent file(s)", files.len()), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 34. list_supported_types — which files read_file can serve
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "List the file types OmniDrive can read, as JSON: extensions grouped by category (code, text, data, document, image), recognized extensionless names, extensions added in settings, and the current file size limit. Check this before reading unfamiliar file types.")]
+    async fn list_supported_types(&self, _params: Parameters<ListSupportedTypesParams>) -> Result<CallToolResult, ErrorData> {
+        let config = self.config_snapshot().await;
+
+        let categories: serde_json::Map<String, serde_json::Value> = crate::extensions::CATEGORIES
+            .iter()
+            .map(|(name, extensions)| (name.to_string(), serde_json::json!(extensions)))
+            .collect();
+        let output = serde_json::to_string_pretty(&serde_json::json!({
+            "categories": categories,
+            "extensionless": crate::extensions::EXTENSIONLESS_SUPPORTED,
+            "extra_extensions": config.extra_extensions,
+            "max_file_size_mb": config.max_file_size_mb,
+        }))
+        .unwrap_or_default();
+
+        Ok(success_log("list_supported_types", "read", None, "Listed supported file types", vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 35. query_json — one value out of a JSON file
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Read a single value from a JSON file by RFC 6901 JSON Pointer (e.g. \"/servers/0/port\"; escape \"/\" as \"~1\" and \"~\" as \"~0\"). Returns { pointer, type, value }. Large objects and arrays come back as { type, truncated: true, keys or length, preview } instead; query a deeper pointer to see more.")]
+    async fn query_json(&self, params: Parameters<QueryJsonParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_path(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
+
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("query_json", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
+
+        if !args.pointer.is_empty() && !args.pointer.starts_with('/') {
+            return Err(ErrorData::invalid_params(
+                format!("Invalid JSON Pointer {:?}: it must be empty or start with \"/\"", args.pointer),
+                None,
+            ));
+        }
+
+        let document = parse_json_file(&file_path)?;
+        let value = document.pointer(&args.pointer).ok_or_else(|| {
+            ErrorData::invalid_params(
+                format!(
+                    "No value at {:?} in {}. The deepest existing part is {:?}.",
+                    args.pointer,
+                    args.path,
+                    deepest_pointer(&document, &args.pointer)
+                ),
+                None,
+            )
+        })?;
+
+        let max_chars = args.max_chars.unwrap_or(4000).clamp(100, 100_000);
+        let output = serde_json::to_string_pretty(&describe_json(&args.pointer, value, max_chars)).unwrap_or_default();
+
+        Ok(success_log("query_json", "read", Some(&args.path.clone()), &format!("Queried JSON at {:?}", args.pointer), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 36. patch_json — structured edit via JSON merge patch
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Edit a JSON file by applying an RFC 7386 merge patch object: keys in the patch are added or updated, keys set to null are removed, nested objects are merged. Key order and indentation are kept. Set dry_run=true to preview the changes. Requires Read/Write permission.")]
+    async fn patch_json(&self, params: Parameters<PatchJsonParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_writable(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
+
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("patch_json", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
+
+        if !args.merge_patch.is_object() {
+            return Err(ErrorData::invalid_params(
+                "merge_patch must be a JSON object (to replace the whole document, use write_file)",
+                None,
+            ));
+        }
+
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
+        let mut document = parse_json(&content)?;
+
+        let mut changes = Vec::new();
+        apply_merge_patch(&mut document, &args.merge_patch, "", &mut changes);
+
+        if changes.is_empty() {
+            return Ok(success_log("patch_json", "read", Some(&args.path.clone()), "Checked JSON patch (no changes)", vec![Content::text(
+                format!("The patch leaves {} unchanged.", args.path),
+            )]));
+        }
+
+        let patched = serialize_like(&document, &content);
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(&patched) {
+            return Err(ErrorData::internal_error(format!("Patched document is not valid JSON, nothing was written: {}", e), None));
+        }
+
+        let listing = changes.join("\n");
+        if args.dry_run {
+            return Ok(success_log("patch_json", "read", Some(&args.path.clone()), "Previewed JSON patch", vec![Content::text(
+                format!("[dry run] Would make {} change(s) to {}:\n{}\n\nResult:\n{}", changes.len(), args.path, listing, patched),
+            )]));
+        }
+
+        fs::write(&file_path, &patched).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to write file: {}", e), None)
+        })?;
+
+        Ok(success_log_bytes("patch_json", "write", Some(&args.path.clone()), &format!("Patched JSON ({} change(s))", changes.len()), patched.len() as u64, vec![Content::text(
+            format!("Made {} change(s) to {}:\n{}", changes.len(), args.path, listing),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 37. format_file — canonical formatting by file type
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Rewrite a file in its canonical format, chosen by extension. Supported: JSON (.json) is pretty-printed with 2-space indentation, key order kept, and a final newline. Formatting an already formatted file changes nothing. Set dry_run=true to preview. Requires Read/Write permission.")]
+    async fn format_file(&self, params: Parameters<FormatFileParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let validated = validate_writable(&args.path, &config)?;
+        let file_path = validated.canonical_path;
+
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("File not found: {}", args.path), None));
+        }
+
+        let extension = file_path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        let formatter = FORMATTERS.iter().find(|f| f.extensions.contains(&extension.as_str())).ok_or_else(|| {
+            let supported: Vec<String> = FORMATTERS.iter().flat_map(|f| f.extensions.iter().map(|e| format!(".{}", e))).collect();
+            ErrorData::invalid_params(
+                format!("No formatter for {}. Supported: {}", args.path, supported.join(", ")),
+                None,
+            )
+        })?;
+
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("format_file", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
+
+        let content = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
+        let formatted = (formatter.format)(&content)?;
+
+        if formatted == content {
+            return Ok(success_log("format_file", "read", Some(&args.path.clone()), "Checked formatting (clean)", vec![Content::text(
+                format!("{} is already formatted as {}.", args.path, formatter.name),
+            )]));
+        }
+
+        let summary = format!("{} lines → {} lines", content.lines().count(), formatted.lines().count());
+        if args.dry_run {
+            return Ok(success_log("format_file", "read", Some(&args.path.clone()), "Previewed formatting", vec![Content::text(
+                format!("[dry run] Would reformat {} as {} ({}):\n\n{}", args.path, formatter.name, summary, formatted),
+            )]));
+        }
+
+        fs::write(&file_path, &formatted).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to write file: {}", e), None)
+        })?;
+
+        Ok(success_log_bytes("format_file", "write", Some(&args.path.clone()), &format!("Formatted as {}", formatter.name), formatted.len() as u64, vec![Content::text(
+            format!("Reformatted {} as {} ({})", args.path, formatter.name, summary),
+        )]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 38. grep_and_read — first match per file, with context
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Search file contents for a pattern and show, for the first match in each file, the surrounding lines with line numbers (the matching line is marked with >). Combines grep_content and read_lines in one call. Use context_lines to widen the window and max_files to limit how many files are shown.")]
+    async fn grep_and_read(&self, params: Parameters<GrepAndReadParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
+
+        let root = validate_path(&args.root_path, &config)?.canonical_path;
+        if !root.is_dir() {
+            return Err(ErrorData::invalid_params(
+                format!("root_path must be a directory: {}", args.root_path), None,
+            ));
+        }
+
+        let re = build_matcher(&args.pattern, args.is_regex, args.case_insensitive)?;
+        let relative_root = resolve_relative_root(args.relative_to.as_deref(), &config)?;
+        let max_files = search_result_cap(args.max_files, 10, &config);
+        let context = args.context_lines.unwrap_or(3).min(50);
+        let filter = GrepFilter {
+            max_depth: GREP_DEFAULT_DEPTH,
+            skip_hidden: true,
+            force_text: false,
+            include_extensions: args.include_extensions.clone(),
+        };
+
+        let walk_config = config.clone();
+        let (sections, more) = blocking_io(move || {
+            let mut sections = Vec::new();
+            let mut more = false;
+            for path in grep_candidates(&root, &filter, &walk_config) {
+                let Ok(content) = fs::read_to_string(&path) else { continue };
+                let lines: Vec<&str> = content.lines().collect();
+                let Some(hit) = lines.iter().position(|l| re.is_match(l)) else { continue };
+                if sections.len() == max_files {
+                    more = true;
+                    break;
+                }
+
+                let (start, end) = (hit.saturating_sub(context), (hit + context + 1).min(lines.len()));
+                let mut section = format!("{} (line {} of {}):\n", display_path(&path, relative_root.as_ref()), hit + 1, lines.len());
+                for (i, line) in lines[start..end].iter().enumerate() {
+                    let marker = if start + i == hit { '>' } else { ' ' };
+                    section.push_str(&format!("{}{:>5} | {}\n", marker, start + i + 1, line));
+                }
+                sections.push(section);
+            }
+            Ok((sections, more))
+        })
+        .await?;
+
+        if sections.is_empty() {
+            return Ok(success_log("grep_and_read", "read", Some(&args.root_path.clone()), &format!("Grepped for {}", args.pattern), vec![Content::text(
+                format!("No matches found for '{}' in {}", args.pattern, args.root_path),
+            )]));
+        }
+
+        let mut output = format!("First match in {} file(s) for '{}':\n\n{}", sections.len(), args.pattern, sections.join("\n"));
+        if more {
+            output.push_str(&format!("\n... more files match; raise max_files (now {}) or narrow root_path to see them", max_files));
+        }
+
+        Ok(success_log("grep_and_read", "read", Some(&args.root_path.clone()), &format!("Grepped and read {} file(s) for {}", sections.len(), args.pattern), vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 39. diff_manifests — what changed between two snapshots
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Compare two manifests from generate_manifest (old_manifest_path and new_manifest_path), or a manifest against a directory as it is now (old_manifest_path and root_path). Returns JSON with added, removed and modified files (modified means the SHA-256 differs), plus the count of unchanged files.")]
+    async fn diff_manifests(&self, params: Parameters<DiffManifestsParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let (old, old_file) = read_manifest(&args.old_manifest_path, &config)?;
+        let (new, new_label) = match (&args.new_manifest_path, &args.root_path) {
+            (Some(path), None) => (read_manifest(path, &config)?.0, path.clone()),
+            (None, Some(root_path)) => {
+                let _permit = self.acquire_heavy_slot().await?;
+                let root = validate_path(root_path, &config)?.canonical_path;
+                if !root.is_dir() {
+                    return Err(ErrorData::invalid_params(format!("root_path must be a directory: {}", root_path), None));
+                }
+                let walk_config = config.clone();
+                let (files, truncated) = blocking_io(move || build_manifest(&root, &walk_config, Some(&old_file))).await?;
+                let live = Manifest { version: 1, generated: chrono::Utc::now().to_rfc3339(), truncated, files };
+                (live, root_path.clone())
+            }
+            _ => return Err(ErrorData::invalid_params("Provide exactly one of new_manifest_path or root_path", None)),
+        };
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut modified = Vec::new();
+        let mut unchanged = 0;
+        for (path, before) in &old.files {
+            match new.files.get(path) {
+                None => removed.push(serde_json::json!({ "path": path, "size": before.size, "sha256": before.sha256 })),
+                Some(after) if after.sha256 != before.sha256 => modified.push(serde_json::json!({
+                    "path": path,
+                    "old_size": before.size,
+                    "new_size": after.size,
+                    "old_sha256": before.sha256,
+                    "new_sha256": after.sha256,
+                })),
+                Some(_) => unchanged += 1,
+            }
+        }
+        for (path, after) in &new.files {
+            if !old.files.contains_key(path) {
+                added.push(serde_json::json!({ "path": path, "size": after.size, "sha256": after.sha256 }));
+            }
+        }
+
+        let mut result = serde_json::json!({
+            "old": { "source": args.old_manifest_path, "generated": old.generated },
+            "new": { "source": new_label, "generated": new.generated },
+            "added": added,
+            "removed": removed,
+            "modified": modified,
+            "unchanged": unchanged,
+        });
+        if old.truncated || new.truncated {
+            // Files past a manifest's cap look added or removed when they may not be
+            result["incomplete"] = serde_json::Value::Bool(true);
+        }
+        let summary = format!("Diffed manifests: {} added, {} removed, {} modified", added.len(), removed.len(), modified.len());
+        let output = serde_json::to_string_pretty(&result).unwrap_or_default();
+
+        Ok(success_log("diff_manifests", "read", Some(&args.old_manifest_path.clone()), &summary, vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 40. empty_directory — delete everything inside a directory
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Delete all contents of a directory but keep the directory itself (e.g. to clean an output folder). Requires confirm=true. Every entry is checked first, and nothing is deleted if any of it is protected or ignored. Reports how many files and directories were removed. Requires Read/Write permission.")]
+    async fn empty_directory(&self, params: Parameters<EmptyDirectoryParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let dir = validate_writable(&args.path, &config)?.canonical_path;
+        if !dir.is_dir() {
+            return Err(ErrorData::invalid_params(format!("Not a directory: {}", args.path), None));
+        }
+
+        let children: Vec<std::path::PathBuf> = fs::read_dir(&dir)
+            .map_err(|e| ErrorData::internal_error(format!("Failed to read directory: {}", e), None))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect();
+        // Check every entry before touching any, so a refusal leaves the directory intact
+        for child in &children {
+            validate_destructive(&child.to_string_lossy(), &config)?;
+        }
+        if children.is_empty() {
+            return Ok(success_log("empty_directory", "delete", Some(&args.path.clone()), "Directory already empty", vec![Content::text(
+                format!("{} is already empty", args.path),
+            )]));
+        }
+        if !args.confirm {
+            return Err(ErrorData::invalid_params(
+                format!(
+                    "{} contains {} entries. Set confirm=true to delete them all permanently.",
+                    args.path,
+                    children.len()
+                ),
+                None,
+            ));
+        }
+
+        let policy = crate::confirm::Policy::from(&*config);
+        crate::confirm::confirm_destructive(policy, "empty_directory", &args.path, "emptying").await?;
+
+        let _permit = self.acquire_heavy_slot().await?;
+        let (files, dirs) = blocking_io(move || {
+            let (mut files, mut dirs) = (0usize, 0usize);
+            for child in children {
+                // symlink_metadata so a link is removed itself, never what it points to
+                let is_dir = fs::symlink_metadata(&child).map(|m| m.is_dir()).unwrap_or(false);
+                let removed = if is_dir {
+                    for entry in walkdir::WalkDir::new(&child).into_iter().filter_map(|e| e.ok()) {
+                        if entry.file_type().is_dir() { dirs += 1 } else { files += 1 }
+                    }
+                    fs::remove_dir_all(&child)
+                } else {
+                    files += 1;
+                    fs::remove_file(&child)
+                };
+                removed.map_err(|e| {
+                    ErrorData::internal_error(format!("Failed to delete {}: {}", child.display(), e), None)
+                })?;
+            }
+            Ok((files, dirs))
+        })
+        .await?;
+
+        let summary = format!("Emptied directory ({} files, {} directories removed)", files, dirs);
+        Ok(success_log("empty_directory", "delete", Some(&args.path.clone()), &summary, vec![Content::text(format!(
+            "Emptied {}: removed {} file(s) and {} director{}",
+            args.path,
+            files,
+            dirs,
+            if dirs == 1 { "y" } else { "ies" }
+        ))]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 41. check_ignore — which ignore rule hides a path
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Explain whether a path in a shared folder is hidden by an ignore rule. Returns JSON with ignored (true/false) and, when ignored, the matching rule and where it is defined (the .mcpignore file and line, or the global ignore list).")]
+    async fn check_ignore(&self, params: Parameters<CheckIgnoreParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let rule = matching_ignore_rule(&args.path, &config)?;
+        let result = match &rule {
+            Some(rule) => serde_json::json!({
+                "path": args.path,
+                "ignored": true,
+                "rule": rule.pattern.trim(),
+                "file": rule.file.as_ref().map(|f| crate::activity::redact_home(&f.to_string_lossy())),
+                "line": rule.line,
+                "source": crate::activity::redact_home(&rule.location()),
+            }),
+            None => serde_json::json!({ "path": args.path, "ignored": false }),
+        };
+        let summary = match &rule {
+            Some(rule) => format!("Ignored by {} ({})", rule.pattern.trim(), rule.location()),
+            None => "Not ignored".to_string(),
+        };
+        let output = serde_json::to_string_pretty(&result).unwrap_or_default();
+
+        Ok(success_log("check_ignore", "read", Some(&args.path.clone()), &summary, vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 42. compare_content — does a file already hold this text?
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Check whether a text file already has exactly the given content, e.g. before calling write_file. Returns \"Identical\" or \"Differs\" followed by a unified diff from the file to the given content.")]
+    async fn compare_content(&self, params: Parameters<CompareContentParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let config = self.config_snapshot().await;
+
+        let file_path = validate_path(&args.path, &config)?.canonical_path;
+        if !file_path.exists() {
+            return Ok(success_log("compare_content", "read", Some(&args.path.clone()), "Compared content: file missing", vec![Content::text(
+                format!("Differs: {} does not exist yet", args.path),
+            )]));
+        }
+        if !file_path.is_file() {
+            return Err(ErrorData::invalid_params(format!("Not a file: {}", args.path), None));
+        }
+
+        let metadata = fs::metadata(&file_path).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let size_mb = metadata.len() as f64 / (1024.0 * 1024.0);
+        if size_mb > config.max_file_size_mb as f64 {
+            return Ok(file_too_large("compare_content", &args.path, metadata.len(), config.max_file_size_mb, ""));
+        }
+
+        let current = fs::read_to_string(&file_path).map_err(|e| {
+            ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+        })?;
+
+        if current == args.content {
+            return Ok(success_log("compare_content", "read", Some(&args.path.clone()), "Compared content: identical", vec![Content::text(
+                format!("Identical: {} already has this content ({} bytes)", args.path, current.len()),
+            )]));
+        }
+
+        let diff = unified_diff(&current, &args.content, &args.path, "content");
+        let output = if diff.is_empty() {
+            format!("Differs: {} has the same lines but different line endings or final newline", args.path)
+        } else {
+            format!("Differs: {}\n\n{}", args.path, diff)
+        };
+        Ok(success_log("compare_content", "read", Some(&args.path.clone()), "Compared content: differs", vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 43. find_duplicates — groups of files with identical content
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "Find files with identical content under root_path. Files are grouped by size first, so only same-sized files are hashed (SHA-256). Returns JSON groups sorted by wasted space (size × extra copies), with paths relative to root_path. Ignored files are skipped; files over the size limit are not hashed, and the file count is capped by the server config.")]
+    async fn find_duplicates(&self, params: Parameters<FindDuplicatesParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
+
+        let root = validate_path(&args.root_path, &config)?.canonical_path;
+        if !root.is_dir() {
+            return Err(ErrorData::invalid_params(format!("root_path must be a directory: {}", args.root_path), None));
+        }
+        let min_size = args.min_size.unwrap_or(1);
+        let max_groups = args.max_groups.unwrap_or(50).max(1);
+
+        let walk_config = config.clone();
+        let scan = blocking_io(move || find_duplicate_files(&root, &walk_config, min_size)).await?;
+
+        let wasted: u64 = scan.groups.iter().map(|g| g.wasted_bytes()).sum();
+        let groups: Vec<serde_json::Value> = scan
+            .groups
+            .iter()
+            .take(max_groups)
+            .map(|g| serde_json::json!({
+                "size": g.size,
+                "sha256": g.sha256,
+                "wasted_bytes": g.wasted_bytes(),
+                "paths": g.paths,
+            }))
+            .collect();
+        let mut result = serde_json::json!({
+            "root": args.root_path,
+            "files_scanned": scan.files_scanned,
+            "duplicate_groups": scan.groups.len(),
+            "wasted_bytes": wasted,
+            "groups": groups,
+        });
+        if scan.groups.len() > max_groups {
+            result["groups_omitted"] = serde_json::json!(scan.groups.len() - max_groups);
+        }
+        if scan.skipped_large > 0 {
+            result["skipped_over_size_limit"] = serde_json::json!(scan.skipped_large);
+        }
+        if scan.truncated {
+            // Duplicates of files past the cap can't have been found
+            result["truncated"] = serde_json::Value::Bool(true);
+        }
+
+        let summary = format!("Found {} duplicate group(s), {} wasted", scan.groups.len(), format_size(wasted));
+        let output = serde_json::to_string_pretty(&result).unwrap_or_default();
+        Ok(success_log("find_duplicates", "read", Some(&args.root_path.clone()), &summary, vec![Content::text(output)]))
+    }
+
+    // ────────────────────────────────────────────────────────
+    // 44. largest_files — the N biggest files in a tree
+    // ────────────────────────────────────────────────────────
+
+    #[tool(description = "List the largest files under root_path, biggest first, as JSON with paths relative to root_path and sizes in bytes and human-readable form. Use count to choose how many (default 10). Ignored files are skipped and the number of files examined is capped by the server config.")]
+    async fn largest_files(&self, params: Parameters<LargestFilesParams>) -> Result<CallToolResult, ErrorData> {
+        let args = params.0;
+        let _permit = self.acquire_heavy_slot().await?;
+        let config = self.config_snapshot().await;
+
+        let root = validate_path(&args.root_path, &config)?.canonical_path;
+        if !root.is_dir() {
+            return Err(ErrorData::invalid_params(format!("root_path must be a directory: {}", args.root_path), None));
+        }
+        let count = args.count.unwrap_or(10).clamp(1, LARGEST_FILES_MAX);
+
+        let walk_config = config.clone();
+        let (largest, files_scanned, truncated) = blocking_io(move || Ok(find_largest_files(&root, &walk_config, count))).await?;
+
+        let files: Vec<serde_json::Value> = largest
+            .iter()
+            .map(|(size, path)| serde_json::json!({ "path": path, "size_bytes": size, "size": format_size(*size) }))
+            .collect();
+        let mut result = serde_json::json!({
+            "root": args.root_path,
+            "files_scanned": files_scanned,
+            "files": files,
+        });
+        if truncated {
+            // Files past the cap weren't looked at, so bigger ones may exist
+            result["truncated"] = serde_json::Value::Bool(true);
+        }
+
+        let summary = match largest.first() {
+            Some((size, path)) => format!("Listed {} largest file(s); biggest is {} ({})", largest.len(), path, format_size(*size)),
+            None => "Listed largest files: none found".to_string(),
+        };
+        let output = serde_json::to_string_pretty(&result).unwrap_or_default();
+        Ok(success_log("largest_files", "read", Some(&args.root_path.clone()), &summary, vec![Content::text(output)]))
+    }
+}
+
+// ─── Helper: JSON documents ───
+
+/// Parse a file as JSON
+fn parse_json_file(path: &std::path::Path) -> Result<serde_json::Value, ErrorData> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        ErrorData::invalid_params(format!("Cannot read file as text: {}", e), None)
+    })?;
+    parse_json(&content)
+}
+
+/// Parse JSON text, reporting where parsing failed
+fn parse_json(content: &str) -> Result<serde_json::Value, ErrorData> {
+    serde_json::from_str(content).map_err(|e| {
+        ErrorData::invalid_params(
+            format!("Not valid JSON (line {}, column {}): {}", e.line(), e.column(), e),
+            None,
+        )
+    })
+}
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// The value at `pointer`, or a summary with a truncated preview when its JSON
+/// is longer than `max_chars`
+fn describe_json(pointer: &str, value: &serde_json::Value, max_chars: usize) -> serde_json::Value {
+    let mut result = serde_json::json!({ "pointer": pointer, "type": json_type(value) });
+    let json = serde_json::to_string_pretty(value).unwrap_or_default();
+    let chars = json.chars().count();
+    if chars <= max_chars {
+        result["value"] = value.clone();
+        return result;
+    }
+
+    result["truncated"] = serde_json::Value::Bool(true);
+    result["total_chars"] = serde_json::json!(chars);
+    match value {
+        serde_json::Value::Object(map) => result["keys"] = serde_json::json!(map.keys().collect::<Vec<_>>()),
+        serde_json::Value::Array(items) => result["length"] = serde_json::json!(items.len()),
+        _ => {}
+    }
+    result["preview"] = serde_json::Value::String(format!("{}…", json.chars().take(max_chars).collect::<String>()));
+    result
+}
+
+/// The longest prefix of `pointer` that resolves in `document`
+fn deepest_pointer(document: &serde_json::Value, pointer: &str) -> String {
+    let mut deepest = String::new();
+    for token in pointer.split('/').skip(1) {
+        let candidate = format!("{}/{}", deepest, token);
+        if document.pointer(&candidate).is_none() {
+            break;
+        }
+        deepest = candidate;
+    }
+    deepest
+}
+
+/// Apply an RFC 7386 merge patch to `target`, recording each change as
+/// "added /a", "updated /b" or "removed /c"
+fn apply_merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value, pointer: &str, changes: &mut Vec<String>) {
+    let here = if pointer.is_empty() { "/" } else { pointer };
+    let serde_json::Value::Object(patch) = patch else {
+        if target != patch {
+            changes.push(format!("updated {}", here));
+            *target = patch.clone();
+        }
+        return;
+    };
+    if !target.is_object() {
+        changes.push(format!("updated {}", here));
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let serde_json::Value::Object(target) = target else { return };
+
+    for (key, value) in patch {
+        let child = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+        if value.is_null() {
+            if target.shift_remove(key).is_some() {
+                changes.push(format!("removed {}", child));
+            }
+        } else if let Some(existing) = target.get_mut(key) {
+            apply_merge_patch(existing, value, &child, changes);
+        } else {
+            // Nulls nested inside an added object are dropped, as the RFC specifies
+            let mut added = serde_json::Value::Null;
+            apply_merge_patch(&mut added, value, &child, &mut Vec::new());
+            changes.push(format!("added {}", child));
+            target.insert(key.clone(), added);
+        }
+    }
+}
+
+/// Serialize `value` with the indentation, line endings and final newline of `original`
+fn serialize_like(value: &serde_json::Value, original: &str) -> String {
+    let indent = original
+        .lines()
+        .skip(1)
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .find(|i| !i.is_empty());
+    let mut out = match indent {
+        Some(indent) => {
+            let mut buf = Vec::new();
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+            let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+            let _ = serde::Serialize::serialize(value, &mut serializer);
+            String::from_utf8(buf).unwrap_or_default()
+        }
+        None => serde_json::to_string(value).unwrap_or_default(),
+    };
+    if original.ends_with('\n') {
+        out.push('\n');
+    }
+    if original.contains("\r\n") {
+        out = out.replace('\n', "\r\n");
+    }
+    out
+}
+
+// ─── Helper: file formatters ───
+//
+// format_file picks the first entry whose extensions match. To support another
+// type, add a formatting function and an entry to FORMATTERS.
+
+struct FileFormatter {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    format: fn(&str) -> Result<String, ErrorData>,
+}
+
+const FORMATTERS: &[FileFormatter] = &[
+    FileFormatter { name: "JSON", extensions: &["json"], format: format_json },
+];
+
+/// Pretty-print with 2-space indentation and a final newline, keeping key order
+fn format_json(content: &str) -> Result<String, ErrorData> {
+    let value = parse_json(content)?;
+    let mut formatted = serde_json::to_string_pretty(&value).map_err(|e| {
+        ErrorData::internal_error(format!("Failed to serialize JSON: {}", e), None)
+    })?;
+    formatted.push('\n');
+    Ok(formatted)
+}
+
+// ─── Helper: line diffs ───
+
+/// Unchanged lines shown around each change in a unified diff
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Changed regions bigger than this (old lines × new lines) are shown as one
+/// replaced block rather than matched line by line
+const DIFF_MAX_CELLS: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum DiffOp {
+    Same,
+    Removed,
+    Added,
+}
+
+/// The line-level edits turning `old` into `new`, keeping the longest run of
+/// common lines
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(DiffOp, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(DiffOp, &str)> = old[..prefix].iter().map(|l| (DiffOp::Same, *l)).collect();
+    if a.len().saturating_mul(b.len()) <= DIFF_MAX_CELLS {
+        // lcs[i * width + j]: longest common subsequence of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                ops.push((DiffOp::Same, a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                ops.push((DiffOp::Removed, a[i]));
+                i += 1;
+            } else {
+                ops.push((DiffOp::Added, b[j]));
+                j += 1;
+            }
+        }
+        ops.extend(a[i..].iter().map(|l| (DiffOp::Removed, *l)));
+        ops.extend(b[j..].iter().map(|l| (DiffOp::Added, *l)));
+    } else {
+        ops.extend(a.iter().map(|l| (DiffOp::Removed, *l)));
+        ops.extend(b.iter().map(|l| (DiffOp::Added, *l)));
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|l| (DiffOp::Same, *l)));
+    ops
+}
+
+/// A unified diff (`---`/`+++` headers and `@@` hunks) from `old` to `new`, or an
+/// empty string when their lines are the same
+fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != DiffOp::Same).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Line numbers in old and new at the start of each op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for (op, _) in &ops {
+        positions.push((old_pos, new_pos));
+        if *op != DiffOp::Added {
+            old_pos += 1;
+        }
+        if *op != DiffOp::Removed {
+            new_pos += 1;
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(DIFF_CONTEXT_LINES);
+        let mut last = changes[k];
+        k += 1;
+        // Changes whose context would touch share a hunk
+        while k < changes.len() && changes[k] - last <= 2 * DIFF_CONTEXT_LINES {
+            last = changes[k];
+            k += 1;
+        }
+        let end = (last + 1 + DIFF_CONTEXT_LINES).min(ops.len());
+
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Added).count();
+        let new_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Removed).count();
+        let (old_start, new_start) = positions[start];
+        // An empty side is numbered by the line before it, as `diff -u` does
+        let line_no = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            line_no(old_start, old_count),
+            old_count,
+            line_no(new_start, new_count),
+            new_count
+        ));
+        for (op, line) in hunk {
+            let marker = match op {
+                DiffOp::Same => ' ',
+                DiffOp::Removed => '-',
+                DiffOp::Added => '+',
+            };
+            out.push(marker);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// ─── Helper: soft errors ───
+//
+// Failures the agent can recover from by changing its request are "soft": the call
+// succeeds with `is_error: true` and a structured body
+// `{"error": {"code", "message", ...}}` it can act on without parsing prose.
+// Soft: a file over max_file_size_mb or whose base64 form would not fit in
+// max_response_bytes (`file_too_large`) and batch_read entries that could not be
+// read (`partial_batch`, `is_error` only when none were read).
+// Hard (`ErrorData`): invalid arguments, sandbox denials, missing files, conflicts
+// and I/O failures — retrying the same call won't help without other changes.
+
+/// A soft error result, logged to the activity feed like any other call
+fn soft_error(tool: &str, path: Option<&str>, code: &str, message: String, details: serde_json::Value) -> CallToolResult {
+    crate::activity::log_activity(tool, "read", path, &format!("Soft error ({}): {}", code, message));
+
+    let mut error = serde_json::json!({ "code": code, "message": message, "recoverable": true });
+    if let (Some(error), serde_json::Value::Object(details)) = (error.as_object_mut(), details) {
+        error.extend(details);
+    }
+    CallToolResult::structured_error(serde_json::json!({ "error": error }))
+}
+
+/// The file exceeds `max_file_size_mb`; `hint` is appended to the message
+fn file_too_large(tool: &str, path: &str, size: u64, limit_mb: u32, hint: &str) -> CallToolResult {
+    let size_mb = size as f64 / (1024.0 * 1024.0);
+    soft_error(
+        tool,
+        Some(path),
+        "file_too_large",
+        format!("File too large: {:.2} MB (limit: {} MB).{}", size_mb, limit_mb, hint),
+        serde_json::json!({ "size_bytes": size, "limit_mb": limit_mb }),
+    )
+}
+
+/// The file's base64 encoding would exceed `max_response_bytes` and be truncated
+fn encoded_too_large(tool: &str, path: &str, size: u64, encoded: u64, limit: u64) -> CallToolResult {
+    soft_error(
+        tool,
+        Some(path),
+        "file_too_large",
+        format!(
+            "File too large to return as base64: {} encoded (response limit: {}). Use peek_bytes for a partial read.",
+            format_size(encoded),
+            format_size(limit)
+        ),
+        serde_json::json!({ "size_bytes": size, "encoded_bytes": encoded, "limit_bytes": limit }),
+    )
+}
+
+// ─── Helper: config snapshots ───
+
+impl OmniDriveServer {
+    /// The current config. The read lock is released before returning, so tools
+    /// finish against the config they started with even if it is reloaded meanwhile.
+    pub async fn config_snapshot(&self) -> Arc<AppConfig> {
+        self.config.read().await.clone()
+    }
+}
+
+/// `read_cache_mb` in bytes; 0 turns the read cache off
+fn read_cache_budget(config: &AppConfig) -> usize {
+    config.read_cache_mb.saturating_mul(1024 * 1024)
+}
+
+// ─── Helper: heavy operation limiter ───
+
+impl OmniDriveServer {
+    /// Wait for a slot in the heavy-operation semaphore, giving up after `HEAVY_OP_WAIT`
+    async fn acquire_heavy_slot(&self) -> Result<OwnedSemaphorePermit, ErrorData> {
+        match tokio::time::timeout(HEAVY_OP_WAIT, self.heavy_ops.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(ErrorData::internal_error("Heavy operation limiter is closed", None)),
+            Err(_) => Err(ErrorData::internal_error(
+                format!(
+                    "Server busy: too many expensive operations are running (waited {}s). Try again shortly.",
+                    HEAVY_OP_WAIT.as_secs()
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Resize the heavy-operation semaphore after `max_heavy_operations` changes.
+    /// Running operations keep their slots, so a lower limit takes full effect as they finish.
+    pub fn set_max_heavy_operations(&self, limit: usize) {
+        let limit = limit.max(1);
+        let previous = self.heavy_ops_limit.swap(limit, Ordering::SeqCst);
+        if limit > previous {
+            self.heavy_ops.add_permits(limit - previous);
+        } else if limit < previous {
+            let excess = previous - limit;
+            let busy = excess - self.heavy_ops.forget_permits(excess);
+            if busy > 0 {
+                // Retire the rest of the slots as the operations holding them release them
+                let heavy_ops = self.heavy_ops.clone();
+                tokio::spawn(async move {
+                    if let Ok(permits) = heavy_ops.acquire_many_owned(busy as u32).await {
+                        permits.forget();
+                    }
+                });
+            }
+        }
+    }
+}
+
+// ─── Helper: blocking work ───
+
+/// Run synchronous work on Tokio's blocking pool so it can't stall the async
+/// workers, giving up after `timeout`. A timed-out thread can't be cancelled;
+/// it runs to completion in the background and its result is dropped.
+async fn run_blocking<T, F>(what: &str, timeout: Duration, work: F) -> Result<T, ErrorData>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let agent = crate::activity::request_agent();
+    let work = move || crate::activity::with_agent_sync(agent, work);
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(work)).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(ErrorData::internal_error(format!("{} failed: {}", what, e), None)),
+        Err(_) => Err(ErrorData::internal_error(
+            format!(
+                "{} timed out after {}s. The file may be malformed or too complex; blocking_timeout_secs in the config sets the limit.",
+                what,
+                timeout.as_secs_f64()
+            ),
+            None,
+        )),
+    }
+}
+
+/// Run synchronous filesystem work (large reads, walks, archive writes) on the
+/// blocking pool so one heavy call doesn't stall every other client's requests
+async fn blocking_io<T, F>(work: F) -> Result<T, ErrorData>
+where
+    F: FnOnce() -> Result<T, ErrorData> + Send + 'static,
+    T: Send + 'static,
+{
+    let agent = crate::activity::request_agent();
+    tokio::task::spawn_blocking(move || crate::activity::with_agent_sync(agent, work))
+        .await
+        .map_err(|e| ErrorData::internal_error(format!("Background I/O task failed: {}", e), None))?
+}
+
+// ─── Helper: zip archives ───
+
+/// A file to be added to an archive under `name`
+struct ZipSource {
+    name: String,
+    path: std::path::PathBuf,
+    size: u64,
+}
+
+/// Which files a directory walk in zip_files keeps
+struct ZipFilter {
+    exclude: Vec<glob::Pattern>,
+    /// Lowercase extensions without the dot; `None` keeps every extension
+    include_extensions: Option<Vec<String>>,
+}
+
+impl ZipFilter {
+    fn new(exclude: &[String], include_extensions: Option<&[String]>) -> Result<Self, String> {
+        let exclude = exclude
+            .iter()
+            .map(|p| glob::Pattern::new(p).map_err(|e| format!("Invalid exclude pattern '{}': {}", p, e)))
+            .collect::<Result<_, _>>()?;
+        let include_extensions = include_extensions.map(|exts| {
+            exts.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect()
+        });
+        Ok(Self { exclude, include_extensions })
+    }
+
+    /// Whether `rel` has one of the included extensions (always true without a filter)
+    fn includes(&self, rel: &std::path::Path) -> bool {
+        let Some(allowed) = &self.include_extensions else { return true };
+        rel.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| allowed.contains(&ext))
+    }
+
+    /// Whether `rel` (relative to the walked directory) matches an exclude pattern.
+    /// Patterns without a slash also match the bare file name, so "*.log" works at any depth.
+    fn excludes(&self, rel: &std::path::Path) -> bool {
+        let rel_str = rel.to_string_lossy().replace('\\', "/");
+        let name = rel.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        self.exclude.iter().any(|p| {
+            p.matches(&rel_str) || (!p.as_str().contains('/') && p.matches(&name))
+        })
+    }
+}
+
+/// Resolve zip_files' inputs into the files it would archive, plus how many were
+/// excluded by pattern. Directories are walked and stored relative to themselves;
+/// files inside them that fail validation are skipped.
+fn collect_zip_sources(
+    paths: &[String],
+    config: &AppConfig,
+    filter: &ZipFilter,
+) -> Result<(Vec<ZipSource>, usize), ErrorData> {
+    let mut sources = Vec::new();
+    let mut excluded = 0;
+    for path_str in paths {
+        let src_path = validate_path(path_str, config)?.canonical_path;
+
+        if src_path.is_file() {
+            let name = src_path.file_name().and_then(|n| n.to_str()).unwrap_or("file").to_string();
+            let size = fs::metadata(&src_path).map(|m| m.len()).unwrap_or(0);
+            sources.push(ZipSource { name, path: src_path, size });
+        } else if src_path.is_dir() {
+            for entry in walkdir::WalkDir::new(&src_path).into_iter().filter_map(|e| e.ok()) {
+                let entry_path = entry.path();
+                if !entry_path.is_file() {
+                    continue;
+                }
+                let entry_str = entry_path.to_string_lossy().to_string();
+                if validate_path(&entry_str, config).is_err() {
+                    continue;
+                }
+                let rel = entry_path.strip_prefix(&src_path).unwrap_or(entry_path);
+                if filter.excludes(rel) {
+                    excluded += 1;
+                    continue;
+                }
+                if !filter.includes(rel) {
+                    continue;
+                }
+                sources.push(ZipSource {
+                    name: rel.to_string_lossy().to_string(),
+                    path: entry_path.to_path_buf(),
+                    size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                });
+            }
+        }
+    }
+    Ok((sources, excluded))
+}
+
+// ─── Helper: list_directory recursive ───
+
+impl OmniDriveServer {
+    async fn list_directory_recursive(
+        &self,
+        dir_path: &std::path::Path,
+        folder: &crate::config::SharedFolder,
+        args: &ListDirectoryParams,
+        config: &Arc<AppConfig>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let _permit = self.acquire_heavy_slot().await?;
+        let max_depth = args.max_depth.clamp(1, 10);
+        let max_entries = config.max_tree_entries.max(1);
+
+        let (root, skip_hidden, walk_config) = (dir_path.to_path_buf(), args.skip_hidden, config.clone());
+        let (entries, deepest, truncated) = blocking_io(move || {
+            let config = &*walk_config;
+            let mut entries = Vec::new();
+            let mut deepest = 0;
+            let mut truncated = false;
+
+            // Prune .mcpignore'd (and, unless requested, hidden) entries during the walk,
+            // so they are neither listed nor descended into (matching the flat listing)
+            let walker = walkdir::WalkDir::new(&root)
+                .max_depth(max_depth)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| {
+                    e.depth() == 0
+                        || (!(skip_hidden && is_hidden_entry(e))
+                            && !is_default_ignored_entry(e, config)
+                            && validate_path(&e.path().to_string_lossy(), config).is_ok())
+                })
+                .filter_map(|e| e.ok());
+
+            for entry in walker {
+                let path = entry.path();
+                let depth = entry.depth();
+                if depth == 0 { continue; } // Skip root
+
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                let is_dir = path.is_dir();
+
+                if !is_dir && !is_supported_extension(name, &config.extra_extensions) { continue; }
+
+                // Stop walking once the cap is reached rather than collecting the whole tree
+                if entries.len() >= max_entries {
+                    truncated = true;
+                    break;
+                }
+
+                deepest = deepest.max(depth);
+
+                let size = if is_dir { 0 } else { entry.metadata().map(|m| m.len()).unwrap_or(0) };
+                let indent = "  ".repeat(depth - 1);
+                let type_marker = if is_dir { "📁" } else { "📄" };
+                let size_str = if is_dir { String::new() } else { format!(" ({})", format_size(size)) };
+
+                entries.push(format!("{}{} {}{}", indent, type_marker, name, size_str));
+            }
+            Ok((entries, deepest, truncated))
+        })
+        .await?;
+
+        // Paginate the flat list of tree entries
+        let total_items = entries.len();
+        let page_size = effective_page_size(args.page_size, config.default_page_size, config.max_tree_page_size);
+        let page = args.page.max(1);
+        let start_idx = (page - 1) * page_size;
+
+        if start_idx >= total_items && total_items > 0 {
+            return
+
+        Ok(success_log("list_directory_recursive", "read", Some(&args.path.clone()), "Listed directory recursively", vec![Content::text(format!(
+                "Page {} is out of range. Total items: {} ({} pages)",
+                page, total_items, (total_items + page_size - 1) / page_size
+            ))]));
+        }
+
+        let paged: Vec<&String> = entries.iter().skip(start_idx).take(page_size).collect();
+
+        let mut output = String::new();
+        output.push_str(&format!("Tree: {} (depth: {}, deepest level listed: {}, page {}/{})\n",
+            args.path, max_depth, deepest, page, (total_items + page_size - 1) / page_size.max(1)));
+        if args.show_folder {
+            output.push_str(&format!("{}\n", folder_line(folder)));
+        }
+        if truncated {
+            output.push_str(&format!(
+                "{} items listed (tree truncated: stopped after {} entries; narrow the path or lower max_depth)\n\n",
+                total_items, max_entries
+            ));
+        } else {
+            output.push_str(&format!("{} items total\n\n", total_items));
+        }
+        for line in paged {
+            output.push_str(line);
+            output.push('\n');
+        }
+
+        Ok(success_log("list_directory_recursive", "read", Some(&args.path.clone()), "Listed directory recursively", vec![Content::text(output)]))
+    }
+}
+
+// ─── Helper: list_tree ───
+
+/// Depth-first walk for list_tree, sharing one node budget across the whole tree
+struct TreeWalk {
+    max_depth: usize,
+    skip_hidden: bool,
+    nodes_left: usize,
+    truncated: bool,
+}
+
+impl TreeWalk {
+    /// JSON nodes for the entries of `dir`, which sits at `depth` below the root
+    fn children(&mut self, dir: &std::path::Path, depth: usize, config: &AppConfig) -> Vec<serde_json::Value> {
+        let mut entries: Vec<_> = match fs::read_dir(dir) {
+            Ok(read) => read.flatten().map(|e| e.path()).collect(),
+            Err(_) => return Vec::new(),
+        };
+        entries.sort();
+
+        let mut nodes = Vec::new();
+        for path in entries {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let is_dir = path.is_dir();
+
+            if self.skip_hidden && name.starts_with('.') { continue; }
+            if is_dir && is_default_ignored_dir(&name, config) { continue; }
+            if !is_dir && !is_supported_extension(&name, &config.extra_extensions) { continue; }
+            if validate_path(&path.to_string_lossy(), config).is_err() { continue; }
+
+            if self.nodes_left == 0 {
+                self.truncated = true;
+                break;
+            }
+            self.nodes_left -= 1;
+
+            if is_dir {
+                let mut node = serde_json::json!({ "name": name, "type": "directory", "size": 0 });
+                if depth < self.max_depth {
+                    node["children"] = serde_json::Value::Array(self.children(&path, depth + 1, config));
+                }
+                nodes.push(node);
+            } else {
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                nodes.push(serde_json::json!({ "name": name, "type": "file", "size": size }));
+            }
+        }
+        nodes
+    }
+}
+
+/// Dotfiles and dot-directories below the walk root. The root itself is never
+/// treated as hidden, so a shared folder like `~/.config` can still be walked.
+fn is_hidden_entry(entry: &walkdir::DirEntry) -> bool {
+    entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Dependency, build and VCS directories that searches and recursive listings skip
+/// while `use_default_ignores` is on. Unlike .mcpignore this only affects traversal:
+/// files inside them can still be read, written or listed directly.
+const DEFAULT_IGNORED_DIRS: &[&str] = &[
+    ".git", ".hg", ".svn", "node_modules", "target", "__pycache__", ".venv", ".tox",
+    ".mypy_cache", ".pytest_cache", ".gradle", ".next",
+];
+
+fn is_default_ignored_dir(name: &str, config: &AppConfig) -> bool {
+    config.use_default_ignores && DEFAULT_IGNORED_DIRS.contains(&name)
+}
+
+/// A default-ignored directory below the walk root
+fn is_default_ignored_entry(entry: &walkdir::DirEntry, config: &AppConfig) -> bool {
+    entry.depth() > 0 && entry.file_type().is_dir() && is_default_ignored_dir(&entry.file_name().to_string_lossy(), config)
+}
+
+/// Whether `path` sits inside a default-ignored directory below `root`
+fn in_default_ignored_dir(path: &std::path::Path, root: &std::path::Path, config: &AppConfig) -> bool {
+    let Some(dirs) = path.strip_prefix(root).ok().and_then(|r| r.parent()) else { return false };
+    dirs.components().any(|c| is_default_ignored_dir(&c.as_os_str().to_string_lossy(), config))
+}
+
+/// Sort `ranges`, clamp them to `total_lines` and merge any that overlap or touch.
+/// Returns 1-indexed inclusive (start, end) pairs; empty or out-of-file ranges are dropped.
+fn merge_line_ranges(ranges: &[LineRange], total_lines: usize) -> Vec<(usize, usize)> {
+    let mut clamped: Vec<(usize, usize)> = ranges
+        .iter()
+        .map(|r| (r.start_line.max(1), r.end_line.min(total_lines)))
+        .filter(|(start, end)| start <= end)
+        .collect();
+    clamped.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in clamped {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Hard ceiling on any configured page size, to keep listings within sane response sizes
+const PAGE_SIZE_CEILING: usize = 1000;
+
+/// Resolve the page size for a listing: the requested size (or the configured
+/// default), clamped to the configured maximum, which itself never exceeds
+/// PAGE_SIZE_CEILING.
+fn effective_page_size(requested: Option<usize>, default: usize, max: usize) -> usize {
+    let max = max.clamp(1, PAGE_SIZE_CEILING);
+    requested.unwrap_or(default).clamp(1, max)
+}
+
+/// Compile a grep pattern, escaping it unless it's a regex
+fn build_matcher(pattern: &str, is_regex: bool, case_insensitive: bool) -> Result<regex::Regex, ErrorData> {
+    let source = if is_regex { pattern.to_string() } else { regex::escape(pattern) };
+    let source = if case_insensitive { format!("(?i){}", source) } else { source };
+    regex::Regex::new(&source).map_err(|e| {
+        if is_regex {
+            ErrorData::invalid_params(format!("Invalid regex '{}': {}", pattern, e), None)
+        } else {
+            ErrorData::internal_error(format!("Internal regex error: {}", e), None)
+        }
+    })
+}
+
+/// Which files a content search looks at
+struct GrepFilter {
+    max_depth: usize,
+    skip_hidden: bool,
+    force_text: bool,
+    include_extensions: Option<Vec<String>>,
+}
+
+/// Files under `root` worth searching: shared, supported, textual (unless forced),
+/// matching the extension filter and within the size limit
+fn grep_candidates<'a>(
+    root: &std::path::Path,
+    filter: &'a GrepFilter,
+    config: &'a AppConfig,
+) -> impl Iterator<Item = std::path::PathBuf> + 'a {
+    walkdir::WalkDir::new(root)
+        .max_depth(filter.max_depth)
+        .into_iter()
+        .filter_entry(move |e| !(filter.skip_hidden && is_hidden_entry(e)) && !is_default_ignored_entry(e, config))
+        .filter_map(|e| e.ok())
+        .filter(move |entry| {
+            let path = entry.path();
+            if !path.is_file() { return false; }
+
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if !is_supported_extension(filename, &config.extra_extensions) { return false; }
+            if is_binary_file(filename) && !filter.force_text { return false; }
+
+            if let Some(ref exts) = filter.include_extensions {
+                let file_ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if !exts.iter().any(|e| e.eq_ignore_ascii_case(file_ext)) {
+                    return false;
+                }
+            }
+
+            // Validate path is within sandbox
+            if validate_path(&path.to_string_lossy(), config).is_err() { return false; }
+
+            // Skip very large files
+            fs::metadata(path).map(|m| m.len() <= (config.max_file_size_mb as u64) * 1024 * 1024).unwrap_or(false)
+        })
+        .map(walkdir::DirEntry::into_path)
+}
+
+/// Resolve how many results a search tool returns: the requested count (or the
+/// tool's default), never more than the configured `max_search_results`
+fn search_result_cap(requested: Option<usize>, default: usize, config: &AppConfig) -> usize {
+    requested.unwrap_or(default).clamp(1, config.max_search_results.max(1))
+}
+
+// ─── Helper: patching ───
+
+/// Apply search-and-replace ops, then line-range ops, to `content`.
+/// Returns the patched text and one summary line per op.
+fn apply_patch_ops(
+    mut content: String,
+    search_replace: &[PatchOp],
+    line_replace: &[LinePatchOp],
+) -> Result<(String, Vec<String>), ErrorData> {
+    let mut summary = Vec::new();
+
+    // Apply search-and-replace operations
+    for (i, op) in search_replace.iter().enumerate() {
+        // An empty search matches everywhere and would never advance
+        if op.search.is_empty() {
+            return Err(ErrorData::invalid_params(
+                format!("Operation {}: search must not be empty", i + 1),
+                None,
+            ));
+        }
+        if op.regex {
+            let re = regex::Regex::new(&op.search).map_err(|e| {
+                ErrorData::invalid_params(format!("Invalid regex in operation {}: {}", i + 1, e), None)
+            })?;
+
+            let limit = op.count.unwrap_or(0);
+            let (new_content, replacements) = if limit > 0 {
+                let mut count = 0usize;
+                let result = re.replace_all(&content, |caps: &regex::Captures| {
+                    count += 1;
+                    if count <= limit {
+                        op.replace.clone()
+                    } else {
+                        caps[0].to_string()
+                    }
+                });
+                (result.to_string(), count.min(limit))
+            } else {
+                let matches = re.find_iter(&content).count();
+                let result = re.replace_all(&content, op.replace.as_str());
+                (result.to_string(), matches)
+            };
+
+            summary.push(format!("Op {}: regex '{}' → {} replacement(s)", i + 1, op.search, replacements));
+            content = new_content;
+        } else {
+            let limit = op.count.unwrap_or(0);
+            let mut count = 0usize;
+            let mut new_content = String::new();
+            let mut remaining = content.as_str();
+
+            while let Some(pos) = remaining.find(&op.search) {
+                if limit > 0 && count >= limit { break; }
+                new_content.push_str(&remaining[..pos]);
+                new_content.push_str(&op.replace);
+                remaining = &remaining[pos + op.search.len()..];
+                count += 1;
+            }
+            new_content.push_str(remaining);
+
+            summary.push(format!("Op {}: '{}' → {} replacement(s)", i + 1, op.search, count));
+            content = new_content;
+        }
+    }
+
+    // Apply line-range replacements (on the already-modified content)
+    if !line_replace.is_empty() {
+        let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+
+        // Sort by start_line descending so we can replace without shifting indices
+        let mut line_ops = line_replace.to_vec();
+        line_ops.sort_by(|a, b| b.start_line.cmp(&a.start_line));
+
+        for (i, op) in line_ops.iter().enumerate() {
+            if op.start_line == 0 || op.end_line == 0 || op.start_line > op.end_line {
+                return Err(ErrorData::invalid_params(
+                    format!("Invalid line range in line_replace op: {}-{} (1-indexed, start <= end)", op.start_line, op.end_line),
+                    None,
+                ));
+            }
+            let start = (op.start_line - 1).min(lines.len());
+            let end = op.end_line.min(lines.len());
+
+            let new_lines: Vec<String> = op.content.lines().map(|l| l.to_string()).collect();
+            let removed = end - start;
+            lines.splice(start..end, new_lines.clone());
+
+            summary.push(format!(
+                "Line op {}: replaced lines {}-{} ({} lines → {} lines)",
+                i + 1, op.start_line, op.end_line, removed, new_lines.len()
+            ));
+        }
+
+        content = lines.join("\n");
+        // Preserve trailing newline if original had one
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+    }
+
+    Ok((content, summary))
+}
+
+/// A file patch computed in memory, ready to be written
+struct PlannedWrite {
+    path: std::path::PathBuf,
+    original: String,
+    patched: String,
+}
+
+/// Write every planned file, or none: if a write fails, files already written are
+/// restored from their in-memory originals. On failure, returns the index of the
+/// failing file and the error.
+fn write_all_or_rollback(plan: &[PlannedWrite]) -> Result<(), (usize, std::io::Error)> {
+    for (i, write) in plan.iter().enumerate() {
+        if let Err(e) = fs::write(&write.path, &write.patched) {
+            for done in &plan[..i] {
+                if let Err(restore) = fs::write(&done.path, &done.original) {
+                    eprintln!("[OmniDrive] Failed to roll back {:?}: {}", done.path, restore);
+                }
+            }
+            return Err((i, e));
+        }
+    }
+    Ok(())
+}
+
+// ─── Helper: CSV slicing ───
+
+#[derive(Debug, Serialize)]
+struct CsvSlice {
+    header: Vec<String>,
+    start_row: usize,
+    rows: Vec<Vec<String>>,
+    has_more: bool,
+}
+
+/// Read `max_rows` records starting at data row `start_row` (1-indexed, header excluded)
+fn read_csv_slice<R: Read>(
+    reader: R,
+    delimiter: u8,
+    start_row: usize,
+    max_rows: usize,
+) -> Result<CsvSlice, csv::Error> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+
+    let header = csv_reader.headers()?.iter().map(|h| h.to_string()).collect();
+
+    let mut rows = Vec::new();
+    let mut has_more = false;
+    for record in csv_reader.records().skip(start_row - 1) {
+        let record = record?;
+        if rows.len() >= max_rows {
+            has_more = true;
+            break;
+        }
+        rows.push(record.iter().map(|f| f.to_string()).collect());
+    }
+
+    Ok(CsvSlice { header, start_row, rows, has_more })
+}
+
+// ─── Helper: text encodings and line endings ───
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// Where decoding `bytes` as UTF-8 failed, e.g. "invalid byte 0xff at byte offset 12 (line 2, column 5)"
+fn describe_utf8_error(bytes: &[u8], err: &std::str::Utf8Error) -> String {
+    let offset = err.valid_up_to();
+    let before = &bytes[..offset];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    // Column counts characters; everything before `offset` is valid UTF-8
+    let column = String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+    let what = match bytes.get(offset) {
+        Some(b) if err.error_len().is_some() => format!("invalid byte 0x{:02x}", b),
+        _ => "truncated multi-byte sequence".to_string(),
+    };
+    format!("{} at byte offset {} (line {}, column {})", what, offset, line, column)
+}
+
+/// The line ending used by the majority of lines (LF on a tie or with no newlines)
+fn detect_line_ending(text: &str) -> LineEnding {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    if crlf > lf { LineEnding::CrLf } else { LineEnding::Lf }
+}
+
+/// Convert every line break (CRLF, LF, or lone CR) to `ending`
+fn normalize_line_endings(text: &str, ending: LineEnding) -> String {
+    let unified = text.replace("\r\n", "\n").replace('\r', "\n");
+    match ending {
+        LineEnding::Lf => unified,
+        LineEnding::CrLf => unified.replace('\n', "\r\n"),
+    }
+}
+
+/// Strip trailing whitespace from each line, rejoining with the dominant line ending.
+/// Returns the new text and how many lines had whitespace removed.
+fn trim_trailing_whitespace(text: &str, ensure_final_newline: bool) -> (String, usize) {
+    let ending = detect_line_ending(text);
+    let mut changed = 0;
+    let mut lines: Vec<&str> = text
+        .split('\n')
+        .map(|segment| {
+            let line = segment.strip_suffix('\r').unwrap_or(segment);
+            let trimmed = line.trim_end();
+            if trimmed.len() != line.len() {
+                changed += 1;
+            }
+            trimmed
+        })
+        .collect();
+
+    if ensure_final_newline {
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        if lines.is_empty() {
+            return (String::new(), changed);
+        }
+        lines.push("");
+    }
+
+    (lines.join(ending.as_str()), changed)
+}
+
+/// Decode raw bytes to a string. Uses `label` if given, otherwise sniffs a BOM and falls back to UTF-8.
+/// Returns the decoded text and the name of the source encoding.
+fn decode_text(bytes: &[u8], label: Option<&str>) -> Result<(String, &'static str), String> {
+    let encoding = match label {
+        Some(l) => encoding_rs::Encoding::for_label(l.trim().as_bytes())
+            .ok_or_else(|| format!("Unknown source encoding: {}", l))?,
+        None => encoding_rs::Encoding::for_bom(bytes)
+            .map(|(enc, _)| enc)
+            .unwrap_or(encoding_rs::UTF_8),
+    };
+
+    let (text, used, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        return Err(format!(
+            "File is not valid {}. Specify source_encoding (e.g. \"windows-1252\").",
+            used.name()
+        ));
+    }
+    Ok((text.into_owned(), used.name()))
+}
+
+// ─── Helper: comment stripping ───
+
+/// How comments and string literals look in a language
+struct CommentSyntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Python-style `"""` / `'''` strings (docstrings are kept)
+    triple_quotes: bool,
+    /// Rust-style `'a'` char literals next to `'a` lifetimes
+    char_literals: bool,
+}
+
+const C_QUOTES: &[char] = &['"', '\''];
+const JS_QUOTES: &[char] = &['"', '\'', '`'];
+
+/// Comment syntax inferred from the file extension; `None` for anything we
+/// don't know well enough to strip safely
+fn comment_syntax(filename: &str) -> Option<CommentSyntax> {
+    let ext = std::path::Path::new(filename).extension()?.to_str()?.to_ascii_lowercase();
+    let c_like = |quotes| CommentSyntax {
+        line: &["//"],
+        block: Some(("/*", "*/")),
+        quotes,
+        triple_quotes: false,
+        char_literals: false,
+    };
+    let hash = |triple_quotes| CommentSyntax {
+        line: &["#"],
+        block: None,
+        quotes: C_QUOTES,
+        triple_quotes,
+        char_literals: false,
+    };
+
+    Some(match ext.as_str() {
+        "rs" => CommentSyntax { char_literals: true, quotes: &['"'], ..c_like(C_QUOTES) },
+        "js" | "jsx" | "ts" | "tsx" | "go" => c_like(JS_QUOTES),
+        "c" | "cpp" | "h" | "hpp" | "cs" | "java" | "kt" | "scala" | "swift" | "dart" | "scss" | "less" => c_like(C_QUOTES),
+        "css" => CommentSyntax { line: &[], ..c_like(C_QUOTES) },
+        "py" => hash(true),
+        "sh" | "rb" | "r" | "yaml" | "yml" | "toml" => hash(false),
+        "sql" => CommentSyntax { line: &["--"], ..c_like(C_QUOTES) },
+        _ => return None,
+    })
+}
+
+/// Remove comments from `text`, leaving string literals untouched. Lines that
+/// held only a comment are dropped; other lines keep their code.
+fn strip_comments(text: &str, syntax: &CommentSyntax) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+
+        if let Some((open, close)) = syntax.block {
+            if rest.starts_with(open) {
+                let end = rest[open.len()..]
+                    .find(close)
+                    .map(|p| open.len() + p + close.len())
+                    .unwrap_or(rest.len());
+                // Keep the newlines so stripped lines still line up with the original
+                out.extend(rest[..end].chars().filter(|&c| c == '\n'));
+                i += end;
+                continue;
+            }
+        }
+
+        if syntax.line.iter().any(|marker| rest.starts_with(marker)) {
+            i += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        let literal_len = if syntax.char_literals && c == '\'' {
+            char_literal_len(rest)
+        } else if syntax.quotes.contains(&c) {
+            string_literal_len(rest, c, syntax.triple_quotes)
+        } else {
+            0
+        };
+
+        if literal_len > 0 {
+            out.push_str(&rest[..literal_len]);
+            i += literal_len;
+        } else {
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+
+    // Drop lines that only held a comment and trim what the comment left behind
+    let mut lines: Vec<&str> = Vec::new();
+    for (original, stripped) in text.split('\n').zip(out.split('\n')) {
+        if stripped.trim().is_empty() && !original.trim().is_empty() {
+            continue;
+        }
+        lines.push(if stripped.len() == original.len() { stripped } else { stripped.trim_end() });
+    }
+    lines.join("\n")
+}
+
+/// Length in bytes of the string literal at the start of `rest`
+fn string_literal_len(rest: &str, quote: char, triple_quotes: bool) -> usize {
+    let triple: String = std::iter::repeat(quote).take(3).collect();
+    if triple_quotes && rest.starts_with(&triple) {
+        return rest[3..].find(&triple).map(|p| p + 6).unwrap_or(rest.len());
+    }
+
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => { chars.next(); }
+            // Single-quoted strings never span lines; an unmatched quote is just a character
+            '\n' if quote == '\'' => return 1,
+            c if c == quote => return idx + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+/// Length of a Rust char literal (`'a'`, `'\n'`, `'\u{1F600}'`) at the start of
+/// `rest`, or 1 for a lifetime such as `'a`
+fn char_literal_len(rest: &str) -> usize {
+    let mut chars = rest.char_indices().skip(1);
+    match chars.next() {
+        Some((_, '\\')) => rest.get(3..).and_then(|r| r.find('\'')).map(|p| p + 4).unwrap_or(1),
+        Some((_, c)) => match chars.next() {
+            Some((idx, '\'')) if c != '\'' => idx + 1,
+            _ => 1,
+        },
+        None => 1,
+    }
+}
+
+// ─── Helper: byte search ───
+
+/// Parse a hex byte string, ignoring whitespace and an optional `0x` prefix
+fn parse_hex_pattern(pattern: &str) -> Result<Vec<u8>, String> {
+    let digits: String = pattern.trim().trim_start_matches("0x").chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() {
+        return Err("pattern must contain at least one byte".to_string());
+    }
+    if digits.len() % 2 != 0 {
+        return Err(format!("pattern '{}' has an odd number of hex digits", pattern));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("pattern '{}' is not valid hex", pattern))
+        })
+        .collect()
+}
+
+/// Offsets of every (possibly overlapping) occurrence of `needle` in `haystack`
+fn find_all<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(offset, _)| offset)
+}
+
+/// Hex dump of a match with `context` bytes either side, the match in [brackets]
+fn hex_context(data: &[u8], offset: usize, len: usize, context: usize) -> String {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+    let start = offset.saturating_sub(context);
+    let end = (offset + len + context).min(data.len());
+
+    let mut parts = Vec::new();
+    if start < offset {
+        parts.push(hex(&data[start..offset]));
+    }
+    parts.push(format!("[{}]", hex(&data[offset..offset + len])));
+    if offset + len < end {
+        parts.push(hex(&data[offset + len..end]));
+    }
+    parts.join(" ")
+}
+
+// ─── Helper: manifests ───
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    generated: String,
+    /// The walk stopped at the file cap, so some files are not listed
+    #[serde(default)]
+    truncated: bool,
+    files: std::collections::BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ManifestEntry {
+    size: u64,
+    sha256: String,
+}
+
+/// Read and parse a manifest file written by generate_manifest, returning it with its resolved path
+fn read_manifest(path: &str, config: &AppConfig) -> Result<(Manifest, std::path::PathBuf), ErrorData> {
+    let file = validate_path(path, config)?.canonical_path;
+    let text = fs::read_to_string(&file)
+        .map_err(|e| ErrorData::invalid_params(format!("Cannot read manifest {}: {}", path, e), None))?;
+    let manifest = serde_json::from_str(&text)
+        .map_err(|e| ErrorData::invalid_params(format!("Invalid manifest {}: {}", path, e), None))?;
+    Ok((manifest, file))
+}
+
+/// Size and hash of every non-ignored file under `root`, keyed by `/`-separated
+/// relative path, stopping after `max_tree_entries` files. `skip` (the manifest
+/// file itself) is left out. Returns the entries and whether the cap was hit.
+fn build_manifest(
+    root: &std::path::Path,
+    config: &AppConfig,
+    skip: Option<&std::path::Path>,
+) -> Result<(std::collections::BTreeMap<String, ManifestEntry>, bool), ErrorData> {
+    let max_files = config.max_tree_entries.max(1);
+    let mut files = std::collections::BTreeMap::new();
+
+    let walker = walkdir::WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0 || (!is_default_ignored_entry(e, config) && validate_path(&e.path().to_string_lossy(), config).is_ok())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && Some(e.path()) != skip);
+
+    for entry in walker {
+        if files.len() >= max_files {
+            return Ok((files, true));
+        }
+        let key = relative_key(entry.path(), root);
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        let sha256 = sha256_file(entry.path())
+            .map_err(|e| ErrorData::internal_error(format!("Failed to hash {}: {}", key, e), None))?;
+        files.insert(key, ManifestEntry { size, sha256 });
+    }
+    Ok((files, false))
+}
+
+/// `path` relative to `root`, with `/` separators on every platform
+fn relative_key(path: &std::path::Path, root: &std::path::Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// ─── Helper: largest files ───
+
+/// Most files largest_files returns
+const LARGEST_FILES_MAX: usize = 1000;
+
+/// The `count` largest files under `root` as (size, relative path), biggest first,
+/// plus how many files were examined and whether the walk hit `max_tree_entries`.
+/// A min-heap of the best `count` so far keeps memory bounded on big trees.
+fn find_largest_files(root: &std::path::Path, config: &AppConfig, count: usize) -> (Vec<(u64, String)>, usize, bool) {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let max_files = config.max_tree_entries.max(1);
+    let mut heap: BinaryHeap<Reverse<(u64, std::path::PathBuf)>> = BinaryHeap::with_capacity(count + 1);
+    let (mut files_scanned, mut truncated) = (0, false);
+
+    let walker = walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0 || (!is_default_ignored_entry(e, config) && validate_path(&e.path().to_string_lossy(), config).is_ok())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+
+    for entry in walker {
+        if files_scanned >= max_files {
+            truncated = true;
+            break;
+        }
+        files_scanned += 1;
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        heap.push(Reverse((size, entry.into_path())));
+        if heap.len() > count {
+            heap.pop();
+        }
+    }
+
+    // Ascending order of Reverse is descending order of size
+    let largest = heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((size, path))| (size, relative_key(&path, root)))
+        .collect();
+    (largest, files_scanned, truncated)
+}
+
+// ─── Helper: duplicate detection ───
+
+/// Files under a root that share one SHA-256
+struct DuplicateGroup {
+    size: u64,
+    sha256: String,
+    /// Relative to the root, sorted
+    paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// Bytes that would be freed by keeping a single copy
+    fn wasted_bytes(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+struct DuplicateScan {
+    /// Most wasted space first
+    groups: Vec<DuplicateGroup>,
+    files_scanned: usize,
+    /// Files over `max_file_size_mb`, left unhashed
+    skipped_large: usize,
+    /// The walk stopped at `max_tree_entries` files
+    truncated: bool,
+}
+
+/// Group the files under `root` by content. Files are bucketed by size first and
+/// only buckets with more than one file are hashed, so unique sizes cost no reads.
+fn find_duplicate_files(root: &std::path::Path, config: &AppConfig, min_size: u64) -> Result<DuplicateScan, ErrorData> {
+    let max_files = config.max_tree_entries.max(1);
+    let max_bytes = config.max_file_size_mb as u64 * 1024 * 1024;
+    let mut by_size: std::collections::HashMap<u64, Vec<std::path::PathBuf>> = std::collections::HashMap::new();
+    let (mut files_scanned, mut skipped_large, mut truncated) = (0, 0, false);
+
+    let walker = walkdir::WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0 || (!is_default_ignored_entry(e, config) && validate_path(&e.path().to_string_lossy(), config).is_ok())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+
+    for entry in walker {
+        if files_scanned >= max_files {
+            truncated = true;
+            break;
+        }
+        files_scanned += 1;
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        if size < min_size {
+            continue;
+        }
+        if size > max_bytes {
+            skipped_large += 1;
+            continue;
+        }
+        by_size.entry(size).or_default().push(entry.into_path());
+    }
+
+    let mut groups = Vec::new();
+    for (size, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        let mut by_hash: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();
+        for path in paths {
+            let key = relative_key(&path, root);
+            let sha256 = sha256_file(&path)
+                .map_err(|e| ErrorData::internal_error(format!("Failed to hash {}: {}", key, e), None))?;
+            by_hash.entry(sha256).or_default().push(key);
+        }
+        groups.extend(
+            by_hash
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|(sha256, paths)| DuplicateGroup { size, sha256, paths }),
+        );
+    }
+    groups.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.paths.cmp(&b.paths)));
+
+    Ok(DuplicateScan { groups, files_scanned, skipped_large, truncated })
+}
+
+// ─── Helper: transient I/O retries ───
+
+/// Errors worth retrying: network filesystems report these for hiccups that
+/// clear on their own. Anything else (PermissionDenied, NotFound, …) is final.
+fn is_transient(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// Run `op` up to `IO_RETRY_ATTEMPTS` times, backing off 20ms, 40ms, 80ms, …
+/// between attempts that failed with a transient error
+fn with_retry<T>(op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    retry_with_backoff(IO_RETRY_ATTEMPTS.load(Ordering::Relaxed), Duration::from_millis(20), op)
+}
+
+fn retry_with_backoff<T>(
+    attempts: usize,
+    base_delay: Duration,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if is_transient(&e) && attempt < attempts => {
+                std::thread::sleep(base_delay * 2u32.pow((attempt - 1).min(6) as u32));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// ─── Helper: moves ───
+
+/// Move `src` to `dst`, trying a rename first and falling back to copy+delete for
+/// files on another filesystem. Returns true when the fallback was used.
+fn move_path(src: &std::path::Path, dst: &std::path::Path) -> Result<bool, ErrorData> {
+    if with_retry(|| fs::rename(src, dst)).is_ok() {
+        return Ok(false);
+    }
+
+    // Cross-device move: copy then delete
+    if !src.is_file() {
+        return Err(ErrorData::internal_error(
+            "Cross-device directory moves are not supported. Copy manually and delete the source.",
+            None,
+        ));
+    }
+    replace_with_copy(src, dst).map_err(|e| {
+        ErrorData::internal_error(format!("Failed to copy during move: {}", e), None)
+    })?;
+    fs::remove_file(src).map_err(|e| {
+        ErrorData::internal_error(format!("Copied but failed to remove source: {}", e), None)
+    })?;
+    Ok(true)
+}
+
+/// Copy `src` next to `dst` under a temporary name, then rename it into place, so an
+/// existing `dst` is only replaced once the copy is complete
+fn replace_with_copy(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    let tmp = temp_sibling(dst);
+    let copied = with_retry(|| fs::copy(src, &tmp)).and_then(|_| fs::rename(&tmp, dst));
+    if copied.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    copied
+}
+
+/// A hidden, unique name next to `path` to build its replacement under
+fn temp_sibling(path: &std::path::Path) -> std::path::PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4()))
+}
+
+// ─── Helper: copies ───
+
+/// Copy a single file, creating the destination's parent directories
+fn copy_one_file(src: &std::path::Path, dst: &std::path::Path) -> Result<u64, ErrorData> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            ErrorData::internal_error(format!("Failed to create destination directory: {}", e), None)
+        })?;
+    }
+
+    with_retry(|| fs::copy(src, dst)).map_err(|e| {
+        ErrorData::internal_error(format!("Failed to copy file: {}", e), None)
+    })
+}
+
+/// Copy a directory tree, skipping ignored entries and symlinks.
+/// Returns the number of files and bytes copied.
+fn copy_dir_recursive(
+    src: &std::path::Path,
+    dst: &std::path::Path,
+    config: &AppConfig,
+) -> Result<(usize, u64), ErrorData> {
+    let mut files = 0;
+    let mut bytes = 0;
+
+    let walker = walkdir::WalkDir::new(src)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || validate_path(&e.path().to_string_lossy(), config).is_ok());
+
+    for entry in walker {
+        let entry = entry.map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        let relative = entry.path().strip_prefix(src).unwrap_or(entry.path());
+        let target = dst.join(relative);
+
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).map_err(|e| {
+                ErrorData::internal_error(format!("Failed to create directory: {}", e), None)
+            })?;
+        } else if entry.file_type().is_file() {
+            validate_writable(&target.to_string_lossy(), config)?;
+            bytes += copy_one_file(entry.path(), &target)?;
+            files += 1;
+        }
+    }
+
+    Ok((files, bytes))
+}
+
+/// Stream `sources` into `output`, with `separator` between each pair. The result is
+/// built under a temporary name and renamed into place, so a failed read leaves an
+/// existing `output` untouched. Returns the number of bytes written.
+fn concat_into(sources: &[std::path::PathBuf], output: &std::path::Path, separator: &[u8]) -> std::io::Result<u64> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = temp_sibling(output);
+    let written = write_concatenated(sources, &tmp, separator).and_then(|n| fs::rename(&tmp, output).map(|_| n));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+fn write_concatenated(sources: &[std::path::PathBuf], output: &std::path::Path, separator: &[u8]) -> std::io::Result<u64> {
+    let mut writer = std::io::BufWriter::new(fs::File::create(output)?);
+    let mut written = 0u64;
+    for (i, src) in sources.iter().enumerate() {
+        if i > 0 && !separator.is_empty() {
+            writer.write_all(separator)?;
+            written += separator.len() as u64;
+        }
+        written += std::io::copy(&mut fs::File::open(src)?, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+// ─── Helper: symlinks ───
+
+#[cfg(unix)]
+fn create_symlink_at(target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink_at(target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+    if target.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink_at(_target: &std::path::Path, _link: &std::path::Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+// ─── Helper: .env parsing ───
+
+/// Parse dotenv-style text into (key, value) pairs in file order. Later
+/// assignments to the same key win, as they do when a shell sources the file.
+fn parse_env(text: &str) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+
+        let Some((key, raw)) = line.split_once('=') else { continue };
+        let key = key.trim();
+        if key.is_empty() {
+            continue;
+        }
+        let value = parse_env_value(raw.trim());
+
+        match vars.iter_mut().find(|(k, _)| k == key) {
+            Some(existing) => existing.1 = value,
+            None => vars.push((key.to_string(), value)),
+        }
+    }
+
+    vars
+}
+
+/// Unquote a single .env value. Double quotes support \n, \t, \" and \\ escapes;
+/// single quotes are literal; unquoted values end at an inline ` #` comment.
+fn parse_env_value(raw: &str) -> String {
+    let mut chars = raw.chars();
+    match chars.next() {
+        Some('"') => {
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => return value,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(other) => value.push(other),
+                        None => value.push('\\'),
+                    },
+                    _ => value.push(c),
+                }
+            }
+            // Unterminated quote: keep what's there
+            value
+        }
+        Some('\'') => {
+            let rest = &raw[1..];
+            match rest.find('\'') {
+                Some(end) => rest[..end].to_string(),
+                None => rest.to_string(),
+            }
+        }
+        _ => {
+            let end = raw
+                .char_indices()
+                .find(|&(i, c)| c == '#' && raw[..i].ends_with(char::is_whitespace))
+                .map(|(i, _)| i)
+                .unwrap_or(raw.len());
+            raw[..end].trim_end().to_string()
+        }
+    }
+}
+
+// ─── Helpers ───
+
+/// One row of a flat directory listing
+struct ListEntry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    mode: Option<u32>,
+    /// Only read when sorting by modification time
+    modified: Option<std::time::SystemTime>,
+}
+
+/// Sort order for a flat directory listing
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("name") => Ok(SortKey::Name),
+            Some("size") => Ok(SortKey::Size),
+            Some("modified") | Some("mtime") => Ok(SortKey::Modified),
+            Some(other) => Err(format!(
+                "Unsupported sort_by '{}'. Use \"name\", \"size\" or \"modified\".",
+                other
+            )),
+        }
+    }
+}
+
+/// Sort listing entries by `key`, optionally grouping directories first.
+/// Ties fall back to case-insensitive name order so pages stay stable.
+fn sort_entries(entries: &mut [ListEntry], key: SortKey, desc: bool, dirs_first: bool) {
+    entries.sort_by(|a, b| {
+        if dirs_first && a.is_dir != b.is_dir {
+            return if a.is_dir { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater };
+        }
+        let by_name = || a.name.to_lowercase().cmp(&b.name.to_lowercase());
+        let ordering = match key {
+            SortKey::Name => by_name(),
+            SortKey::Size => a.size.cmp(&b.size).then_with(by_name),
+            SortKey::Modified => a.modified.cmp(&b.modified).then_with(by_name),
+        };
+        if desc { ordering.reverse() } else { ordering }
+    });
+}
+
+/// Permission bits of a file, where the platform has them
+#[cfg(unix)]
+fn unix_mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Parse an octal mode string like "755", "0644" or "0o600"
+fn parse_octal_mode(mode: &str) -> Result<u32, String> {
+    let digits = mode.trim().trim_start_matches("0o");
+    if digits.is_empty() || digits.len() > 4 || !digits.chars().all(|c| ('0'..='7').contains(&c)) {
+        return Err(format!("Invalid mode '{}': expected 3-4 octal digits, e.g. \"755\"", mode));
+    }
+    u32::from_str_radix(digits, 8).map_err(|e| format!("Invalid mode '{}': {}", mode, e))
+}
+
+/// Apply permission bits, returning the (old, new) modes
+#[cfg(unix)]
+fn apply_mode(path: &std::path::Path, mode: u32) -> Result<(u32, u32), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let meta = fs::metadata(path).map_err(|e| format!("Failed to read metadata: {}", e))?;
+    let old_mode = meta.permissions().mode() & 0o7777;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|e| format!("Failed to set permissions: {}", e))?;
+    Ok((old_mode, mode))
+}
+
+#[cfg(not(unix))]
+fn apply_mode(_path: &std::path::Path, _mode: u32) -> Result<(u32, u32), String> {
+    Err("set_permissions is unsupported on this platform (Unix only).".to_string())
+}
+
+/// Apply the configured `created_file_mode` to a file the server just created.
+/// Off Unix, or when unset, the file keeps the platform default.
+fn apply_created_file_mode(path: &std::path::Path, config: &AppConfig) -> Result<(), ErrorData> {
+    let Some(mode) = config.created_file_mode.as_deref() else { return Ok(()) };
+    if !cfg!(unix) {
+        return Ok(());
+    }
+    let mode = parse_octal_mode(mode)
+        .map_err(|e| ErrorData::internal_error(format!("Invalid created_file_mode in config: {}", e), None))?;
+    apply_mode(path, mode)
+        .map(|_| ())
+        .map_err(|e| ErrorData::internal_error(e, None))
+}
+
+/// Render permission bits as octal plus an `ls`-style summary, e.g. "0755 rwxr-xr-x"
+fn format_mode(mode: u32) -> String {
+    let mut summary = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        summary.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        summary.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        summary.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    format!("{:04o} {}", mode, summary)
+}
+
+/// Render the head of a file: as text when it is UTF-8 (a character cut off by the
+/// byte limit is dropped), otherwise as base64
+fn describe_peek(head: &[u8]) -> String {
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) => text.to_string(),
+        None => format!("[Binary content as base64]\n{}", general_purpose::STANDARD.encode(head)),
+    }
+}
+
+/// Render bytes like `hexdump -C`: offset, 16 hex bytes split in two groups, ASCII column
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, b) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", b));
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("{:08x}  {:<49} |{}|\n", row * 16, hex, ascii));
+    }
+    out
+}
+
+/// A shared folder root that result paths are shown relative to
+struct RelativeRoot {
+    configured: std::path::PathBuf,
+    canonical: std::path::PathBuf,
+}
+
+/// Resolve a `relative_to` argument, which must name the root of an enabled shared folder
+fn resolve_relative_root(relative_to: Option<&str>, config: &AppConfig) -> Result<Option<RelativeRoot>, ErrorData> {
+    let Some(relative_to) = relative_to else { return Ok(None) };
+    let requested = fs::canonicalize(relative_to).ok();
+
+    for folder in config.folders.iter().filter(|f| f.enabled) {
+        if let Ok(canonical) = fs::canonicalize(&folder.path) {
+            if requested.as_ref() == Some(&canonical) {
+                return Ok(Some(RelativeRoot {
+                    configured: std::path::PathBuf::from(&folder.path),
+                    canonical,
+                }));
+            }
+        }
+    }
+
+    Err(ErrorData::invalid_params(
+        format!("relative_to must be the root of a shared folder: {}", relative_to),
+        None,
+    ))
+}
+
+/// Show `path` relative to `root` when it lies inside it, otherwise as-is with the
+/// home directory redacted
+fn display_path(path: &std::path::Path, root: Option<&RelativeRoot>) -> String {
+    root.and_then(|r| {
+        path.strip_prefix(&r.canonical)
+            .or_else(|_| path.strip_prefix(&r.configured))
+            .ok()
+    })
+    .map(|rel| rel.to_string_lossy().to_string())
+    .unwrap_or_else(|| crate::activity::redact_home(&path.to_string_lossy()))
+}
+
+/// Human-facing timestamp, as shown by get_file_info and search previews, in the
+/// configured `display_timezone` and `display_time_format`. An invalid format
+/// falls back to the default rather than failing the tool.
+fn format_timestamp(time: std::time::SystemTime, config: &AppConfig) -> String {
+    use chrono::format::{Item, StrftimeItems};
+
+    let valid = !StrftimeItems::new(&config.display_time_format).any(|item| matches!(item, Item::Error));
+    let format = if valid { config.display_time_format.as_str() } else { crate::config::DEFAULT_TIME_FORMAT };
+    let utc: chrono::DateTime<chrono::Utc> = time.into();
+    match display_offset(config.display_timezone.as_deref()) {
+        Some(offset) => utc.with_timezone(&offset).format(format).to_string(),
+        None => utc.with_timezone(&chrono::Local).format(format).to_string(),
+    }
+}
+
+/// The fixed offset named by `display_timezone` ("UTC", "+05:30", "-0800" or "+09"),
+/// or `None` for local time, including when the value can't be parsed
+fn display_offset(timezone: Option<&str>) -> Option<chrono::FixedOffset> {
+    let timezone = timezone?.trim();
+    if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return chrono::FixedOffset::east_opt(0);
+    }
+    let sign = match timezone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let rest = &timezone[1..];
+    let (hours, minutes) = match rest.split_once(':') {
+        Some(parts) => parts,
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    chrono::FixedOffset::east_opt(sign * seconds)
+}
+
+/// Hex-encoded SHA-256 of a file, streamed in chunks so large files aren't loaded at once
+fn sha256_file(path: &std::path::Path) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Optimistic concurrency check for writes: fail with a conflict unless the file
+/// on disk still has the hash the agent last saw
+fn check_expected_sha256(path: &std::path::Path, expected: Option<&str>, display: &str) -> Result<(), ErrorData> {
+    let Some(expected) = expected else { return Ok(()) };
+
+    if !path.is_file() {
+        return Err(ErrorData::invalid_params(
+            format!("Conflict: expected_sha256 was given but {} does not exist", display),
+            None,
+        ));
+    }
+    let actual = sha256_file(path).map_err(|e| ErrorData::internal_error(format!("Failed to hash file: {}", e), None))?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(ErrorData::invalid_params(
+            format!(
+                "Conflict: {} has changed since it was read (expected SHA-256 {}, found {}). Re-read the file and retry.",
+                display, expected.trim(), actual
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// One-line summary of a file for search previews: size, modified time and,
+/// for text files, the first non-empty line (shortened)
+fn file_preview(path: &std::path::Path, config: &AppConfig) -> String {
+    const MAX_PREVIEW_CHARS: usize = 120;
+
+    let meta = fs::metadata(path).ok();
+    let size = meta.as_ref().map(|m| format_size(m.len())).unwrap_or_else(|| "?".to_string());
+    let modified = meta
+        .and_then(|m| m.modified().ok())
+        .map(|t| format_timestamp(t, config))
+        .unwrap_or_else(|| "?".to_string());
+
+    let mut head = Vec::new();
+    let first_line = fs::File::open(path)
+        .and_then(|f| f.take(4096).read_to_end(&mut head))
+        .ok()
+        .filter(|_| !head.contains(&0))
+        .and_then(|_| {
+            String::from_utf8_lossy(&head)
+                .lines()
+                .map(str::trim)
+                .find(|l| !l.is_empty())
+                .map(|l| {
+                    if l.chars().count() > MAX_PREVIEW_CHARS {
+                        format!("{}…", l.chars().take(MAX_PREVIEW_CHARS).collect::<String>())
+                    } else {
+                        l.to_string()
+                    }
+                })
+        });
+
+    match first_line {
+        Some(line) => format!("[{}, modified {}] {}", size, modified, line),
+        None => format!("[{}, modified {}]", size, modified),
+    }
+}
+
+/// How a folder's permission is shown to agents
+fn permission_label(permission: &crate::config::Permission) -> &'static str {
+    match permission {
+        crate::config::Permission::ReadOnly => "read-only",
+        crate::config::Permission::ReadWrite => "read-write",
+        crate::config::Permission::CreateOnly => "create-only",
+        crate::config::Permission::AppendOnly => "append-only",
+    }
+}
+
+/// The shared folder a listing belongs to, e.g. `Folder: /home/me/docs (read-only)`
+fn folder_line(folder: &crate::config::SharedFolder) -> String {
+    format!("Folder: {} ({})", folder.path, permission_label(&folder.permission))
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 { format!("{} B", bytes) }
+    else if bytes < 1024 * 1024 { format!("{:.1} KB", bytes as f64 / 1024.0) }
+    else if bytes < 1024 * 1024 * 1024 { format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)) }
     else { format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0)) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{params, text_of, TestSandbox};
+
+    #[tokio::test]
+    async fn test_write_then_read_round_trip() {
+        let sandbox = TestSandbox::new();
+        let path = sandbox.path("notes/todo.md");
+
+        let written = sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": path, "content": "- ship it\n" })))
+            .await
+            .unwrap();
+        assert!(text_of(&written).contains("Successfully wrote"));
+
+        let read = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&read), "- ship it\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_denied_in_read_only_folder() {
+        let sandbox = TestSandbox::with_permission(crate::config::Permission::ReadOnly);
+        let err = sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("a.txt"), "content": "x" })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("read-only"));
+        assert!(!sandbox.root.join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_patch_file_search_and_line_replace() {
+        let sandbox = TestSandbox::new();
+        let file = sandbox.write("main.rs", "fn old() {}\nlet a = 1;\nlet b = 2;\n");
+
+        sandbox
+            .server
+            .patch_file(params(serde_json::json!({
+                "path": file.to_string_lossy(),
+                "search_replace": [{ "search": "old", "replace": "new" }],
+                "line_replace": [{ "start_line": 3, "end_line": 3, "content": "let b = 3;" }],
+            })))
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn new() {}\nlet a = 1;\nlet b = 3;\n");
+    }
+
+    #[tokio::test]
+    async fn test_zip_then_unzip_round_trip() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("src/lib.rs", "pub fn a() {}");
+        sandbox.write("src/nested/mod.rs", "pub fn b() {}");
+        let archive = sandbox.path("out/src.zip");
+
+        let zipped = sandbox
+            .server
+            .zip_files(params(serde_json::json!({ "paths": [sandbox.path("src")], "output_path": archive })))
+            .await
+            .unwrap();
+        assert!(text_of(&zipped).contains("2 files"));
+
+        sandbox
+            .server
+            .unzip_files(params(serde_json::json!({ "archive_path": archive, "destination": sandbox.path("restored") })))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("restored/nested/mod.rs")).unwrap(), "pub fn b() {}");
+    }
+
+    #[tokio::test]
+    async fn test_zip_dry_run_lists_files_without_writing() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("src/lib.rs", "pub fn a() {}");
+        sandbox.write("src/nested/mod.rs", "pub fn b() {}");
+
+        let result = sandbox
+            .server
+            .zip_files(params(serde_json::json!({ "paths": [sandbox.path("src")], "output_path": sandbox.path("out/src.zip"), "dry_run": true })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+
+        assert!(text.contains("would create"), "{}", text);
+        assert!(text.contains("2 files (26 B uncompressed)"), "{}", text);
+        assert!(text.contains("lib.rs (13 B)"));
+        assert!(text.contains("nested/mod.rs"));
+        assert!(!sandbox.root.join("out").exists());
+    }
+
+    #[tokio::test]
+    async fn test_unzip_dry_run_lists_entries_without_writing() {
+        let sandbox = TestSandbox::new();
+        let archive = sandbox.root.join("in.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, body) in [("a.txt", "alpha"), ("docs/b.txt", "bravo!")] {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(body.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let result = sandbox
+            .server
+            .unzip_files(params(serde_json::json!({ "archive_path": sandbox.path("in.zip"), "destination": sandbox.path("out"), "dry_run": true })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+
+        assert!(text.contains("would extract"), "{}", text);
+        assert!(text.contains("a.txt (5 B)"));
+        assert!(text.contains("docs/b.txt (6 B)"));
+        assert!(!sandbox.root.join("out").exists());
+    }
+
+    #[tokio::test]
+    async fn test_unzip_skips_ignored_and_protected_entries() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".mcpignore", "secrets/\n");
+        let archive = sandbox.root.join("in.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, body) in [("a.txt", "alpha"), (".mcpignore", ""), ("secrets/key.txt", "hunter2")] {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(body.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let result = sandbox
+            .server
+            .unzip_files(params(serde_json::json!({ "archive_path": sandbox.path("in.zip"), "destination": sandbox.path("") })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+
+        assert!(text.contains("Extracted 1 files"), "{}", text);
+        assert!(text.contains("Skipped 2 entries"), "{}", text);
+        assert_eq!(fs::read_to_string(sandbox.root.join("a.txt")).unwrap(), "alpha");
+        assert_eq!(fs::read_to_string(sandbox.root.join(".mcpignore")).unwrap(), "secrets/\n");
+        assert!(!sandbox.root.join("secrets").exists());
+    }
+
+    #[tokio::test]
+    async fn test_zip_exclude_patterns_skip_matching_files() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("proj/src/main.rs", "fn main() {}");
+        sandbox.write("proj/debug.log", "noise");
+        sandbox.write("proj/src/trace.log", "noise");
+        sandbox.write("proj/target/debug/app", "binary");
+        let archive = sandbox.path("proj.zip");
+
+        let result = sandbox
+            .server
+            .zip_files(params(serde_json::json!({
+                "paths": [sandbox.path("proj")],
+                "output_path": archive,
+                "exclude": ["*.log", "target/**"],
+            })))
+            .await
+            .unwrap();
+        assert!(text_of(&result).contains("1 files"), "{}", text_of(&result));
+        assert!(text_of(&result).contains("3 excluded by pattern"));
+
+        let names: Vec<String> = zip::ZipArchive::new(fs::File::open(sandbox.root.join("proj.zip")).unwrap())
+            .unwrap()
+            .file_names()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(names, vec!["src/main.rs"]);
+
+        let err = sandbox
+            .server
+            .zip_files(params(serde_json::json!({ "paths": [sandbox.path("proj")], "output_path": archive, "exclude": ["[oops"] })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Invalid exclude pattern"));
+    }
+
+    #[tokio::test]
+    async fn test_zip_include_extensions_keeps_only_source_files() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("proj/src/main.rs", "fn main() {}");
+        sandbox.write("proj/src/gen.RS", "// generated");
+        sandbox.write("proj/README.md", "# proj");
+        sandbox.write("proj/Cargo.toml", "[package]");
+        sandbox.write("proj/build/out.rs", "// build output");
+
+        sandbox
+            .server
+            .zip_files(params(serde_json::json!({
+                "paths": [sandbox.path("proj")],
+                "output_path": sandbox.path("src.zip"),
+                "include_extensions": [".rs"],
+                "exclude": ["build/**"],
+            })))
+            .await
+            .unwrap();
+
+        let mut names: Vec<String> = zip::ZipArchive::new(fs::File::open(sandbox.root.join("src.zip")).unwrap())
+            .unwrap()
+            .file_names()
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["src/gen.RS", "src/main.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_grep_max_depth_excludes_deeper_files() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("top.txt", "needle");
+        sandbox.write("a/b/deep.txt", "needle");
+
+        let shallow = sandbox
+            .server
+            .grep_content(params(serde_json::json!({
+                "pattern": "needle",
+                "root_path": sandbox.root.to_string_lossy(),
+                "max_depth": 1,
+            })))
+            .await
+            .unwrap();
+        let text = text_of(&shallow);
+        assert!(text.contains("top.txt"));
+        assert!(!text.contains("deep.txt"));
+
+        let default = sandbox
+            .server
+            .grep_content(params(serde_json::json!({
+                "pattern": "needle",
+                "root_path": sandbox.root.to_string_lossy(),
+            })))
+            .await
+            .unwrap();
+        assert!(text_of(&default).contains("deep.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_grep_skips_invalid_utf8_lines_not_files() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("mixed.log", b"start\n\xff\xfe garbage\nneedle after bad bytes\n".to_vec());
+        sandbox.write("logo.png", b"\x89PNG\nneedle in binary\n".to_vec());
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let result = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains("mixed.log:3:needle after bad bytes"));
+        assert!(!text.contains("logo.png"));
+
+        let forced = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root, "force_text": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&forced).contains("logo.png:2:needle in binary"));
+    }
+
+    #[tokio::test]
+    async fn test_redact_home_leaves_file_contents_alone() {
+        let home = dirs::home_dir().unwrap().to_string_lossy().to_string();
+        let sandbox = TestSandbox::new();
+        let body = format!("notes live in {}/notes.txt\n", home);
+        sandbox.write("paths.txt", &body);
+
+        crate::activity::set_redact_home(true);
+        let read = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("paths.txt") })))
+            .await
+            .unwrap();
+        let grep = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "root_path": sandbox.root.to_string_lossy(), "pattern": "notes live" })))
+            .await
+            .unwrap();
+        crate::activity::set_redact_home(false);
+
+        assert_eq!(text_of(&read), body);
+        assert!(text_of(&grep).contains(&format!("{}/notes.txt", home)), "{}", text_of(&grep));
+    }
+
+    #[tokio::test]
+    async fn test_read_file_peek_ignores_size_limit() {
+        let sandbox = TestSandbox::with_config(|c| c.max_file_size_mb = 1);
+        let big = "abcdefghij".repeat(150_000);
+        sandbox.write("big.log", &big);
+        let path = sandbox.path("big.log");
+
+        let soft = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap();
+        assert_eq!(soft.is_error, Some(true));
+        assert!(text_of(&soft).contains("File too large"));
+
+        let peek = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": path, "peek_bytes": 16 })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&peek), "[Peek: first 16 of 1500000 bytes]\nabcdefghijabcdef");
+    }
+
+    #[test]
+    fn test_describe_peek() {
+        assert_eq!(describe_peek(b"hello"), "hello");
+        // "é" is two bytes; a peek that splits it keeps the complete prefix
+        assert_eq!(describe_peek(&"aé".as_bytes()[..2]), "a");
+        assert_eq!(describe_peek(&[0xff, 0x00, 0x10]), "[Binary content as base64]\n/wAQ");
+    }
+
+    #[test]
+    fn test_parse_env_quotes_and_comments() {
+        let text = "# database\n\
+            export DB_HOST=localhost\n\
+            DB_PASS=\"p@ss # not a comment\"\n\
+            GREETING='hello $USER'\n\
+            ESCAPED=\"line1\\nline2 \\\"q\\\"\"\n\
+            PORT = 5432 # inline comment\n\
+            URL=http://host/#anchor\n\
+            EMPTY=\n\
+            not a pair\n\
+            PORT=6543\n";
+        let vars = parse_env(text);
+        let get = |k: &str| vars.iter().find(|(key, _)| key == k).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("DB_HOST"), Some("localhost"));
+        assert_eq!(get("DB_PASS"), Some("p@ss # not a comment"));
+        assert_eq!(get("GREETING"), Some("hello $USER"));
+        assert_eq!(get("ESCAPED"), Some("line1\nline2 \"q\""));
+        assert_eq!(get("URL"), Some("http://host/#anchor"));
+        assert_eq!(get("EMPTY"), Some(""));
+        // The later assignment wins but keeps the first position
+        assert_eq!(get("PORT"), Some("6543"));
+        let keys: Vec<&str> = vars.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["DB_HOST", "DB_PASS", "GREETING", "ESCAPED", "PORT", "URL", "EMPTY"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_env_redacts_values_by_default() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".env", "API_KEY=secret\n");
+        let path = sandbox.path(".env");
+
+        let redacted = sandbox
+            .server
+            .read_env(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&redacted)).unwrap();
+        assert_eq!(json["vars"]["API_KEY"], serde_json::Value::Null);
+        assert!(!text_of(&redacted).contains("secret"));
+
+        let full = sandbox
+            .server
+            .read_env(params(serde_json::json!({ "path": path, "include_values": true })))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&full)).unwrap();
+        assert_eq!(json["vars"]["API_KEY"], "secret");
+    }
+
+    #[tokio::test]
+    async fn test_skip_hidden_in_recursive_listing_and_grep() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("visible.txt", "needle");
+        sandbox.write(".secret.txt", "needle");
+        sandbox.write(".config/settings.txt", "needle");
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "recursive": true })))
+            .await
+            .unwrap();
+        let text = text_of(&listing);
+        assert!(text.contains("visible.txt"));
+        assert!(!text.contains(".secret.txt"));
+        assert!(!text.contains(".config"));
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "recursive": true, "skip_hidden": false })))
+            .await
+            .unwrap();
+        let text = text_of(&listing);
+        assert!(text.contains(".secret.txt"));
+        assert!(text.contains("settings.txt"));
+
+        let grep = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root })))
+            .await
+            .unwrap();
+        assert!(text_of(&grep).starts_with("Found 1 match(es)"));
+
+        let grep = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root, "skip_hidden": false })))
+            .await
+            .unwrap();
+        assert!(text_of(&grep).starts_with("Found 3 match(es)"));
+    }
+
+    #[test]
+    fn test_cap_contents_truncates_with_footer() {
+        let small = cap_contents(vec![Content::text("hello")], 1024);
+        assert_eq!(small.len(), 1);
+        assert_eq!(small[0].as_text().unwrap().text, "hello");
+
+        let capped = cap_contents(vec![Content::text("abcdef"), Content::text("ghij")], 4);
+        assert_eq!(capped.len(), 1);
+        assert_eq!(capped[0].as_text().unwrap().text, "abcd\n[output truncated, 6 bytes omitted]");
+
+        // Never splits a multi-byte character
+        let capped = cap_contents(vec![Content::text("aé")], 2);
+        assert_eq!(capped[0].as_text().unwrap().text, "a\n[output truncated, 2 bytes omitted]");
+    }
+
+    #[tokio::test]
+    async fn test_read_file_refuses_base64_that_would_be_truncated() {
+        let sandbox = TestSandbox::with_config(|c| c.max_response_bytes = 4096);
+        sandbox.write("small.png", vec![7u8; 1000]);
+        sandbox.write("large.png", vec![7u8; 4000]);
+
+        let small = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("small.png") })))
+            .await
+            .unwrap();
+        let text = text_of(&small);
+        let encoded = text.split_once(";base64,").unwrap().1;
+        assert_eq!(general_purpose::STANDARD.decode(encoded).unwrap(), vec![7u8; 1000]);
+
+        let large = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("large.png") })))
+            .await
+            .unwrap();
+        assert_eq!(large.is_error, Some(true));
+        let error = &large.structured_content.as_ref().unwrap()["error"];
+        assert_eq!(error["code"], "file_too_large");
+        assert_eq!(error["size_bytes"], 4000);
+        assert_eq!(error["limit_bytes"], 4096);
+    }
+
+    #[tokio::test]
+    async fn test_move_into_keeps_name_and_respects_overwrite() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("report.txt", "new");
+        sandbox.write("archive/report.txt", "old");
+        sandbox.write("notes.txt", "notes");
+
+        let moved = sandbox
+            .server
+            .move_into(params(serde_json::json!({ "source": sandbox.path("notes.txt"), "dest_dir": sandbox.path("fresh/dir") })))
+            .await
+            .unwrap();
+        assert!(text_of(&moved).starts_with("Moved"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("fresh/dir/notes.txt")).unwrap(), "notes");
+        assert!(!sandbox.root.join("notes.txt").exists());
+
+        let err = sandbox
+            .server
+            .move_into(params(serde_json::json!({ "source": sandbox.path("report.txt"), "dest_dir": sandbox.path("archive") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("already exists"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("archive/report.txt")).unwrap(), "old");
+
+        sandbox
+            .server
+            .move_into(params(serde_json::json!({ "source": sandbox.path("report.txt"), "dest_dir": sandbox.path("archive"), "overwrite": true })))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("archive/report.txt")).unwrap(), "new");
+        assert!(!sandbox.root.join("report.txt").exists());
+
+        // A directory can't take the file's place, and the file is left alone
+        sandbox.write("report.txt/inner.txt", "dir");
+        let err = sandbox
+            .server
+            .move_into(params(serde_json::json!({ "source": sandbox.path("report.txt"), "dest_dir": sandbox.path("archive"), "overwrite": true })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("with a directory"), "{}", err.message);
+        assert_eq!(fs::read_to_string(sandbox.root.join("archive/report.txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_replace_with_copy_keeps_destination_on_failure() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (src, dst) = (tmp.path().join("src.txt"), tmp.path().join("dst.txt"));
+        fs::write(&dst, "old").unwrap();
+
+        assert!(replace_with_copy(&src, &dst).is_err());
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "old");
+
+        fs::write(&src, "new").unwrap();
+        replace_with_copy(&src, &dst).unwrap();
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "new");
+        // Only the source and the replaced file remain; the temporary copy is gone
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_copy_into_file() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("notes.txt", "hello");
+
+        let copied = sandbox
+            .server
+            .copy_into(params(serde_json::json!({ "source": sandbox.path("notes.txt"), "dest_dir": sandbox.path("backup") })))
+            .await
+            .unwrap();
+        assert!(text_of(&copied).ends_with("(5 B)"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("backup/notes.txt")).unwrap(), "hello");
+        assert!(sandbox.root.join("notes.txt").exists());
+
+        let err = sandbox
+            .server
+            .copy_into(params(serde_json::json!({ "source": sandbox.path("notes.txt"), "dest_dir": sandbox.path("backup") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("already exists"));
+    }
+
+    #[tokio::test]
+    async fn test_copy_into_recursive_directory() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("project/src/main.rs", "fn main() {}");
+        sandbox.write("project/README.md", "# readme");
+
+        let err = sandbox
+            .server
+            .copy_into(params(serde_json::json!({ "source": sandbox.path("project"), "dest_dir": sandbox.path("copies") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("recursive=true"));
+
+        let copied = sandbox
+            .server
+            .copy_into(params(serde_json::json!({ "source": sandbox.path("project"), "dest_dir": sandbox.path("copies"), "recursive": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&copied).contains("2 file(s)"));
+        assert_eq!(
+            fs::read_to_string(sandbox.root.join("copies/project/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(fs::read_to_string(sandbox.root.join("copies/project/README.md")).unwrap(), "# readme");
+    }
+
+    #[test]
+    fn test_effective_page_size() {
+        assert_eq!(effective_page_size(None, 50, 100), 50);
+        assert_eq!(effective_page_size(Some(80), 50, 100), 80);
+        assert_eq!(effective_page_size(Some(500), 50, 100), 100);
+        assert_eq!(effective_page_size(Some(0), 50, 100), 1);
+        // A default above the maximum is clamped too
+        assert_eq!(effective_page_size(None, 300, 100), 100);
+        // Configured maximums can't exceed the hard ceiling
+        assert_eq!(effective_page_size(Some(5000), 50, 5000), PAGE_SIZE_CEILING);
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_honors_configured_page_sizes() {
+        let sandbox = TestSandbox::with_config(|c| {
+            c.default_page_size = 2;
+            c.max_page_size = 3;
+            c.max_tree_page_size = 4;
+        });
+        for i in 0..6 {
+            sandbox.write(&format!("file{}.txt", i), "x");
+        }
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root })))
+            .await
+            .unwrap();
+        assert!(text_of(&listing).contains("Page 1 of 3 (6 items)"));
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "page_size": 100 })))
+            .await
+            .unwrap();
+        assert!(text_of(&listing).contains("Page 1 of 2 (6 items)"));
+
+        let tree = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "recursive": true, "page_size": 100 })))
+            .await
+            .unwrap();
+        assert!(text_of(&tree).contains("page 1/2"));
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_sort_by_size_and_modified() {
+        let sandbox = TestSandbox::new();
+        let small = sandbox.write("a_small.txt", "x");
+        let large = sandbox.write("b_large.txt", "x".repeat(100));
+        let medium = sandbox.write("c_medium.txt", "x".repeat(10));
+        fs::create_dir(sandbox.root.join("z_dir")).unwrap();
+
+        let base = std::time::SystemTime::now() - Duration::from_secs(3600);
+        for (path, offset) in [(&medium, 0), (&small, 60), (&large, 120)] {
+            let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+            file.set_modified(base + Duration::from_secs(offset)).unwrap();
+        }
+
+        let order = |text: &str, names: &[&str]| {
+            let positions: Vec<usize> = names.iter().map(|n| text.find(n).unwrap()).collect();
+            positions.windows(2).all(|w| w[0] < w[1])
+        };
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "sort_by": "size", "sort_desc": true })))
+            .await
+            .unwrap();
+        assert!(order(&text_of(&listing), &["z_dir", "b_large", "c_medium", "a_small"]));
+
+        let listing = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "sort_by": "modified", "dirs_first": false, "sort_desc": true })))
+            .await
+            .unwrap();
+        let text = text_of(&listing);
+        assert!(order(&text, &["b_large", "a_small", "c_medium"]));
+        // The directory was created just now, so it is the newest entry
+        assert!(order(&text, &["z_dir", "b_large"]));
+
+        let err = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "sort_by": "color" })))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_recursive_listing_stops_at_entry_cap() {
+        let sandbox = TestSandbox::with_config(|c| c.max_tree_entries = 100);
+        for dir in 0..20 {
+            for file in 0..20 {
+                sandbox.write(&format!("d{:02}/f{:02}.txt", dir, file), "x");
+            }
+        }
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let tree = sandbox
+            .server
+            .list_directory(params(serde_json::json!({ "path": root, "recursive": true })))
+            .await
+            .unwrap();
+        let text = text_of(&tree);
+        assert!(text.contains("100 items listed (tree truncated: stopped after 100 entries"));
+        assert!(!text.contains("d19"));
+
+        let small = TestSandbox::new();
+        small.write("a/b.txt", "x");
+        let tree = small
+            .server
+            .list_directory(params(serde_json::json!({ "path": small.root.to_string_lossy(), "recursive": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&tree).contains("2 items total"));
+    }
+
+    #[test]
+    fn test_strip_comments_rust() {
+        let source = "//! Crate docs\n\
+            use std::fmt; // trailing\n\
+            \n\
+            /* block\n   spanning lines */\n\
+            fn main<'a>(s: &'a str) {\n\
+            \x20   let url = \"http://example.com/*not*/\";\n\
+            \x20   let q = '\"'; let slash = '/';\n\
+            \x20   println!(\"{}\", url); /* inline */ let x = 1;\n\
+            }\n";
+        let syntax = comment_syntax("main.rs").unwrap();
+        assert_eq!(
+            strip_comments(source, &syntax),
+            "use std::fmt;\n\
+            \n\
+            fn main<'a>(s: &'a str) {\n\
+            \x20   let url = \"http://example.com/*not*/\";\n\
+            \x20   let q = '\"'; let slash = '/';\n\
+            \x20   println!(\"{}\", url);  let x = 1;\n\
+            }\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_comments_python() {
+        let source = "# module comment\n\
+            def greet(name):\n\
+            \x20   \"\"\"Docstring with # hash.\"\"\"\n\
+            \x20   return f\"#{name}\"  # trailing comment\n\
+            \n\
+            \x20   # indented comment\n\
+            x = 'it''s'\n";
+        let syntax = comment_syntax("app.py").unwrap();
+        assert_eq!(
+            strip_comments(source, &syntax),
+            "def greet(name):\n\
+            \x20   \"\"\"Docstring with # hash.\"\"\"\n\
+            \x20   return f\"#{name}\"\n\
+            \n\
+            x = 'it''s'\n"
+        );
+        assert!(comment_syntax("notes.txt").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_strip_comments() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("lib.rs", "// header\nfn a() {} // note\n");
+        sandbox.write("notes.txt", "// not code\n");
+
+        let read = |path: String| {
+            sandbox
+                .server
+                .read_file(params(serde_json::json!({ "path": path, "strip_comments": true })))
+        };
+        assert_eq!(text_of(&read(sandbox.path("lib.rs")).await.unwrap()), "fn a() {}\n");
+        assert_eq!(text_of(&read(sandbox.path("notes.txt")).await.unwrap()), "// not code\n");
+    }
+
+    #[tokio::test]
+    async fn test_extra_extension_becomes_readable() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("main.tf", "resource \"x\" {}");
+        let path = sandbox.path("main.tf");
+
+        let err = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Unsupported file type"));
+
+        Arc::make_mut(&mut *sandbox.server.config.write().await).extra_extensions = vec!["tf".to_string()];
+        let read = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&read), "resource \"x\" {}");
+    }
+
+    #[tokio::test]
+    async fn test_grep_max_per_file_spreads_results() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("a_noisy.log", "hit\n".repeat(500));
+        sandbox.write("b_quiet.txt", "hit\nmiss\nhit\n");
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let spread = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "hit", "root_path": root, "max_results": 10, "max_per_file": 3 })))
+            .await
+            .unwrap();
+        let text = text_of(&spread);
+        assert!(text.starts_with("Found 5 match(es)"));
+        assert!(text.contains("a_noisy.log: … more matches not shown (max_per_file=3)"));
+        assert!(text.contains("b_quiet.txt:1:hit"));
+        assert!(text.contains("b_quiet.txt:3:hit"));
+        assert!(!text.contains("b_quiet.txt: …"));
+    }
+
+    #[test]
+    fn test_parse_hex_pattern() {
+        assert_eq!(parse_hex_pattern("deadBEEF").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(parse_hex_pattern("0x89 50 4e 47").unwrap(), vec![0x89, 0x50, 0x4e, 0x47]);
+        assert!(parse_hex_pattern("abc").is_err());
+        assert!(parse_hex_pattern("zz").is_err());
+        assert!(parse_hex_pattern(" ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grep_binary_reports_offsets_and_context() {
+        let sandbox = TestSandbox::new();
+        let mut data = vec![0u8; 40];
+        data[3..7].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        data[30..34].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        sandbox.write("blob.bin", &data);
+        sandbox.write("other.dat", [1u8, 2, 3]);
+
+        let found = sandbox
+            .server
+            .grep_binary(params(serde_json::json!({ "pattern": "de ad be ef", "path": sandbox.path("blob.bin"), "context_bytes": 2 })))
+            .await
+            .unwrap();
+        let text = text_of(&found);
+        assert!(text.starts_with("Found 2 match(es)"));
+        assert!(text.contains("@0x00000003: 00 00 [de ad be ef] 00 00"));
+        assert!(text.contains("@0x0000001e: 00 00 [de ad be ef] 00 00"));
+
+        let found = sandbox
+            .server
+            .grep_binary(params(serde_json::json!({ "pattern": "0203", "root_path": sandbox.root.to_string_lossy() })))
+            .await
+            .unwrap();
+        let text = text_of(&found);
+        assert!(text.contains("other.dat@0x00000001: 01 [02 03]"));
+
+        let err = sandbox
+            .server
+            .grep_binary(params(serde_json::json!({ "pattern": "00" })))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_list_tree_nests_and_respects_depth() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("top.txt", "abc");
+        sandbox.write("a/mid.txt", "x");
+        sandbox.write("a/b/deep.txt", "x");
+        sandbox.write(".hidden/h.txt", "x");
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let result = sandbox
+            .server
+            .list_tree(params(serde_json::json!({ "path": root, "max_depth": 2 })))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+        assert_eq!(json["truncated"], false);
+        assert_eq!(json["nodes"], 4);
+
+        let top = json["tree"]["children"].as_array().unwrap();
+        let names: Vec<_> = top.iter().map(|n| n["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["a", "top.txt"]);
+        assert_eq!(top[1]["type"], "file");
+        assert_eq!(top[1]["size"], 3);
+
+        let a = &top[0]["children"];
+        assert_eq!(a[0]["name"], "b");
+        assert_eq!(a[1]["name"], "mid.txt");
+        // b sits at the depth limit: listed, but not descended into
+        assert!(a[0].get("children").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_tree_caps_total_nodes() {
+        let sandbox = TestSandbox::with_config(|c| c.max_tree_entries = 3);
+        for i in 0..5 {
+            sandbox.write(&format!("d/f{}.txt", i), "x");
+        }
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let result = sandbox
+            .server
+            .list_tree(params(serde_json::json!({ "path": root })))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["nodes"], 3);
+        assert_eq!(json["tree"]["children"][0]["children"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expected_sha256_guards_writes() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("doc.txt", "hello");
+        let path = sandbox.path("doc.txt");
+        let hash = sha256_file(&sandbox.root.join("doc.txt")).unwrap();
+        let stale = "0".repeat(64);
+
+        let err = sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": path, "content": "clobber", "expected_sha256": stale })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Conflict"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("doc.txt")).unwrap(), "hello");
+
+        let err = sandbox
+            .server
+            .patch_file(params(serde_json::json!({
+                "path": path,
+                "search_replace": [{ "search": "hello", "replace": "bye" }],
+                "expected_sha256": stale,
+            })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Conflict"));
+
+        sandbox
+            .server
+            .patch_file(params(serde_json::json!({
+                "path": path,
+                "search_replace": [{ "search": "hello", "replace": "bye" }],
+                "expected_sha256": hash,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("doc.txt")).unwrap(), "bye");
+
+        let hash = sha256_file(&sandbox.root.join("doc.txt")).unwrap();
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": path, "content": "final", "expected_sha256": hash.to_uppercase() })))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("doc.txt")).unwrap(), "final");
+    }
+
+    #[tokio::test]
+    async fn test_concat_files_in_order_with_separator() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("part1.txt", "alpha");
+        sandbox.write("part2.txt", "beta");
+        sandbox.write("part3.txt", "gamma");
+        let out = sandbox.path("joined/all.txt");
+        let paths = [sandbox.path("part1.txt"), sandbox.path("part3.txt"), sandbox.path("part2.txt")];
+
+        let result = sandbox
+            .server
+            .concat_files(params(serde_json::json!({ "paths": paths, "output_path": out, "separator": "\n--\n" })))
+            .await
+            .unwrap();
+        assert!(text_of(&result).contains("Concatenated 3 file(s)"));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "alpha\n--\ngamma\n--\nbeta");
+
+        // Existing output needs overwrite=true
+        let err = sandbox
+            .server
+            .concat_files(params(serde_json::json!({ "paths": [paths[0].clone()], "output_path": out })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("already exists"));
+
+        sandbox
+            .server
+            .concat_files(params(serde_json::json!({ "paths": [paths[0].clone()], "output_path": out, "overwrite": true })))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "alpha");
+
+        // The output can't also be a source
+        let err = sandbox
+            .server
+            .concat_files(params(serde_json::json!({ "paths": [out.clone()], "output_path": out, "overwrite": true })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("also one of the sources"));
+    }
+
+    #[tokio::test]
+    async fn test_concat_files_denied_in_read_only_folder() {
+        let sandbox = TestSandbox::with_permission(crate::config::Permission::ReadOnly);
+        sandbox.write("a.txt", "a");
+
+        let result = sandbox
+            .server
+            .concat_files(params(serde_json::json!({ "paths": [sandbox.path("a.txt")], "output_path": sandbox.path("out.txt") })))
+            .await;
+        assert!(result.is_err());
+        assert!(!sandbox.root.join("out.txt").exists());
+    }
+
+    #[test]
+    fn test_failed_concat_keeps_existing_output() {
+        let sandbox = TestSandbox::new();
+        let a = sandbox.write("a.txt", "a");
+        let output = sandbox.write("out.txt", "original");
+
+        let result = concat_into(&[a, sandbox.root.join("vanished.txt")], &output, b"");
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "original");
+        let names: Vec<_> = fs::read_dir(&sandbox.root).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_reports_utf8_error_location() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("mixed.txt", b"first line\nsecond \xff line\nthird\n");
+
+        let err = sandbox
+            .server
+            .read_lines(params(serde_json::json!({ "path": sandbox.path("mixed.txt") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("invalid byte 0xff at byte offset 18 (line 2, column 8)"), "{}", err.message);
+        assert!(err.message.contains("convert_file"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_created_file_mode_applies_to_new_files_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let sandbox = TestSandbox::with_config(|c| c.created_file_mode = Some("600".to_string()));
+        let mode_of = |rel: &str| fs::metadata(sandbox.root.join(rel)).unwrap().permissions().mode() & 0o777;
+
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("secret.env"), "content": "KEY=1" })))
+            .await
+            .unwrap();
+        assert_eq!(mode_of("secret.env"), 0o600);
+
+        // Existing files keep their mode when overwritten
+        sandbox.write("shared.txt", "old");
+        fs::set_permissions(sandbox.root.join("shared.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("shared.txt"), "content": "new" })))
+            .await
+            .unwrap();
+        assert_eq!(mode_of("shared.txt"), 0o644);
+    }
+
+    #[tokio::test]
+    async fn test_search_files_preview() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("notes/plan.md", "\n# Launch plan\nsecond line\n");
+
+        let plain = sandbox
+            .server
+            .search_files(params(serde_json::json!({ "pattern": "*.md" })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&plain), sandbox.path("notes/plan.md"));
+
+        let previewed = sandbox
+            .server
+            .search_files(params(serde_json::json!({ "pattern": "*.md", "preview": true })))
+            .await
+            .unwrap();
+        let text = text_of(&previewed);
+        assert!(text.starts_with(&sandbox.path("notes/plan.md")));
+        assert!(text.contains("] # Launch plan"), "{}", text);
+        assert!(text.contains("27 B, modified"), "{}", text);
+        assert!(!text.contains("second line"));
+    }
+
+    #[tokio::test]
+    async fn test_writable_reported_by_folder_permission() {
+        for (permission, writable) in [
+            (crate::config::Permission::ReadOnly, false),
+            (crate::config::Permission::ReadWrite, true),
+        ] {
+            let sandbox = TestSandbox::with_permission(permission);
+            sandbox.write("a.txt", "x");
+
+            let info = sandbox
+                .server
+                .get_file_info(params(serde_json::json!({ "path": sandbox.path("a.txt") })))
+                .await
+                .unwrap();
+            assert!(text_of(&info).contains(&format!("Writable: {}\n", writable)));
+
+            let exists = sandbox
+                .server
+                .path_exists(params(serde_json::json!({ "path": sandbox.path("missing.txt") })))
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_str(&text_of(&exists)).unwrap();
+            assert_eq!(json["exists"], false);
+            assert_eq!(json["type"], serde_json::Value::Null);
+            assert_eq!(json["writable"], writable);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_patch_files_applies_all() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("a.rs", "fn old_name() {}\n");
+        sandbox.write("b.rs", "old_name();\nold_name();\n");
+
+        let result = sandbox
+            .server
+            .patch_files(params(serde_json::json!({ "files": [
+                { "path": sandbox.path("a.rs"), "search_replace": [{ "search": "old_name", "replace": "new_name" }] },
+                { "path": sandbox.path("b.rs"), "search_replace": [{ "search": "old_name", "replace": "new_name" }] },
+            ]})))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains("1 replacement(s)"));
+        assert!(text.contains("2 replacement(s)"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("a.rs")).unwrap(), "fn new_name() {}\n");
+        assert_eq!(fs::read_to_string(sandbox.root.join("b.rs")).unwrap(), "new_name();\nnew_name();\n");
+    }
+
+    #[tokio::test]
+    async fn test_patch_files_writes_nothing_when_one_patch_fails() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("a.txt", "alpha");
+        sandbox.write("b.txt", "beta");
+
+        let err = sandbox
+            .server
+            .patch_files(params(serde_json::json!({ "files": [
+                { "path": sandbox.path("a.txt"), "search_replace": [{ "search": "alpha", "replace": "ALPHA" }] },
+                { "path": sandbox.path("b.txt"), "search_replace": [{ "search": "(", "replace": "x", "regex": true }] },
+            ]})))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("b.txt"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("a.txt")).unwrap(), "alpha");
+    }
+
+    #[tokio::test]
+    async fn test_patch_files_rejects_empty_search() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("a.txt", "alpha");
+
+        let err = sandbox
+            .server
+            .patch_files(params(serde_json::json!({ "files": [
+                { "path": sandbox.path("a.txt"), "search_replace": [{ "search": "", "replace": "x" }] },
+            ]})))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("search must not be empty"), "{}", err.message);
+        assert_eq!(fs::read_to_string(sandbox.root.join("a.txt")).unwrap(), "alpha");
+    }
+
+    #[test]
+    fn test_write_all_or_rollback_restores_written_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        fs::write(&first, "original").unwrap();
+
+        let plan = [
+            PlannedWrite { path: first.clone(), original: "original".to_string(), patched: "patched".to_string() },
+            // Parent directory doesn't exist, so this write fails
+            PlannedWrite { path: dir.path().join("gone/second.txt"), original: String::new(), patched: "x".to_string() },
+        ];
+        let (failed, _) = write_all_or_rollback(&plan).unwrap_err();
+        assert_eq!(failed, 1);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "original");
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_times_out() {
+        let ok = run_blocking("Quick work", Duration::from_secs(5), || 42).await.unwrap();
+        assert_eq!(ok, 42);
+
+        let err = run_blocking("Slow work", Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_secs(2));
+        })
+        .await
+        .unwrap_err();
+        assert!(err.message.contains("Slow work timed out"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_io_keeps_runtime_responsive() {
+        // #[tokio::test] runs on a single thread: if the slow work ran inline,
+        // the ticker below could not run at all until it finished
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        };
+
+        blocking_io(|| {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(())
+        })
+        .await
+        .unwrap();
+        ticker.abort();
+        assert!(ticks.load(Ordering::Relaxed) >= 5, "runtime was blocked during the I/O");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_tool_calls_all_complete() {
+        let sandbox = TestSandbox::new();
+        for i in 0..20 {
+            sandbox.write(&format!("src/f{}.txt", i), "needle\n".repeat(100));
+        }
+        let root = sandbox.root.to_string_lossy().to_string();
+
+        let (grep, read, zip) = tokio::join!(
+            sandbox.server.grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root }))),
+            sandbox.server.read_file(params(serde_json::json!({ "path": sandbox.path("src/f0.txt") }))),
+            sandbox.server.zip_files(params(serde_json::json!({ "paths": [sandbox.path("src")], "output_path": sandbox.path("out.zip") }))),
+        );
+        assert!(text_of(&grep.unwrap()).starts_with("Found 50 match(es)"));
+        assert_eq!(text_of(&read.unwrap()).lines().count(), 100);
+        assert!(text_of(&zip.unwrap()).contains("20 files"));
+    }
+
+    #[tokio::test]
+    async fn test_reload_during_grep_does_not_abort_it() {
+        let sandbox = TestSandbox::new();
+        for i in 0..300 {
+            sandbox.write(&format!("src/f{}.txt", i), "needle\n".repeat(200));
+        }
+        let root = sandbox.root.to_string_lossy().to_string();
+        let server = sandbox.server.clone();
+        let grep = tokio::spawn(async move {
+            server.grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root }))).await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // Remove the folder mid-walk. The swap must not wait for the grep to finish.
+        let mut emptied = (*sandbox.server.config_snapshot().await).clone();
+        emptied.folders.clear();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            *sandbox.server.config.write().await = Arc::new(emptied);
+        })
+        .await
+        .expect("reload waited on the running grep");
+
+        let result = grep.await.unwrap().expect("in-flight grep was aborted by the reload");
+        assert!(text_of(&result).starts_with("Found"));
+
+        let after = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("src/f0.txt") })))
+            .await;
+        assert!(after.is_err(), "new calls use the reloaded config");
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage_reports_sizes() {
+        let sandbox = TestSandbox::new();
+
+        let result = sandbox
+            .server
+            .disk_usage(params(serde_json::json!({ "path": sandbox.path("not-yet-written.bin") })))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+        let total = json["total_bytes"].as_u64().unwrap();
+        assert!(total > 0);
+        assert!(json["available_bytes"].as_u64().unwrap() <= total);
+        assert!(json["total"].as_str().unwrap().ends_with('B'));
+    }
+
+    #[test]
+    fn test_retry_with_backoff_retries_transient_errors_only() {
+        use std::io::{Error, ErrorKind};
+
+        let mut calls = 0;
+        let result = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+            if calls < 3 { Err(Error::from(ErrorKind::Interrupted)) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: std::io::Result<()> = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+            Err(Error::from(ErrorKind::WouldBlock))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: std::io::Result<()> = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+            Err(Error::from(ErrorKind::PermissionDenied))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_generate_and_verify_round_trip() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("a.txt", "alpha");
+        sandbox.write("sub/b.txt", "beta");
+        let root = sandbox.root.to_string_lossy().to_string();
+        let manifest_path = sandbox.path("manifest.json");
+
+        let generated = sandbox
+            .server
+            .generate_manifest(params(serde_json::json!({ "root_path": root, "output_path": manifest_path })))
+            .await
+            .unwrap();
+        assert!(text_of(&generated).contains("2 file(s)"));
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        assert_eq!(manifest["files"]["sub/b.txt"]["size"], 4);
+
+        let verify = |sandbox: &TestSandbox| {
+            let server = sandbox.server.clone();
+            let (root, manifest_path) = (root.clone(), manifest_path.clone());
+            async move {
+                let result = server
+                    .verify_manifest(params(serde_json::json!({ "root_path": root, "manifest_path": manifest_path })))
+                    .await
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(&text_of(&result)).unwrap()
+            }
+        };
+
+        let report = verify(&sandbox).await;
+        assert_eq!(report["intact"], true, "{}", report);
+
+        sandbox.write("a.txt", "ALPHA");
+        fs::remove_file(sandbox.root.join("sub/b.txt")).unwrap();
+        sandbox.write("c.txt", "new");
+        let report = verify(&sandbox).await;
+        assert_eq!(report["intact"], false);
+        assert_eq!(report["changed"], serde_json::json!(["a.txt"]));
+        assert_eq!(report["missing"], serde_json::json!(["sub/b.txt"]));
+        assert_eq!(report["added"], serde_json::json!(["c.txt"]));
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_delete_is_denied() {
+        let sandbox = TestSandbox::with_config(|c| {
+            c.require_confirmation_for_destructive = true;
+            c.confirmation_timeout_secs = 0;
+        });
+        let file = sandbox.write("keep.txt", "still here");
+
+        let err = sandbox
+            .server
+            .delete_file(params(serde_json::json!({ "path": sandbox.path("keep.txt") })))
+            .await
+            .unwrap_err();
+
+        assert!(err.message.contains("Denied"), "{}", err.message);
+        assert!(err.message.contains("Nothing was changed"));
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_hex_dump_format() {
+        let dump = hex_dump(b"OmniDrive\x00\x01\x02 hex\xffdump!");
+        assert_eq!(
+            dump,
+            "00000000  4f 6d 6e 69 44 72 69 76  65 00 01 02 20 68 65 78  |OmniDrive... hex|\n\
+             00000010  ff 64 75 6d 70 21                                 |.dump!|\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_file_hex_preview_for_unknown_binary() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("firmware.blob", vec![0xca, 0xfe, 0xba, 0xbe, 0x41]);
+
+        let err = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("firmware.blob") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("hex_preview"));
+
+        let result = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("firmware.blob"), "hex_preview": true, "hex_bytes": 4 })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.starts_with("[Hex preview: first 4 of 5 bytes]\n00000000  ca fe ba be "), "{}", text);
+        assert!(text.ends_with("|....|\n"));
+    }
+
+    #[test]
+    fn test_server_instructions_override() {
+        use rmcp::ServerHandler;
+
+        let default = TestSandbox::new().server.get_info().instructions.unwrap();
+        assert!(default.starts_with("OmniDrive provides secure access"));
+
+        let appended = TestSandbox::with_config(|c| {
+            c.server_instructions = Some("Never modify files under legal/.".to_string());
+        });
+        let text = appended.server.get_info().instructions.unwrap();
+        assert!(text.starts_with("OmniDrive provides secure access"));
+        assert!(text.ends_with("\n\nNever modify files under legal/."));
+
+        let replaced = TestSandbox::with_config(|c| {
+            c.server_instructions = Some("Acme policy: read only.".to_string());
+            c.replace_instructions = true;
+        });
+        assert_eq!(replaced.server.get_info().instructions.unwrap(), "Acme policy: read only.");
+    }
+
+    #[tokio::test]
+    async fn test_list_supported_types_groups_by_category() {
+        let sandbox = TestSandbox::with_config(|c| {
+            c.extra_extensions = vec!["tf".to_string()];
+            c.max_file_size_mb = 7;
+        });
+
+        let result = sandbox
+            .server
+            .list_supported_types(params(serde_json::json!({})))
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+
+        for (category, example) in [("code", "rs"), ("text", "md"), ("data", "json"), ("document", "pdf"), ("image", "png")] {
+            let list = json["categories"][category].as_array().unwrap_or_else(|| panic!("missing {}", category));
+            assert!(list.contains(&serde_json::json!(example)), "{} lacks {}", category, example);
+        }
+        assert!(json["extensionless"].as_array().unwrap().contains(&serde_json::json!("Makefile")));
+        assert_eq!(json["extra_extensions"], serde_json::json!(["tf"]));
+        assert_eq!(json["max_file_size_mb"], 7);
+    }
+
+    #[tokio::test]
+    async fn test_soft_error_shapes() {
+        let sandbox = TestSandbox::with_config(|c| c.max_file_size_mb = 1);
+        sandbox.write("big.json", "[".to_string() + &"1,".repeat(600_000) + "1]");
+        sandbox.write("ok.txt", "fine");
+
+        let too_large = sandbox
+            .server
+            .validate_json(params(serde_json::json!({ "path": sandbox.path("big.json") })))
+            .await
+            .unwrap();
+        assert_eq!(too_large.is_error, Some(true));
+        let error = &too_large.structured_content.as_ref().unwrap()["error"];
+        assert_eq!(error["code"], "file_too_large");
+        assert_eq!(error["recoverable"], true);
+        assert_eq!(error["limit_mb"], 1);
+        assert_eq!(error["size_bytes"], 1_200_003);
+        assert!(error["message"].as_str().unwrap().starts_with("File too large: 1.14 MB"));
+
+        let partial = sandbox
+            .server
+            .batch_read(params(serde_json::json!({ "paths": [sandbox.path("ok.txt"), sandbox.path("missing.txt")] })))
+            .await
+            .unwrap();
+        assert_eq!(partial.is_error, Some(false));
+        assert!(text_of(&partial).contains("fine"));
+        let error = &partial.structured_content.as_ref().unwrap()["error"];
+        assert_eq!(error["code"], "partial_batch");
+        assert_eq!(error["read"], 1);
+        assert_eq!(error["failed"][0]["code"], "not_a_file");
+
+        let none_read = sandbox
+            .server
+            .batch_read(params(serde_json::json!({ "paths": [sandbox.path("missing.txt")] })))
+            .await
+            .unwrap();
+        assert_eq!(none_read.is_error, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_query_json_pointers() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(
+            "config.json",
+            r#"{"servers": [{"name": "a", "port": 80}, {"name": "b", "port": 8080}], "paths": {"a/b": "slash", "x~y": "tilde"}, "big": [1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31,32,33,34,35,36,37,38,39,40]}"#,
+        );
+        let query = |pointer: &str, max_chars: Option<usize>| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "path": sandbox.path("config.json"), "pointer": pointer, "max_chars": max_chars });
+            async move { server.query_json(params(args)).await }
+        };
+        let json_of = |result: CallToolResult| serde_json::from_str::<serde_json::Value>(&text_of(&result)).unwrap();
+
+        let port = json_of(query("/servers/1/port", None).await.unwrap());
+        assert_eq!(port["value"], 8080);
+        assert_eq!(port["type"], "number");
+
+        let server = json_of(query("/servers/0", None).await.unwrap());
+        assert_eq!(server["value"], serde_json::json!({ "name": "a", "port": 80 }));
+        assert_eq!(server["type"], "object");
+
+        assert_eq!(json_of(query("/paths/a~1b", None).await.unwrap())["value"], "slash");
+        assert_eq!(json_of(query("/paths/x~0y", None).await.unwrap())["value"], "tilde");
+        assert_eq!(json_of(query("", None).await.unwrap())["type"], "object");
+
+        let big = json_of(query("/big", Some(100)).await.unwrap());
+        assert_eq!(big["truncated"], true);
+        assert_eq!(big["length"], 40);
+        assert!(big["value"].is_null());
+        assert!(big["preview"].as_str().unwrap().ends_with('…'));
+
+        let missing = query("/servers/5/port", None).await.unwrap_err();
+        assert!(missing.message.contains("\"/servers\""), "{}", missing.message);
+        assert!(query("servers", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_patch_json_adds_updates_and_removes_keys() {
+        let sandbox = TestSandbox::new();
+        let original = "{\n    \"name\": \"app\",\n    \"debug\": true,\n    \"db\": {\n        \"host\": \"localhost\",\n        \"port\": 5432\n    }\n}\n";
+        sandbox.write("config.json", original);
+        let patch = serde_json::json!({ "debug": null, "db": { "port": 6543, "user": "admin" }, "version": 2 });
+
+        let preview = sandbox
+            .server
+            .patch_json(params(serde_json::json!({ "path": sandbox.path("config.json"), "merge_patch": patch, "dry_run": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&preview).contains("[dry run] Would make 4 change(s)"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("config.json")).unwrap(), original);
+
+        let result = sandbox
+            .server
+            .patch_json(params(serde_json::json!({ "path": sandbox.path("config.json"), "merge_patch": patch })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        for change in ["removed /debug", "updated /db/port", "added /db/user", "added /version"] {
+            assert!(text.contains(change), "{} missing from {}", change, text);
+        }
+
+        let patched = fs::read_to_string(sandbox.root.join("config.json")).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&patched).unwrap(),
+            serde_json::json!({ "name": "app", "db": { "host": "localhost", "port": 6543, "user": "admin" }, "version": 2 })
+        );
+        assert!(patched.starts_with("{\n    \"name\": \"app\",\n    \"db\": {\n        \"host\""), "{}", patched);
+        assert!(patched.ends_with("}\n"));
+
+        let not_object = sandbox
+            .server
+            .patch_json(params(serde_json::json!({ "path": sandbox.path("config.json"), "merge_patch": [1] })))
+            .await;
+        assert!(not_object.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_format_file_json_is_idempotent() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("messy.json", r#"{"b":1,  "a":[1,2,{"c":null}],"s":"x y"}"#);
+        sandbox.write("notes.txt", "hello");
+        let format = |rel: &str, dry_run: bool| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "path": sandbox.path(rel), "dry_run": dry_run });
+            async move { server.format_file(params(args)).await }
+        };
+
+        let preview = format("messy.json", true).await.unwrap();
+        assert!(text_of(&preview).starts_with("[dry run] Would reformat"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("messy.json")).unwrap(), r#"{"b":1,  "a":[1,2,{"c":null}],"s":"x y"}"#);
+
+        format("messy.json", false).await.unwrap();
+        let formatted = fs::read_to_string(sandbox.root.join("messy.json")).unwrap();
+        assert_eq!(
+            formatted,
+            "{\n  \"b\": 1,\n  \"a\": [\n    1,\n    2,\n    {\n      \"c\": null\n    }\n  ],\n  \"s\": \"x y\"\n}\n"
+        );
+
+        let again = format("messy.json", false).await.unwrap();
+        assert!(text_of(&again).contains("already formatted"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("messy.json")).unwrap(), formatted);
+
+        let unsupported = format("notes.txt", false).await.unwrap_err();
+        assert!(unsupported.message.contains("Supported: .json"));
+    }
+
+    #[tokio::test]
+    async fn test_writes_to_ignored_paths_are_denied() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".mcpignore", "secrets/\n*.key\n");
+        sandbox.write("secrets/token.txt", "t0ken");
+        sandbox.write("project/signing.key", "k");
+        sandbox.write("project/readme.md", "hi");
+
+        for rel in ["secrets/new.txt", "project/other.key", ".mcpignore"] {
+            let result = sandbox
+                .server
+                .write_file(params(serde_json::json!({ "path": sandbox.path(rel), "content": "x" })))
+                .await;
+            assert!(result.is_err(), "writing {} should be denied", rel);
+        }
+        assert_eq!(fs::read_to_string(sandbox.root.join(".mcpignore")).unwrap(), "secrets/\n*.key\n");
+
+        let delete = sandbox
+            .server
+            .delete_file(params(serde_json::json!({ "path": sandbox.path("project/signing.key") })))
+            .await;
+        assert!(delete.is_err());
+
+        // Moving the directory would carry the ignored key along
+        let err = sandbox
+            .server
+            .move_file(params(serde_json::json!({ "source": sandbox.path("project"), "destination": sandbox.path("moved") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("signing.key"), "{}", err.message);
+        assert!(sandbox.root.join("project/signing.key").exists());
+
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("project/notes.md"), "content": "ok" })))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_only_folder_allows_new_files_but_not_overwrites() {
+        let sandbox = TestSandbox::with_permission(crate::config::Permission::CreateOnly);
+        sandbox.write("existing.txt", "original");
+        let write = |rel: &str| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "path": sandbox.path(rel), "content": "new" });
+            async move { server.write_file(params(args)).await }
+        };
+
+        write("fresh/new.txt").await.unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("fresh/new.txt")).unwrap(), "new");
+
+        for rel in ["existing.txt", "fresh/new.txt"] {
+            let err = write(rel).await.unwrap_err();
+            assert!(err.message.contains("create-only"), "{}", err.message);
+        }
+        assert_eq!(fs::read_to_string(sandbox.root.join("existing.txt")).unwrap(), "original");
+
+        let delete = sandbox
+            .server
+            .delete_file(params(serde_json::json!({ "path": sandbox.path("existing.txt") })))
+            .await;
+        assert!(delete.is_err());
+        let moved = sandbox
+            .server
+            .move_file(params(serde_json::json!({ "source": sandbox.path("existing.txt"), "destination": sandbox.path("renamed.txt") })))
+            .await;
+        assert!(moved.is_err());
+        assert!(sandbox.root.join("existing.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_append_only_folder_rejects_truncating_writes() {
+        let sandbox = TestSandbox::with_permission(crate::config::Permission::AppendOnly);
+        sandbox.write("app.log", "line 1\n");
+        let write = |rel: &str, content: &str, append: bool| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "path": sandbox.path(rel), "content": content, "append": append });
+            async move { server.write_file(params(args)).await }
+        };
+
+        write("app.log", "line 2\n", true).await.unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("app.log")).unwrap(), "line 1\nline 2\n");
+
+        let err = write("app.log", "truncated", false).await.unwrap_err();
+        assert!(err.message.contains("append-only"), "{}", err.message);
+        assert_eq!(fs::read_to_string(sandbox.root.join("app.log")).unwrap(), "line 1\nline 2\n");
+
+        write("new.log", "first\n", false).await.unwrap();
+        write("other.log", "created by append\n", true).await.unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("other.log")).unwrap(), "created by append\n");
+
+        let patched = sandbox
+            .server
+            .trim_whitespace(params(serde_json::json!({ "path": sandbox.path("app.log") })))
+            .await;
+        assert!(patched.is_err());
+        let delete = sandbox
+            .server
+            .delete_file(params(serde_json::json!({ "path": sandbox.path("app.log") })))
+            .await;
+        assert!(delete.is_err());
+        let moved = sandbox
+            .server
+            .move_file(params(serde_json::json!({ "source": sandbox.path("app.log"), "destination": sandbox.path("moved.log") })))
+            .await;
+        assert!(moved.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_max_search_results_caps_search_and_grep() {
+        let sandbox = TestSandbox::with_config(|c| c.max_search_results = 3);
+        for i in 0..6 {
+            sandbox.write(&format!("notes/{}.txt", i), "needle\nneedle\n");
+        }
+
+        let search = sandbox
+            .server
+            .search_files(params(serde_json::json!({ "pattern": "*.txt", "root_path": sandbox.root.to_string_lossy() })))
+            .await
+            .unwrap();
+        let text = text_of(&search);
+        assert_eq!(text.lines().filter(|l| l.ends_with(".txt")).count(), 3, "{}", text);
+        assert!(text.ends_with("... and 3 more results"), "{}", text);
+
+        let grep_count = |max_results: usize| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "pattern": "needle", "root_path": sandbox.path("notes"), "max_results": max_results });
+            async move {
+                let text = text_of(&server.grep_content(params(args)).await.unwrap());
+                text.lines().filter(|l| l.ends_with(":needle")).count()
+            }
+        };
+        // Asking for more than the configured cap gets the cap; asking for fewer is honored
+        assert_eq!(grep_count(100).await, 3);
+        assert_eq!(grep_count(2).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_grep_and_read_shows_first_match_with_context() {
+        let sandbox = TestSandbox::new();
+        let body: String = (1..=20).map(|i| if i == 10 { "fn target() {}\n".to_string() } else { format!("line {}\n", i) }).collect();
+        sandbox.write("src/a.rs", &body);
+        sandbox.write("src/b.rs", "fn target() {}\nfn target() {}\n");
+        sandbox.write("src/c.rs", "nothing here\n");
+
+        let result = sandbox
+            .server
+            .grep_and_read(params(serde_json::json!({
+                "pattern": "fn target",
+                "root_path": sandbox.path("src"),
+                "context_lines": 2,
+                "relative_to": sandbox.root.to_string_lossy(),
+            })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.starts_with("First match in 2 file(s)"), "{}", text);
+        assert!(text.contains("src/a.rs (line 10 of 20):\n     8 | line 8\n     9 | line 9\n>   10 | fn target() {}\n    11 | line 11\n    12 | line 12\n"), "{}", text);
+        assert!(text.contains("src/b.rs (line 1 of 2):\n>    1 | fn target() {}\n     2 | fn target() {}\n"), "{}", text);
+        assert!(!text.contains("c.rs"));
+
+        let limited = sandbox
+            .server
+            .grep_and_read(params(serde_json::json!({ "pattern": "fn target", "root_path": sandbox.path("src"), "max_files": 1 })))
+            .await
+            .unwrap();
+        let text = text_of(&limited);
+        assert!(text.starts_with("First match in 1 file(s)"), "{}", text);
+        assert!(text.contains("more files match"));
+    }
+
+    #[tokio::test]
+    async fn test_diff_manifests_reports_added_removed_and_modified() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("data/same.txt", "unchanged");
+        sandbox.write("data/edit.txt", "before");
+        sandbox.write("data/gone.txt", "bye");
+        let snapshot = |name: &str| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "root_path": sandbox.path("data"), "output_path": sandbox.path(&format!("snapshots/{}", name)) });
+            async move { server.generate_manifest(params(args)).await.unwrap() }
+        };
+
+        snapshot("1.json").await;
+        sandbox.write("data/edit.txt", "after!");
+        fs::remove_file(sandbox.root.join("data/gone.txt")).unwrap();
+        sandbox.write("data/sub/new.txt", "hello");
+        snapshot("2.json").await;
+
+        let result = sandbox
+            .server
+            .diff_manifests(params(serde_json::json!({
+                "old_manifest_path": sandbox.path("snapshots/1.json"),
+                "new_manifest_path": sandbox.path("snapshots/2.json"),
+            })))
+            .await
+            .unwrap();
+        let diff: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+        assert_eq!(diff["added"][0]["path"], "sub/new.txt");
+        assert_eq!(diff["added"][0]["size"], 5);
+        assert_eq!(diff["removed"][0]["path"], "gone.txt");
+        assert_eq!(diff["modified"][0]["path"], "edit.txt");
+        assert_eq!(diff["modified"][0]["old_size"], 6);
+        assert_eq!(diff["modified"][0]["new_size"], 6);
+        assert_eq!(diff["unchanged"], 1);
+        assert!(diff.get("incomplete").is_none());
+
+        // Against the live tree the result is the same
+        let live = sandbox
+            .server
+            .diff_manifests(params(serde_json::json!({ "old_manifest_path": sandbox.path("snapshots/1.json"), "root_path": sandbox.path("data") })))
+            .await
+            .unwrap();
+        let live: serde_json::Value = serde_json::from_str(&text_of(&live)).unwrap();
+        for key in ["added", "removed", "modified", "unchanged"] {
+            assert_eq!(live[key], diff[key], "{}", key);
+        }
+
+        let neither = sandbox
+            .server
+            .diff_manifests(params(serde_json::json!({ "old_manifest_path": sandbox.path("snapshots/1.json") })))
+            .await;
+        assert!(neither.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_default_ignores_skip_dependency_dirs_unless_disabled() {
+        let setup = |sandbox: &TestSandbox| {
+            sandbox.write("src/main.js", "needle");
+            sandbox.write("node_modules/pkg/index.js", "needle");
+            sandbox.write("target/debug/out.txt", "needle");
+        };
+        let grep = |sandbox: &TestSandbox| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "pattern": "needle", "root_path": sandbox.root.to_string_lossy() });
+            async move { text_of(&server.grep_content(params(args)).await.unwrap()) }
+        };
+        let search = |sandbox: &TestSandbox| {
+            let server = sandbox.server.clone();
+            let args = serde_json::json!({ "pattern": "*.js", "root_path": sandbox.root.to_string_lossy() });
+            async move { text_of(&server.search_files(params(args)).await.unwrap()) }
+        };
+
+        let sandbox = TestSandbox::new();
+        setup(&sandbox);
+        let (grepped, searched) = (grep(&sandbox).await, search(&sandbox).await);
+        assert!(grepped.contains("Found 1 match(es)") && grepped.contains("main.js"), "{}", grepped);
+        assert!(searched.contains("main.js") && !searched.contains("node_modules"), "{}", searched);
+        // Direct access is unaffected
+        let read = sandbox
+            .server
+            .read_file(params(serde_json::json!({ "path": sandbox.path("node_modules/pkg/index.js") })))
+            .await
+            .unwrap();
+        assert!(text_of(&read).contains("needle"));
+
+        let opted_out = TestSandbox::with_config(|c| c.use_default_ignores = false);
+        setup(&opted_out);
+        let (grepped, searched) = (grep(&opted_out).await, search(&opted_out).await);
+        assert!(grepped.contains("Found 3 match(es)"), "{}", grepped);
+        assert!(searched.contains("node_modules"), "{}", searched);
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_ranges_are_labeled_and_merged() {
+        let sandbox = TestSandbox::new();
+        let body: String = (1..=50).map(|i| format!("line {}\n", i)).collect();
+        sandbox.write("long.txt", &body);
+
+        let result = sandbox
+            .server
+            .read_lines(params(serde_json::json!({
+                "path": sandbox.path("long.txt"),
+                "ranges": [
+                    { "start_line": 40, "end_line": 42 },
+                    { "start_line": 2, "end_line": 4 },
+                    { "start_line": 3, "end_line": 6 },
+                    { "start_line": 7, "end_line": 7 },
+                    { "start_line": 49, "end_line": 90 },
+                ],
+            })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains("Lines 2-7:\n     2 | line 2\n"), "{}", text);
+        assert!(text.contains("     7 | line 7\n\nLines 40-42:\n"), "{}", text);
+        assert!(text.contains("Lines 49-50:\n    49 | line 49\n    50 | line 50\n"), "{}", text);
+        assert_eq!(text.matches("| line 3\n").count(), 1, "overlapping lines are shown once");
+        assert!(!text.contains("line 8\n"));
+
+        let outside = sandbox
+            .server
+            .read_lines(params(serde_json::json!({ "path": sandbox.path("long.txt"), "ranges": [{ "start_line": 60, "end_line": 70 }] })))
+            .await;
+        assert!(outside.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_empty_directory_keeps_directory_and_counts_removals() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("out/a.txt", "a");
+        sandbox.write("out/b.txt", "b");
+        sandbox.write("out/nested/c.txt", "c");
+
+        let err = sandbox
+            .server
+            .empty_directory(params(serde_json::json!({ "path": sandbox.path("out") })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("confirm=true"), "{}", err.message);
+        assert!(sandbox.root.join("out/a.txt").exists());
+
+        let result = sandbox
+            .server
+            .empty_directory(params(serde_json::json!({ "path": sandbox.path("out"), "confirm": true })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains("removed 3 file(s) and 1 directory"), "{}", text);
+        assert!(sandbox.root.join("out").is_dir());
+        assert_eq!(fs::read_dir(sandbox.root.join("out")).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_empty_directory_refuses_when_anything_is_ignored() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".mcpignore", "*.key\n");
+        sandbox.write("out/build.log", "log");
+        sandbox.write("out/keys/signing.key", "k");
+
+        let result = sandbox
+            .server
+            .empty_directory(params(serde_json::json!({ "path": sandbox.path("out"), "confirm": true })))
+            .await;
+        assert!(result.is_err());
+        assert!(sandbox.root.join("out/build.log").exists(), "nothing is deleted on refusal");
+
+        // Emptying the shared root would take its .mcpignore along
+        let result = sandbox
+            .server
+            .empty_directory(params(serde_json::json!({ "path": sandbox.root.to_string_lossy(), "confirm": true })))
+            .await;
+        assert!(result.is_err());
+        assert!(sandbox.root.join(".mcpignore").exists());
+    }
+
+    #[tokio::test]
+    async fn test_check_ignore_reports_matching_rule() {
+        let sandbox = TestSandbox::with_config(|c| c.global_ignore = vec!["*.tmp".to_string()]);
+        sandbox.write(".mcpignore", "# local\nsecrets/\n*.key\n");
+        sandbox.write("secrets/token.txt", "t0ken");
+
+        let check = |rel: &'static str| {
+            let server = sandbox.server.clone();
+            let path = sandbox.path(rel);
+            async move {
+                let result = server.check_ignore(params(serde_json::json!({ "path": path }))).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(&text_of(&result)).unwrap()
+            }
+        };
+
+        let key = check("certs/signing.key").await;
+        assert_eq!(key["ignored"], true);
+        assert_eq!(key["rule"], "*.key");
+        assert_eq!(key["line"], 3);
+        assert!(key["file"].as_str().unwrap().ends_with(".mcpignore"));
+
+        let secret = check("secrets/token.txt").await;
+        assert_eq!(secret["rule"], "secrets/");
+        assert_eq!(secret["line"], 2);
+
+        let tmp = check("draft.tmp").await;
+        assert_eq!(tmp["source"], "global_ignore[1]");
+        assert!(tmp["file"].is_null());
+
+        let visible = check("notes/readme.md").await;
+        assert_eq!(visible["ignored"], false);
+        assert!(visible.get("rule").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compare_content_identical_and_differing() {
+        let sandbox = TestSandbox::new();
+        let original = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        sandbox.write("list.txt", original);
+
+        let compare = |content: &str| {
+            sandbox.server.compare_content(params(serde_json::json!({ "path": sandbox.path("list.txt"), "content": content })))
+        };
+
+        let same = text_of(&compare(original).await.unwrap());
+        assert!(same.starts_with("Identical:"), "{}", same);
+
+        let edited = original.replace("two", "TWO").replace("nine\n", "");
+        let diff = text_of(&compare(&edited).await.unwrap());
+        assert!(diff.starts_with("Differs:"), "{}", diff);
+        assert!(diff.contains("--- "), "{}", diff);
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n one\n-two\n+TWO\n three\n"), "{}", diff);
+        assert!(diff.contains("@@ -6,5 +6,4 @@\n six\n seven\n eight\n-nine\n ten\n"), "{}", diff);
+
+        let missing = sandbox
+            .server
+            .compare_content(params(serde_json::json!({ "path": sandbox.path("new.txt"), "content": "x" })))
+            .await
+            .unwrap();
+        assert!(text_of(&missing).contains("does not exist"));
+        assert_eq!(fs::read_to_string(sandbox.root.join("list.txt")).unwrap(), original);
+    }
+
+    #[test]
+    fn test_unified_diff_pure_insertion() {
+        let diff = unified_diff("a\nb\n", "a\nnew\nb\n", "old", "new");
+        assert_eq!(diff, "--- old\n+++ new\n@@ -1,2 +1,3 @@\n a\n+new\n b\n");
+        assert_eq!(unified_diff("a\r\nb\r\n", "a\nb\n", "old", "new"), "");
+        assert_eq!(unified_diff("", "x\n", "old", "new"), "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+
+    #[test]
+    fn test_format_timestamp_honors_timezone_and_format() {
+        // 2024-03-01 12:30:00 UTC
+        let time = std::time::UNIX_EPOCH + Duration::from_secs(1_709_296_200);
+        let config = |timezone: Option<&str>, format: &str| AppConfig {
+            display_timezone: timezone.map(str::to_string),
+            display_time_format: format.to_string(),
+            ..AppConfig::default()
+        };
+
+        assert_eq!(format_timestamp(time, &config(Some("UTC"), crate::config::DEFAULT_TIME_FORMAT)), "2024-03-01 12:30:00");
+        assert_eq!(format_timestamp(time, &config(Some("+05:30"), "%d/%m/%Y %H:%M %:z")), "01/03/2024 18:00 +05:30");
+        assert_eq!(format_timestamp(time, &config(Some("-0800"), "%Y-%m-%dT%H:%M:%S%z")), "2024-03-01T04:30:00-0800");
+        // A broken format falls back to the default instead of failing
+        assert_eq!(format_timestamp(time, &config(Some("utc"), "%Y-%Q")), "2024-03-01 12:30:00");
+        assert_eq!(display_offset(Some("Europe/Paris")), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_file_info_uses_display_time_settings() {
+        let sandbox = TestSandbox::with_config(|c| {
+            c.display_timezone = Some("+02:00".to_string());
+            c.display_time_format = "%d.%m.%Y %H:%M".to_string();
+        });
+        let file = sandbox.write("notes.md", "# notes");
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + Duration::from_secs(1_709_296_200))
+            .unwrap();
+
+        let result = sandbox
+            .server
+            .get_file_info(params(serde_json::json!({ "path": sandbox.path("notes.md") })))
+            .await
+            .unwrap();
+        assert!(text_of(&result).contains("Modified: 01.03.2024 14:30\n"), "{}", text_of(&result));
+    }
+
+    #[tokio::test]
+    async fn test_find_duplicates_groups_identical_files() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".mcpignore", "private/\n");
+        sandbox.write("photos/a.jpg", "x".repeat(1000));
+        sandbox.write("photos/copy of a.jpg", "x".repeat(1000));
+        sandbox.write("backup/a.jpg", "x".repeat(1000));
+        sandbox.write("notes/todo.txt", "milk");
+        sandbox.write("notes/todo-old.txt", "milk");
+        // Same size as the todo files but different bytes
+        sandbox.write("notes/other.txt", "eggs");
+        sandbox.write("private/todo.txt", "milk");
+        sandbox.write("empty1.txt", "");
+        sandbox.write("empty2.txt", "");
+
+        let result = sandbox
+            .server
+            .find_duplicates(params(serde_json::json!({ "root_path": sandbox.root.to_string_lossy() })))
+            .await
+            .unwrap();
+        let found: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+
+        assert_eq!(found["duplicate_groups"], 2);
+        assert_eq!(found["wasted_bytes"], 2000 + 4);
+        let groups = found["groups"].as_array().unwrap();
+        assert_eq!(groups[0]["wasted_bytes"], 2000);
+        assert_eq!(groups[0]["paths"], serde_json::json!(["backup/a.jpg", "photos/a.jpg", "photos/copy of a.jpg"]));
+        assert_eq!(groups[1]["paths"], serde_json::json!(["notes/todo-old.txt", "notes/todo.txt"]));
+
+        let limited = sandbox
+            .server
+            .find_duplicates(params(serde_json::json!({ "root_path": sandbox.root.to_string_lossy(), "max_groups": 1, "min_size": 0 })))
+            .await
+            .unwrap();
+        let limited: serde_json::Value = serde_json::from_str(&text_of(&limited)).unwrap();
+        assert_eq!(limited["duplicate_groups"], 3, "empty files count once min_size is 0");
+        assert_eq!(limited["groups"].as_array().unwrap().len(), 1);
+        assert_eq!(limited["groups_omitted"], 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_through_symlinked_parent_outside_folder_is_denied() {
+        let sandbox = TestSandbox::new();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), sandbox.root.join("link")).unwrap();
+        sandbox.write("docs/readme.md", "hi");
+
+        let err = sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("link/new/sub/file.txt"), "content": "x" })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("outside the writable folder"), "{}", err.message);
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0, "no directories were created outside");
+
+        // New parents inside the folder are still created as before
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("docs/new/sub/file.txt"), "content": "x" })))
+            .await
+            .unwrap();
+        assert!(sandbox.root.join("docs/new/sub/file.txt").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_read_cache_serves_unchanged_files_and_drops_changed_ones() {
+        let sandbox = TestSandbox::with_config(|c| c.read_cache_mb = 1);
+        let file = sandbox.write("notes.txt", "first version");
+        let read = || sandbox.server.read_file(params(serde_json::json!({ "path": sandbox.path("notes.txt") })));
+        assert_eq!(text_of(&read().await.unwrap()), "first version");
+
+        // Same size and modification time: indistinguishable, so the cached copy is served
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        fs::write(&file, "FIRST VERSION").unwrap();
+        fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        assert_eq!(text_of(&read().await.unwrap()), "first version");
+
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("notes.txt"), "content": "second version" })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&read().await.unwrap()), "second version");
+    }
+
+    #[tokio::test]
+    async fn test_read_cache_is_off_by_default() {
+        let sandbox = TestSandbox::new();
+        let file = sandbox.write("notes.txt", "first version");
+        let read = || sandbox.server.read_file(params(serde_json::json!({ "path": sandbox.path("notes.txt") })));
+        read().await.unwrap();
+
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        fs::write(&file, "FIRST VERSION").unwrap();
+        fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        assert_eq!(text_of(&read().await.unwrap()), "FIRST VERSION");
+    }
+
+    #[tokio::test]
+    async fn test_largest_files_biggest_first() {
+        let sandbox = TestSandbox::new();
+        sandbox.write(".mcpignore", "*.bak\n");
+        for (name, size) in [("a.txt", 10), ("media/video.mp4", 5000), ("media/photo.jpg", 3000), ("docs/report.pdf", 800), ("huge.bak", 9000)] {
+            sandbox.write(name, vec![b'x'; size]);
+        }
+
+        let result = sandbox
+            .server
+            .largest_files(params(serde_json::json!({ "root_path": sandbox.root.to_string_lossy(), "count": 3 })))
+            .await
+            .unwrap();
+        let found: serde_json::Value = serde_json::from_str(&text_of(&result)).unwrap();
+
+        let files = found["files"].as_array().unwrap();
+        let listed: Vec<(&str, u64)> = files.iter().map(|f| (f["path"].as_str().unwrap(), f["size_bytes"].as_u64().unwrap())).collect();
+        assert_eq!(listed, vec![("media/video.mp4", 5000), ("media/photo.jpg", 3000), ("docs/report.pdf", 800)]);
+        assert_eq!(files[0]["size"], format_size(5000));
+        assert_eq!(found["files_scanned"], 5, "the ignored .bak file is not counted");
+        assert!(found.get("truncated").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_write_file_overwrite_flag_and_default() {
+        async fn write(sandbox: &TestSandbox, content: &str, overwrite: Option<bool>) -> Result<CallToolResult, ErrorData> {
+            let mut args = serde_json::json!({ "path": sandbox.path("notes.txt"), "content": content });
+            if let Some(overwrite) = overwrite {
+                args["overwrite"] = serde_json::json!(overwrite);
+            }
+            sandbox.server.write_file(params(args)).await
+        }
+
+        // Default config: replacing is allowed unless the call opts out
+        let sandbox = TestSandbox::new();
+        sandbox.write("notes.txt", "v1");
+        write(&sandbox, "v2", None).await.unwrap();
+        let err = write(&sandbox, "v3", Some(false)).await.unwrap_err();
+        assert!(err.message.contains("overwrite=true"), "{}", err.message);
+        assert_eq!(fs::read_to_string(sandbox.root.join("notes.txt")).unwrap(), "v2");
+
+        // Cautious config: replacing needs explicit intent, creating a new file doesn't
+        let sandbox = TestSandbox::with_config(|c| c.default_overwrite = false);
+        write(&sandbox, "new", None).await.unwrap();
+        assert!(write(&sandbox, "clobbered", None).await.is_err());
+        assert_eq!(fs::read_to_string(sandbox.root.join("notes.txt")).unwrap(), "new");
+        write(&sandbox, "replaced", Some(true)).await.unwrap();
+        assert_eq!(fs::read_to_string(sandbox.root.join("notes.txt")).unwrap(), "replaced");
+        sandbox
+            .server
+            .write_file(params(serde_json::json!({ "path": sandbox.path("notes.txt"), "content": "+more", "append": true })))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_tools_absolute_and_relative_paths() {
+        let sandbox = TestSandbox::new();
+        sandbox.write("docs/guide.md", "needle");
+        let root = sandbox.root.to_string_lossy().to_string();
+        let absolute = sandbox.path("docs/guide.md");
+        let relative = std::path::Path::new("docs").join("guide.md").to_string_lossy().to_string();
+
+        let found = sandbox
+            .server
+            .search_files(params(serde_json::json!({ "pattern": "*.md" })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&found), absolute);
+
+        let found_rel = sandbox
+            .server
+            .search_files(params(serde_json::json!({ "pattern": "*.md", "relative_to": root })))
+            .await
+            .unwrap();
+        assert_eq!(text_of(&found_rel), relative);
+
+        let grep_abs = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root })))
+            .await
+            .unwrap();
+        assert!(text_of(&grep_abs).contains(&format!("{}:1:needle", absolute)));
+
+        let grep_rel = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root, "relative_to": root })))
+            .await
+            .unwrap();
+        let text = text_of(&grep_rel);
+        assert!(text.contains(&format!("\n{}:1:needle", relative)));
+        assert!(!text.contains(&absolute));
+
+        let err = sandbox
+            .server
+            .grep_content(params(serde_json::json!({ "pattern": "needle", "root_path": root, "relative_to": sandbox.path("docs") })))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_get_file_info_hash_only_when_requested() {
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        let file = root.join("hello.txt");
+        fs::write(&file, "hello").unwrap();
+        let path = file.to_string_lossy().to_string();
+
+        let plain = server
+            .get_file_info(params(serde_json::json!({ "path": path })))
+            .await
+            .unwrap();
+        assert!(!text_of(&plain).contains("SHA-256"));
+
+        let expected = "SHA-256: 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        for _ in 0..2 {
+            let hashed = server
+                .get_file_info(params(serde_json::json!({ "path": path, "include_hash": true })))
+                .await
+                .unwrap();
+            assert!(text_of(&hashed).contains(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heavy_operation_limit_is_resized() {
+        let mut config = AppConfig::default();
+        config.max_heavy_operations = 2;
+        let server = OmniDriveServer::new(config);
+
+        server.set_max_heavy_operations(3);
+        assert_eq!(server.heavy_ops.available_permits(), 3);
+
+        let first = server.acquire_heavy_slot().await.unwrap();
+        let second = server.acquire_heavy_slot().await.unwrap();
+        server.set_max_heavy_operations(1);
+        assert_eq!(server.heavy_ops.available_permits(), 0);
+
+        // Both running operations finish; only one slot should come back
+        drop(first);
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while server.heavy_ops.available_permits() == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(server.heavy_ops.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_folder_reported_only_when_requested() {
+        let sandbox = TestSandbox::with_permission(crate::config::Permission::ReadOnly);
+        let server = &sandbox.server;
+        let file = sandbox.write("docs/a.txt", "hello");
+        let folder = sandbox.root.to_string_lossy().to_string();
+        let expected = format!("Folder: {} (read-only)", folder);
+
+        let plain = server
+            .get_file_info(params(serde_json::json!({ "path": file.to_string_lossy() })))
+            .await
+            .unwrap();
+        assert!(!text_of(&plain).contains("Folder: "));
+
+        let info = server
+            .get_file_info(params(serde_json::json!({ "path": file.to_string_lossy(), "show_folder": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&info).contains(&format!("Folder: {}\n", folder)), "{}", text_of(&info));
+
+        let docs = sandbox.path("docs");
+        let plain = server.list_directory(params(serde_json::json!({ "path": docs }))).await.unwrap();
+        assert!(!text_of(&plain).contains("Folder: "));
+
+        for recursive in [false, true] {
+            let listing = server
+                .list_directory(params(serde_json::json!({ "path": docs, "recursive": recursive, "show_folder": true })))
+                .await
+                .unwrap();
+            assert!(text_of(&listing).contains(&expected), "{}", text_of(&listing));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_file_info_timestamps_and_mode() {
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        let file = root.join("notes.md");
+        fs::write(&file, "# notes").unwrap();
+        let meta = fs::metadata(&file).unwrap();
+
+        let result = server
+            .get_file_info(params(serde_json::json!({ "path": file.to_string_lossy() })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+
+        assert_eq!(text.contains("Created: "), meta.created().is_ok());
+        assert_eq!(text.contains("Accessed: "), meta.accessed().is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = format!("Mode: {:04o}", meta.permissions().mode() & 0o7777);
+            assert!(text.contains(&mode));
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_directory_show_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        let script = root.join("run.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        let dir = root.to_string_lossy().to_string();
+
+        let with_mode = server
+            .list_directory(params(serde_json::json!({ "path": dir, "show_mode": true })))
+            .await
+            .unwrap();
+        assert!(text_of(&with_mode).contains("0750 rwxr-x---"));
+
+        let without = server
+            .list_directory(params(serde_json::json!({ "path": dir })))
+            .await
+            .unwrap();
+        assert!(!text_of(&without).contains("Mode"));
+    }
+
+    #[test]
+    fn test_parse_octal_mode() {
+        assert_eq!(parse_octal_mode("755").unwrap(), 0o755);
+        assert_eq!(parse_octal_mode("0644").unwrap(), 0o644);
+        assert_eq!(parse_octal_mode("0o600").unwrap(), 0o600);
+        assert!(parse_octal_mode("789").is_err());
+        assert!(parse_octal_mode("rwx").is_err());
+        assert!(parse_octal_mode("17777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_permissions_reports_old_and_new() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        let script = root.join("build.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let result = server
+            .set_permissions(params(serde_json::json!({ "path": script.to_string_lossy(), "mode": "755" })))
+            .await
+            .unwrap();
+        assert!(text_of(&result).contains("0644 rw-r--r-- → 0755 rwxr-xr-x"));
+        assert_eq!(fs::metadata(&script).unwrap().permissions().mode() & 0o7777, 0o755);
+    }
+
+    #[cfg(not(unix))]
+    #[tokio::test]
+    async fn test_set_permissions_unsupported_off_unix() {
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        let file = root.join("a.txt");
+        fs::write(&file, "a").unwrap();
+
+        let err = server
+            .set_permissions(params(serde_json::json!({ "path": file.to_string_lossy(), "mode": "755" })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("unsupported on this platform"));
+    }
+
+    #[tokio::test]
+    async fn test_grep_bad_regex_is_invalid_params() {
+        let sandbox = TestSandbox::new();
+        let (server, root) = (&sandbox.server, &sandbox.root);
+        fs::write(root.join("a.txt"), "needle").unwrap();
+
+        let err = server
+            .grep_content(params(serde_json::json!({
+                "pattern": "(unclosed",
+                "root_path": root.to_string_lossy(),
+                "is_regex": true,
+            })))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+
+        let outside = server
+            .read_file(params(serde_json::json!({ "path": "/definitely/not/shared.txt" })))
+            .await
+            .unwrap_err();
+        assert_eq!(outside.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_read_csv_slice_quoted_fields() {
+        let data = "name,notes\n\"Smith, Jane\",\"said \"\"hi\"\"\"\nBob,plain\n";
+        let slice = read_csv_slice(data.as_bytes(), b',', 1, 10).unwrap();
+        assert_eq!(slice.header, vec!["name", "notes"]);
+        assert_eq!(slice.rows[0], vec!["Smith, Jane", "said \"hi\""]);
+        assert_eq!(slice.rows[1], vec!["Bob", "plain"]);
+        assert!(!slice.has_more);
+    }
+
+    #[test]
+    fn test_read_csv_slice_custom_delimiter_and_window() {
+        let data = "a;b\n1;2\n3;4\n5;6\n";
+        let slice = read_csv_slice(data.as_bytes(), b';', 2, 1).unwrap();
+        assert_eq!(slice.header, vec!["a", "b"]);
+        assert_eq!(slice.rows, vec![vec!["3".to_string(), "4".to_string()]]);
+        assert!(slice.has_more);
+    }
+
+    #[test]
+    fn test_normalize_crlf_to_lf_preserves_content() {
+        let text = "first line\r\nsecond, with text\r\n\r\nlast";
+        let converted = normalize_line_endings(text, LineEnding::Lf);
+        assert_eq!(converted, "first line\nsecond, with text\n\nlast");
+        assert_eq!(detect_line_ending(text), LineEnding::CrLf);
+        assert_eq!(normalize_line_endings(&converted, LineEnding::CrLf), text);
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_mixed() {
+        let text = "fn main() {  \n\tlet x = 1;\t\n    x\n}   \n\n\n";
+        let (trimmed, changed) = trim_trailing_whitespace(text, false);
+        assert_eq!(trimmed, "fn main() {\n\tlet x = 1;\n    x\n}\n\n\n");
+        assert_eq!(changed, 3);
+
+        let (single, _) = trim_trailing_whitespace(text, true);
+        assert_eq!(single, "fn main() {\n\tlet x = 1;\n    x\n}\n");
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_keeps_crlf() {
+        let (trimmed, changed) = trim_trailing_whitespace("a \r\nb\r\n", false);
+        assert_eq!(trimmed, "a\r\nb\r\n");
+        assert_eq!(changed, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_symlink_at_unix() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let target = dir.join("target.txt");
+        fs::write(&target, "hello").unwrap();
+
+        let link = dir.join("link.txt");
+        create_symlink_at(&target, &link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "hello");
+
+        // A second link at the same path must fail rather than clobber
+        assert!(create_symlink_at(&target, &link).is_err());
+    }
+
+    #[test]
+    fn test_decode_text_legacy_encoding() {
+        // "café" in windows-1252
+        let bytes = [0x63, 0x61, 0x66, 0xE9];
+        assert!(decode_text(&bytes, None).is_err());
+        let (text, name) = decode_text(&bytes, Some("windows-1252")).unwrap();
+        assert_eq!(text, "café");
+        assert_eq!(name, "windows-1252");
+    }
+
+    #[tokio::test]
+    async fn test_recent_activity_hides_paths_outside_shared_folders() {
+        let mine = TestSandbox::new();
+        let other = TestSandbox::new();
+        mine.write("mine.txt", "a");
+        other.write("secret.txt", "b");
+        mine.server.read_file(params(serde_json::json!({ "path": mine.path("mine.txt") }))).await.unwrap();
+        other.server.read_file(params(serde_json::json!({ "path": other.path("secret.txt") }))).await.unwrap();
+
+        let result = mine
+            .server
+            .recent_activity(params(serde_json::json!({ "limit": 100 })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains(&mine.path("mine.txt")), "{}", text);
+        assert!(!text.contains(&other.path("secret.txt")), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_hides_paths_outside_shared_folders() {
+        let mine = TestSandbox::new();
+        let other = TestSandbox::new();
+        mine.write("mine.txt", "a");
+        other.write("secret.txt", "b");
+        mine.server.read_file(params(serde_json::json!({ "path": mine.path("mine.txt") }))).await.unwrap();
+        other.server.read_file(params(serde_json::json!({ "path": other.path("secret.txt") }))).await.unwrap();
+
+        let result = mine
+            .server
+            .recent_files(params(serde_json::json!({ "limit": 100 })))
+            .await
+            .unwrap();
+        let text = text_of(&result);
+        assert!(text.contains(&mine.path("mine.txt")), "{}", text);
+        assert!(!text.contains(&other.path("secret.txt")), "{}", text);
+    }
+}
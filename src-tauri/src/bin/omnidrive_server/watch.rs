@@ -0,0 +1,171 @@
+//! Resource subscriptions: an agent subscribes to a shared file or folder by its
+//! `file://` URI and receives `notifications/resources/updated` whenever anything
+//! under it changes.
+//!
+//! Notifications need a server-to-client channel, so they only reach clients on
+//! stdio or on SSE in stateful mode; a stateless SSE request drops its
+//! subscriptions when it completes.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rmcp::model::ResourceUpdatedNotificationParam;
+use rmcp::{Peer, RoleServer};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Subscriptions allowed across all sessions
+const MAX_SUBSCRIPTIONS: usize = 32;
+
+/// Bursts of changes within this window produce a single notification
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the `MAX_SUBSCRIPTIONS` slots, released on drop
+struct Slot;
+
+impl Slot {
+    fn acquire() -> Option<Slot> {
+        ACTIVE
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < MAX_SUBSCRIPTIONS).then_some(n + 1))
+            .ok()
+            .map(|_| Slot)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct Subscription {
+    _watcher: RecommendedWatcher,
+    forwarder: JoinHandle<()>,
+    _slot: Slot,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
+
+/// The subscriptions of one session, keyed by URI. Dropping it ends them all.
+#[derive(Default)]
+pub struct Subscriptions {
+    active: Mutex<HashMap<String, Subscription>>,
+}
+
+impl Subscriptions {
+    /// Start pushing change notifications for `path` to `peer`. Subscribing to a
+    /// URI twice is a no-op.
+    pub fn subscribe(&self, uri: &str, path: &Path, peer: Peer<RoleServer>) -> Result<(), String> {
+        let mut active = self.active.lock().unwrap();
+        if active.contains_key(uri) {
+            return Ok(());
+        }
+
+        let slot = Slot::acquire().ok_or_else(|| {
+            format!("Too many subscriptions (limit: {}). Unsubscribe from another resource first.", MAX_SUBSCRIPTIONS)
+        })?;
+        let (watcher, mut changes) =
+            watch_changes(path).map_err(|e| format!("Cannot watch {}: {}", path.display(), e))?;
+
+        let notify_uri = uri.to_string();
+        let forwarder = tokio::spawn(async move {
+            while changes.recv().await.is_some() {
+                let param = ResourceUpdatedNotificationParam { uri: notify_uri.clone() };
+                if let Err(e) = peer.notify_resource_updated(param).await {
+                    eprintln!("[OmniDrive] Stopped notifying {}: {}", notify_uri, e);
+                    break;
+                }
+            }
+        });
+
+        active.insert(uri.to_string(), Subscription { _watcher: watcher, forwarder, _slot: slot });
+        Ok(())
+    }
+
+    /// Stop notifications for `uri`, returning whether it was subscribed
+    pub fn unsubscribe(&self, uri: &str) -> bool {
+        self.active.lock().unwrap().remove(uri).is_some()
+    }
+}
+
+/// The local path named by a `file://` URI, with percent-escapes (e.g. `%20`) decoded
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?;
+    let path = percent_encoding::percent_decode_str(encoded).decode_utf8().ok()?;
+    // file:///C:/Users/... names a Windows drive path
+    let is_drive = path.len() > 2 && path.starts_with('/') && path.as_bytes()[2] == b':';
+    Some(if is_drive { path[1..].to_string() } else { path.to_string() })
+}
+
+/// Watch everything under `path`, yielding one item per debounced burst of changes.
+/// Changes inside OmniDrive's own data directory (e.g. the activity log) are ignored,
+/// so tool calls don't notify about themselves. The feed ends when the watcher is dropped.
+fn watch_changes(path: &Path) -> notify::Result<(RecommendedWatcher, mpsc::Receiver<()>)> {
+    let data_dir = crate::config::get_config_dir();
+    let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let relevant = !event.kind.is_access() && event.paths.iter().any(|p| !p.starts_with(&data_dir));
+            if relevant {
+                let _ = raw_tx.send(());
+            }
+        }
+    })?;
+    watcher.watch(path, RecursiveMode::Recursive)?;
+
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        while raw_rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while raw_rx.try_recv().is_ok() {}
+            if tx.send(()).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok((watcher, rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_file_change_produces_one_debounced_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_watcher, mut changes) = watch_changes(dir.path()).unwrap();
+
+        for i in 0..5 {
+            fs::write(dir.path().join("notes.txt"), format!("edit {}", i)).unwrap();
+        }
+
+        tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("no notification for the change")
+            .unwrap();
+        let extra = tokio::time::timeout(DEBOUNCE * 3, changes.recv()).await;
+        assert!(extra.is_err(), "a burst of writes should notify once");
+    }
+
+    #[test]
+    fn test_uri_to_path() {
+        assert_eq!(uri_to_path("file:///home/me/docs").as_deref(), Some("/home/me/docs"));
+        assert_eq!(uri_to_path("file:///C:/Users/me").as_deref(), Some("C:/Users/me"));
+        assert_eq!(uri_to_path("https://example.com/x"), None);
+        assert_eq!(uri_to_path("file:///home/me/My%20Docs").as_deref(), Some("/home/me/My Docs"));
+        assert_eq!(uri_to_path("file:///home/me/r%C3%A9sum%C3%A9.txt").as_deref(), Some("/home/me/résumé.txt"));
+        assert_eq!(uri_to_path("file:///C:/My%20Docs").as_deref(), Some("C:/My Docs"));
+        assert_eq!(uri_to_path("file:///bad%FF"), None);
+    }
+}
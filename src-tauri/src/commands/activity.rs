@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use chrono::{DateTime, Utc, Duration};
+use tauri::State;
+
+use crate::commands::AppState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActivityEntry {
@@ -12,6 +16,16 @@ pub struct ActivityEntry {
     pub path: Option<String>,
     pub agent: String,
     pub summary: String,
+    /// Bytes read or written; absent for older lines and tools that move no content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// The scope identity the server logged the entry under; absent for older lines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// HMAC signature written by the server when `sign_activity` is on.
+    /// Verification re-serializes the entry, so field order must match the server's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,6 +35,39 @@ pub struct ConnectedAgent {
     pub status: String, // "connected" or "disconnected"
 }
 
+/// Outcome of checking every signature in the activity log
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogVerification {
+    pub total_lines: usize,
+    pub signed: usize,
+    /// Lines written before signing was enabled
+    pub unsigned: usize,
+    /// 1-based line number of the first entry whose signature doesn't match
+    pub first_invalid_line: Option<usize>,
+    pub valid: bool,
+}
+
+/// Byte totals for one agent within one shared folder
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ByteStats {
+    pub agent: String,
+    /// Shared folder path, or "(other)" when the entry matches no folder
+    pub folder: String,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// A file an agent recently read or wrote
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RecentFile {
+    pub agent: String,
+    pub path: String,
+    /// When the agent last touched the file
+    pub timestamp: String,
+    /// "read" or "write", from that latest access
+    pub category: String,
+}
+
 lazy_static::lazy_static! {
     static ref ACTIVITY_FILE: PathBuf = {
         let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
@@ -28,11 +75,32 @@ lazy_static::lazy_static! {
         path.push("activity.jsonl");
         path
     };
+
+    /// Advisory lock file shared with the MCP server, which appends to the log
+    static ref ACTIVITY_LOCK: PathBuf = ACTIVITY_FILE.with_file_name("activity.lock");
+}
+
+/// Take the cross-process advisory lock on the activity log.
+/// Released when the returned file is dropped.
+fn lock_log(exclusive: bool) -> std::io::Result<fs::File> {
+    if let Some(dir) = ACTIVITY_LOCK.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::OpenOptions::new().create(true).write(true).open(&*ACTIVITY_LOCK)?;
+    if exclusive {
+        fs2::FileExt::lock_exclusive(&file)?;
+    } else {
+        fs2::FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
 }
 
 /// Read and parse the activity log file
 fn read_all_logs() -> Vec<ActivityEntry> {
-    let content = fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default();
+    let content = {
+        let _lock = lock_log(false);
+        fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default()
+    };
     let mut entries: Vec<ActivityEntry> = content
         .lines()
         .filter_map(|line| serde_json::from_str(line).ok())
@@ -108,6 +176,281 @@ pub async fn get_connected_agents() -> Result<Vec<ConnectedAgent>, String> {
 
 #[tauri::command]
 pub async fn clear_activity_log() -> Result<(), String> {
+    let _lock = lock_log(true).map_err(|e| e.to_string())?;
     fs::write(&*ACTIVITY_FILE, "").map_err(|e| e.to_string())?;
     Ok(())
 }
+
+/// Expand a leading `~` (written when home redaction is on) back to the home directory
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            format!("{}{}", home.to_string_lossy().trim_end_matches(['/', '\\']), rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// The shared folder containing `path`, matching on a path boundary
+fn folder_for<'a>(path: &str, folders: &'a [String]) -> Option<&'a str> {
+    folders
+        .iter()
+        .filter(|folder| {
+            let folder = folder.trim_end_matches(['/', '\\']);
+            path.strip_prefix(folder)
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with(['/', '\\']))
+        })
+        .max_by_key(|folder| folder.len())
+        .map(|s| s.as_str())
+}
+
+/// Sum recorded bytes per agent and folder. Reads and writes are split by category.
+fn aggregate_bytes(entries: &[ActivityEntry], folders: &[String]) -> Vec<ByteStats> {
+    let mut totals: BTreeMap<(String, String), (u64, u64)> = BTreeMap::new();
+
+    for entry in entries {
+        let Some(bytes) = entry.bytes else { continue };
+        let folder = entry
+            .path
+            .as_deref()
+            .map(expand_home)
+            .and_then(|path| folder_for(&path, folders).map(str::to_string))
+            .unwrap_or_else(|| "(other)".to_string());
+
+        let slot = totals.entry((entry.agent.clone(), folder)).or_default();
+        match entry.category.as_str() {
+            "read" => slot.0 += bytes,
+            "write" => slot.1 += bytes,
+            _ => {}
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|((agent, folder), (bytes_read, bytes_written))| ByteStats {
+            agent,
+            folder,
+            bytes_read,
+            bytes_written,
+        })
+        .collect()
+}
+
+/// Per-agent, per-folder totals of bytes read and written
+#[tauri::command]
+pub async fn get_activity_stats(state: State<'_, AppState>) -> Result<Vec<ByteStats>, String> {
+    let folders: Vec<String> = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        config.folders.iter().map(|f| f.path.clone()).collect()
+    };
+    Ok(aggregate_bytes(&read_all_logs(), &folders))
+}
+
+/// Whether a logged path names a file, rather than a summary like "3 paths"
+fn is_file_path(path: &str) -> bool {
+    path.starts_with(['/', '\\', '~']) || path.get(1..3).is_some_and(|s| s == ":\\" || s == ":/")
+}
+
+/// The `per_agent` most recently read or written distinct paths of each agent.
+/// `entries` must be sorted newest first, as `read_all_logs` returns them.
+fn recent_files(entries: &[ActivityEntry], per_agent: usize) -> Vec<RecentFile> {
+    let mut by_agent: BTreeMap<&str, Vec<RecentFile>> = BTreeMap::new();
+
+    for entry in entries {
+        if entry.category != "read" && entry.category != "write" {
+            continue;
+        }
+        let Some(path) = entry.path.as_deref().filter(|p| is_file_path(p)) else { continue };
+
+        let files = by_agent.entry(&entry.agent).or_default();
+        if files.len() >= per_agent || files.iter().any(|f| f.path == path) {
+            continue;
+        }
+        files.push(RecentFile {
+            agent: entry.agent.clone(),
+            path: path.to_string(),
+            timestamp: entry.timestamp.clone(),
+            category: entry.category.clone(),
+        });
+    }
+
+    by_agent.into_values().flatten().collect()
+}
+
+/// Each agent's most recently accessed files, newest first within each agent
+#[tauri::command]
+pub async fn get_recent_files(limit: Option<usize>) -> Result<Vec<RecentFile>, String> {
+    Ok(recent_files(&read_all_logs(), limit.unwrap_or(20).clamp(1, 200)))
+}
+
+/// Check each line's signature against `key`. Lines that don't parse count as altered.
+fn verify_lines(content: &str, key: &[u8]) -> LogVerification {
+    let mut result = LogVerification {
+        total_lines: 0,
+        signed: 0,
+        unsigned: 0,
+        first_invalid_line: None,
+        valid: true,
+    };
+
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        result.total_lines += 1;
+
+        let intact = match serde_json::from_str::<ActivityEntry>(line) {
+            Ok(mut entry) => match entry.sig.take() {
+                Some(sig) => {
+                    result.signed += 1;
+                    serde_json::to_string(&entry)
+                        .map(|payload| crate::audit::verify(key, &payload, &sig))
+                        .unwrap_or(false)
+                }
+                None => {
+                    result.unsigned += 1;
+                    true
+                }
+            },
+            Err(_) => false,
+        };
+
+        if !intact && result.first_invalid_line.is_none() {
+            result.first_invalid_line = Some(i + 1);
+            result.valid = false;
+        }
+    }
+    result
+}
+
+/// Recompute every activity entry signature and report the first altered line
+#[tauri::command]
+pub async fn verify_activity_log() -> Result<LogVerification, String> {
+    let dir = ACTIVITY_FILE.parent().map(PathBuf::from).unwrap_or_default();
+    let key = crate::audit::load_key(&dir)
+        .map_err(|e| format!("No activity signing key ({}). Enable sign_activity first.", e))?;
+    let content = {
+        let _lock = lock_log(false);
+        fs::read_to_string(&*ACTIVITY_FILE).unwrap_or_default()
+    };
+    Ok(verify_lines(&content, &key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(agent: &str, category: &str, path: Option<&str>, bytes: Option<u64>) -> ActivityEntry {
+        ActivityEntry {
+            id: "1".to_string(),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            tool: "read_file".to_string(),
+            category: category.to_string(),
+            path: path.map(str::to_string),
+            agent: agent.to_string(),
+            summary: String::new(),
+            bytes,
+            scope: None,
+            sig: None,
+        }
+    }
+
+    fn signed_line(key: &[u8], summary: &str) -> String {
+        let mut e = entry("Claude", "write", Some("/data/a.txt"), Some(5));
+        e.summary = summary.to_string();
+        let payload = serde_json::to_string(&e).unwrap();
+        e.sig = Some(crate::audit::sign(key, &payload));
+        serde_json::to_string(&e).unwrap()
+    }
+
+    #[test]
+    fn test_recent_files_dedupes_per_agent_and_caps() {
+        let mut log = vec![
+            entry("claude", "read", Some("/docs/a.md"), None),
+            entry("claude", "write", Some("/docs/b.md"), None),
+            entry("claude", "read", Some("3 paths"), None),
+            entry("claude", "delete", Some("/docs/c.md"), None),
+            entry("cursor", "read", Some("/docs/a.md"), None),
+            entry("claude", "write", Some("/docs/a.md"), None),
+            entry("claude", "read", Some("/docs/d.md"), None),
+        ];
+        for (i, e) in log.iter_mut().enumerate() {
+            e.timestamp = format!("2024-01-01T00:00:0{}Z", i);
+        }
+        log.reverse();
+
+        let recent = recent_files(&log, 2);
+        let summary: Vec<(&str, &str, &str)> = recent
+            .iter()
+            .map(|f| (f.agent.as_str(), f.path.as_str(), f.timestamp.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("claude", "/docs/d.md", "2024-01-01T00:00:06Z"),
+                ("claude", "/docs/a.md", "2024-01-01T00:00:05Z"),
+                ("cursor", "/docs/a.md", "2024-01-01T00:00:04Z"),
+            ]
+        );
+        assert_eq!(recent[1].category, "write");
+    }
+
+    #[test]
+    fn test_verify_lines_accepts_intact_log() {
+        let key = b"test-key";
+        let unsigned = serde_json::to_string(&entry("Claude", "read", None, None)).unwrap();
+        let log = [unsigned, signed_line(key, "one"), signed_line(key, "two")].join("\n");
+
+        let result = verify_lines(&log, key);
+        assert!(result.valid);
+        assert_eq!((result.total_lines, result.signed, result.unsigned), (3, 2, 1));
+        assert_eq!(result.first_invalid_line, None);
+    }
+
+    #[test]
+    fn test_verify_lines_reports_first_tampered_line() {
+        let key = b"test-key";
+        let log = [
+            signed_line(key, "one"),
+            signed_line(key, "two").replace("two", "edited"),
+            signed_line(key, "three").replace("\"bytes\":5", "\"bytes\":500"),
+        ]
+        .join("\n");
+
+        let result = verify_lines(&log, key);
+        assert!(!result.valid);
+        assert_eq!(result.first_invalid_line, Some(2));
+
+        // A different key fails from the first line
+        assert_eq!(verify_lines(&signed_line(key, "one"), b"other-key").first_invalid_line, Some(1));
+    }
+
+    #[test]
+    fn test_aggregate_bytes_per_agent_and_folder() {
+        let folders = vec!["/data/docs".to_string(), "/data/docs/inner".to_string()];
+        let entries = vec![
+            entry("Claude", "read", Some("/data/docs/a.txt"), Some(100)),
+            entry("Claude", "read", Some("/data/docs/b.txt"), Some(50)),
+            entry("Claude", "write", Some("/data/docs/c.txt"), Some(7)),
+            entry("Claude", "read", Some("/data/docs/inner/d.txt"), Some(3)),
+            entry("Claude", "read", Some("/data/docs2/e.txt"), Some(9)),
+            entry("Cursor", "write", Some("/data/docs/a.txt"), Some(11)),
+            entry("Cursor", "read", Some("/data/docs/a.txt"), None),
+            entry("Cursor", "system", None, None),
+        ];
+
+        let stats = aggregate_bytes(&entries, &folders);
+        let row = |agent: &str, folder: &str| {
+            stats
+                .iter()
+                .find(|s| s.agent == agent && s.folder == folder)
+                .map(|s| (s.bytes_read, s.bytes_written))
+        };
+
+        assert_eq!(stats.len(), 4);
+        assert_eq!(row("Claude", "/data/docs"), Some((150, 7)));
+        assert_eq!(row("Claude", "/data/docs/inner"), Some((3, 0)));
+        assert_eq!(row("Claude", "(other)"), Some((9, 0)));
+        assert_eq!(row("Cursor", "/data/docs"), Some((0, 11)));
+    }
+}
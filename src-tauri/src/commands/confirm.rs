@@ -0,0 +1,145 @@
+//! The app side of destructive-operation confirmations.
+//!
+//! The server writes `<id>.json` requests into `~/.omnidrive/confirmations/` and
+//! polls for `<id>.response.json`. We watch that directory, emit a
+//! `confirmation-requested` event for each new request, and write the user's answer.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use tauri::{AppHandle, Emitter};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A destructive operation waiting on the user
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingConfirmation {
+    pub id: String,
+    pub agent: String,
+    pub tool: String,
+    pub path: String,
+    pub action: String,
+    pub requested_at: String,
+    pub expires_at: String,
+}
+
+#[derive(Serialize)]
+struct ConfirmationResponse {
+    approved: bool,
+}
+
+fn confirmations_dir() -> PathBuf {
+    crate::config::store::get_shared_config_dir().join("confirmations")
+}
+
+/// Request ids are uuids; anything else could escape the directory
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+/// Unanswered, unexpired requests in `dir`, oldest first
+fn pending_in(dir: &Path, now: DateTime<Utc>) -> Vec<PendingConfirmation> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut pending: Vec<PendingConfirmation> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json") && !p.to_string_lossy().ends_with(".response.json"))
+        .filter_map(|p| serde_json::from_str::<PendingConfirmation>(&fs::read_to_string(p).ok()?).ok())
+        .filter(|c| !dir.join(format!("{}.response.json", c.id)).exists())
+        .filter(|c| {
+            DateTime::parse_from_rfc3339(&c.expires_at)
+                .map(|t| t.with_timezone(&Utc) > now)
+                .unwrap_or(false)
+        })
+        .collect();
+    pending.sort_by(|a, b| a.requested_at.cmp(&b.requested_at));
+    pending
+}
+
+fn respond_in(dir: &Path, id: &str, approved: bool) -> Result<(), String> {
+    if !is_valid_id(id) {
+        return Err(format!("Invalid confirmation id: {}", id));
+    }
+    if !dir.join(format!("{}.json", id)).exists() {
+        return Err("This request has already expired or been answered".to_string());
+    }
+    let body = serde_json::to_string(&ConfirmationResponse { approved }).map_err(|e| e.to_string())?;
+    let tmp = dir.join(format!("{}.response.tmp", id));
+    fs::write(&tmp, body).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, dir.join(format!("{}.response.json", id))).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_pending_confirmations() -> Result<Vec<PendingConfirmation>, String> {
+    Ok(pending_in(&confirmations_dir(), Utc::now()))
+}
+
+#[tauri::command]
+pub async fn respond_to_confirmation(id: String, approved: bool) -> Result<(), String> {
+    respond_in(&confirmations_dir(), &id, approved)
+}
+
+/// Emit `confirmation-requested` once for every new request the server writes
+pub fn watch_confirmations(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let dir = confirmations_dir();
+        let mut seen = HashSet::new();
+        loop {
+            let pending = pending_in(&dir, Utc::now());
+            for request in &pending {
+                if seen.insert(request.id.clone()) {
+                    let _ = app.emit("confirmation-requested", request);
+                }
+            }
+            seen.retain(|id| pending.iter().any(|p| &p.id == id));
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(dir: &Path, id: &str, expires_at: &str) {
+        let request = PendingConfirmation {
+            id: id.to_string(),
+            agent: "claude".to_string(),
+            tool: "delete_file".to_string(),
+            path: "/tmp/a.txt".to_string(),
+            action: "deletion".to_string(),
+            requested_at: "2024-01-01T00:00:00Z".to_string(),
+            expires_at: expires_at.to_string(),
+        };
+        fs::write(dir.join(format!("{}.json", id)), serde_json::to_string(&request).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_pending_excludes_answered_and_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-01-01T00:00:30Z").unwrap().with_timezone(&Utc);
+        request(dir.path(), "aaaa-1", "2024-01-01T00:01:00Z");
+        request(dir.path(), "bbbb-2", "2024-01-01T00:01:00Z");
+        request(dir.path(), "cccc-3", "2024-01-01T00:00:10Z");
+
+        assert_eq!(pending_in(dir.path(), now).len(), 2);
+
+        respond_in(dir.path(), "bbbb-2", false).unwrap();
+        let pending = pending_in(dir.path(), now);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "aaaa-1");
+
+        let response = fs::read_to_string(dir.path().join("bbbb-2.response.json")).unwrap();
+        assert_eq!(response, r#"{"approved":false}"#);
+    }
+
+    #[test]
+    fn test_respond_rejects_unknown_and_malformed_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(respond_in(dir.path(), "../config", true).is_err());
+        assert!(respond_in(dir.path(), "dddd-4", true).unwrap_err().contains("expired"));
+    }
+}
@@ -3,9 +3,10 @@ use std::path::Path;
 use std::sync::Mutex;
 use tauri::State;
 
-use crate::config::types::{AppConfig, FolderScanResult, Permission, SharedFolder};
-use crate::config::store::write_shared_config;
+use crate::config::types::{AppConfig, FolderScanResult, IgnoreCheck, Permission, SharedFolder};
+use crate::config::store::{self, write_shared_config};
 use crate::file_filter;
+use crate::ignore;
 
 /// Application state holding the current config, protected by a Mutex
 pub struct AppState {
@@ -13,7 +14,7 @@ pub struct AppState {
 }
 
 /// Scan a folder and return counts of supported vs unsupported files
-fn scan_folder(path: &str) -> Result<FolderScanResult, String> {
+fn scan_folder(path: &str, extra_extensions: &[String]) -> Result<FolderScanResult, String> {
     let dir_path = Path::new(path);
     if !dir_path.exists() {
         return Err(format!("Path does not exist: {}", path));
@@ -38,7 +39,7 @@ fn scan_folder(path: &str) -> Result<FolderScanResult, String> {
                     .and_then(|n| n.to_str())
                     .unwrap_or("");
 
-                if file_filter::is_file_supported(filename) {
+                if file_filter::is_file_supported(filename, extra_extensions) {
                     supported += 1;
                 } else {
                     unsupported += 1;
@@ -112,7 +113,7 @@ pub fn add_folder(
     }
 
     // Scan the folder for supported/unsupported files
-    let scan = scan_folder(&path)?;
+    let scan = scan_folder(&path, &config.extra_extensions)?;
 
     // Add the folder
     config.folders.push(SharedFolder {
@@ -120,6 +121,7 @@ pub fn add_folder(
         permission: Permission::ReadOnly,
         enabled: true,
         available: true,
+        case_insensitive_ignore: None,
     });
 
     // Persist config
@@ -195,8 +197,41 @@ pub fn toggle_folder_enabled(
 
 /// Scan a folder for file type breakdown
 #[tauri::command]
-pub fn scan_folder_files(path: String) -> Result<FolderScanResult, String> {
-    scan_folder(&path)
+pub fn scan_folder_files(state: State<'_, AppState>, path: String) -> Result<FolderScanResult, String> {
+    let extra_extensions = state.config.lock().map_err(|e| e.to_string())?.extra_extensions.clone();
+    scan_folder(&path, &extra_extensions)
+}
+
+/// Explain whether a path inside a shared folder is hidden from agents, and by which rule
+#[tauri::command]
+pub fn check_ignore(state: State<'_, AppState>, path: String) -> Result<IgnoreCheck, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    let target = fs::canonicalize(&path).unwrap_or_else(|_| Path::new(&path).to_path_buf());
+
+    let (folder, root) = config
+        .folders
+        .iter()
+        .filter_map(|f| Some((f, fs::canonicalize(&f.path).ok()?)))
+        .find(|(_, root)| target.starts_with(root))
+        .ok_or_else(|| format!("{} is not inside a shared folder", path))?;
+
+    // Same precedence as the server: the folder's setting, the global one, then the platform
+    let case_insensitive = folder
+        .case_insensitive_ignore
+        .or(config.case_insensitive_ignore)
+        .unwrap_or(cfg!(any(windows, target_os = "macos")));
+    let rules = ignore::load_rules(&root, &config.global_ignore);
+    let rule = ignore::first_match(&rules, &target, &root, case_insensitive);
+
+    Ok(IgnoreCheck {
+        path: path.clone(),
+        folder: folder.path.clone(),
+        ignored: rule.is_some(),
+        rule: rule.map(|r| r.pattern.trim().to_string()),
+        file: rule.and_then(|r| r.file.as_ref()).map(|f| f.to_string_lossy().to_string()),
+        line: rule.map(|r| r.line),
+        source: rule.map(|r| r.location()),
+    })
 }
 
 /// Get the path to the MCP server binary (for connection info)
@@ -243,3 +278,64 @@ pub fn update_max_file_size(
     persist_config(&config)?;
     Ok(())
 }
+
+/// Replace the ignore rules applied to every shared folder
+#[tauri::command]
+pub fn update_global_ignore(
+    state: State<'_, AppState>,
+    patterns: Vec<String>,
+) -> Result<(), String> {
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    config.global_ignore = patterns
+        .iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    persist_config(&config)?;
+    Ok(())
+}
+
+/// Replace the extensions shared in addition to the built-in list
+#[tauri::command]
+pub fn update_extra_extensions(
+    state: State<'_, AppState>,
+    extensions: Vec<String>,
+) -> Result<(), String> {
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    config.extra_extensions = extensions
+        .iter()
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect();
+    persist_config(&config)?;
+    Ok(())
+}
+
+/// Pause or resume all sharing. While paused the server refuses every tool call
+/// and SSE request; folders and their settings are kept.
+#[tauri::command]
+pub fn set_paused(state: State<'_, AppState>, paused: bool) -> Result<(), String> {
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    config.paused = paused;
+    persist_config(&config)?;
+    Ok(())
+}
+
+/// Get the active config profile (`None` for the default config)
+#[tauri::command]
+pub fn get_active_profile() -> Option<String> {
+    store::get_active_profile()
+}
+
+/// Switch to another config profile and load its folders.
+/// Pass `None` to go back to the default config.
+#[tauri::command]
+pub fn switch_profile(
+    state: State<'_, AppState>,
+    profile: Option<String>,
+) -> Result<AppConfig, String> {
+    let new_config = store::switch_profile(profile)?;
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    *config = new_config;
+    Ok(config.clone())
+}
@@ -1,5 +1,6 @@
 pub mod folders;
 pub mod activity;
 pub mod sse;
+pub mod confirm;
 
 pub use folders::*;
@@ -42,6 +42,11 @@ pub fn start_sse_mode(app: AppHandle, port: u16, allowed_origins: Vec<String>) -
         port.to_string(),
     ];
 
+    if let Some(profile) = crate::config::store::get_active_profile() {
+        args.push("--profile".to_string());
+        args.push(profile);
+    }
+
     if !allowed_origins.is_empty() {
         args.push("--allowed-origins".to_string());
         args.push(allowed_origins.join(","));
@@ -1,13 +1,65 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use serde_json;
 use crate::config::types::AppConfig;
 
+lazy_static::lazy_static! {
+    /// Profile the desktop app is editing; `None` means the default config
+    static ref ACTIVE_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Returns the directory holding the shared config and its profiles (~/.omnidrive)
+pub(crate) fn get_shared_config_dir() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".omnidrive")
+}
+
 /// Returns the path to the shared config file that the MCP sidecar reads.
-/// Located at: ~/.omnidrive/config.json
+/// Located at: ~/.omnidrive/config.json, or ~/.omnidrive/config.<profile>.json
+/// when a profile is active.
 pub fn get_shared_config_path() -> PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    home.join(".omnidrive").join("config.json")
+    profile_config_path(&get_shared_config_dir(), get_active_profile().as_deref())
+}
+
+/// Config file for `profile` inside `dir` (`config.json` when no profile is given)
+pub fn profile_config_path(dir: &Path, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(name) => dir.join(format!("config.{}.json", name)),
+        None => dir.join("config.json"),
+    }
+}
+
+/// Profile names become part of a file name, so keep them to a safe charset
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn get_active_profile() -> Option<String> {
+    ACTIVE_PROFILE.lock().unwrap().clone()
+}
+
+/// Switch the active profile and return its config. A profile that doesn't exist
+/// yet starts from the default config and is created on the next write.
+pub fn switch_profile(profile: Option<String>) -> Result<AppConfig, String> {
+    if let Some(name) = &profile {
+        if !is_valid_profile_name(name) {
+            return Err(format!(
+                "Invalid profile name '{}': use letters, digits, '-' or '_'",
+                name
+            ));
+        }
+    }
+
+    let dir = get_shared_config_dir();
+    let path = profile_config_path(&dir, profile.as_deref());
+    let source = if path.is_file() { path } else { profile_config_path(&dir, None) };
+
+    *ACTIVE_PROFILE.lock().unwrap() = profile;
+    Ok(read_config_file(&source))
 }
 
 /// Write the current AppConfig to the shared config file so the MCP sidecar can read it.
@@ -29,10 +81,30 @@ pub fn write_shared_config(config: &AppConfig) -> Result<(), String> {
 
 /// Read the shared config file. Returns a default config if the file doesn't exist.
 pub fn read_shared_config() -> AppConfig {
-    let path = get_shared_config_path();
+    read_config_file(&get_shared_config_path())
+}
 
-    match fs::read_to_string(&path) {
+fn read_config_file(path: &Path) -> AppConfig {
+    match fs::read_to_string(path) {
         Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
         Err(_) => AppConfig::default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_config_path() {
+        let dir = Path::new("/home/u/.omnidrive");
+        assert_eq!(profile_config_path(dir, None), dir.join("config.json"));
+        assert_eq!(profile_config_path(dir, Some("work")), dir.join("config.work.json"));
+    }
+
+    #[test]
+    fn test_switch_profile_rejects_unsafe_names() {
+        assert!(switch_profile(Some("../escape".to_string())).is_err());
+        assert!(switch_profile(Some(String::new())).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Permission level for a shared folder
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -6,6 +7,10 @@ use serde::{Deserialize, Serialize};
 pub enum Permission {
     ReadOnly,
     ReadWrite,
+    /// New files may be created, but existing ones can't be overwritten, moved or deleted
+    CreateOnly,
+    /// Like `CreateOnly`, but existing files may also be appended to
+    AppendOnly,
 }
 
 impl Default for Permission {
@@ -23,6 +28,9 @@ pub struct SharedFolder {
     /// Whether the folder path currently exists on disk
     #[serde(default = "default_true")]
     pub available: bool,
+    /// Overrides the global `case_insensitive_ignore` for this folder's ignore rules
+    #[serde(default)]
+    pub case_insensitive_ignore: Option<bool>,
 }
 
 fn default_true() -> bool {
@@ -36,17 +44,198 @@ pub struct AppConfig {
     /// Maximum file size in MB that the MCP server will serve (default: 50)
     #[serde(default = "default_max_file_size")]
     pub max_file_size_mb: u32,
+    /// How many expensive tools (grep, zip, recursive listing) may run at once (default: 4)
+    #[serde(default = "default_max_heavy_operations")]
+    pub max_heavy_operations: usize,
+    /// Replace the home directory with `~` in paths and messages shown to agents and in the
+    /// activity log; file contents are always returned as they are on disk
+    #[serde(default)]
+    pub redact_home: bool,
+    /// Ignore rules (same syntax as `.mcpignore`) applied to every shared folder
+    #[serde(default)]
+    pub global_ignore: Vec<String>,
+    /// Largest tool response in bytes; longer output is truncated with a footer (default: 1 MB)
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    /// Entries per list_directory page when the agent doesn't ask for a size (default: 50)
+    #[serde(default = "default_page_size")]
+    pub default_page_size: usize,
+    /// Largest page a flat list_directory call may request (default: 100)
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: usize,
+    /// Largest page a recursive list_directory call may request (default: 200)
+    #[serde(default = "default_max_tree_page_size")]
+    pub max_tree_page_size: usize,
+    /// Recursive listings stop walking after this many entries (default: 10,000)
+    #[serde(default = "default_max_tree_entries")]
+    pub max_tree_entries: usize,
+    /// Extensions shared in addition to the built-in list, e.g. ["tf", "vue"]
+    #[serde(default)]
+    pub extra_extensions: Vec<String>,
+    /// Sign each activity entry with an HMAC so tampering with the log can be detected
+    #[serde(default)]
+    pub sign_activity: bool,
+    /// Run the SSE transport with stateful sessions instead of stateless requests (also `--stateful`)
+    #[serde(default)]
+    pub sse_stateful: bool,
+    /// Octal mode (e.g. "600") applied to files the MCP server creates; unset keeps the umask default. Unix only
+    #[serde(default)]
+    pub created_file_mode: Option<String>,
+    /// Longest a PDF text extraction or file hash may run before the tool gives up (default: 60)
+    #[serde(default = "default_blocking_timeout_secs")]
+    pub blocking_timeout_secs: u64,
+    /// How many times writes, renames and copies are attempted on transient I/O errors (default: 3)
+    #[serde(default = "default_io_retry_attempts")]
+    pub io_retry_attempts: u32,
+    /// Ask the user in the app before deleting, overwriting by move, or recursively deleting (default: off)
+    #[serde(default)]
+    pub require_confirmation_for_destructive: bool,
+    /// Seconds to wait for a confirmation before denying the operation (default: 60)
+    #[serde(default = "default_confirmation_timeout_secs")]
+    pub confirmation_timeout_secs: u64,
+    /// Longest absolute path (in bytes) a tool may use (default: 4096)
+    #[serde(default = "default_max_path_length")]
+    pub max_path_length: usize,
+    /// Most directory levels a path may have (default: 128)
+    #[serde(default = "default_max_path_components")]
+    pub max_path_components: usize,
+    /// Longest single file or directory name (in bytes) a path may contain (default: 255)
+    #[serde(default = "default_max_filename_length")]
+    pub max_filename_length: usize,
+    /// Extra guidance for agents, appended to the built-in server instructions
+    #[serde(default)]
+    pub server_instructions: Option<String>,
+    /// Use server_instructions instead of the built-in instructions rather than appending to them
+    #[serde(default)]
+    pub replace_instructions: bool,
+    /// Match ignore rules case-insensitively in every folder; unset follows the platform (insensitive on Windows and macOS)
+    #[serde(default)]
+    pub case_insensitive_ignore: Option<bool>,
+    /// Most results any search tool returns. A tool's own max_results can lower this but not raise it (default: 200)
+    #[serde(default = "default_max_search_results")]
+    pub max_search_results: usize,
+    /// Skip dependency, build and VCS directories (.git, node_modules, target, __pycache__, …) when walking directories (default: true). Unlike .mcpignore, files inside them can still be accessed directly.
+    #[serde(default = "default_true")]
+    pub use_default_ignores: bool,
+    /// Folders each agent may use, by agent name. Agents not listed see every folder unless deny_unscoped_agents is set; with no entries, scoping is off. An agent is the client that launched the server (stdio) or the paired Origin of the request (SSE); names clients give themselves don't count.
+    #[serde(default)]
+    pub agent_scopes: HashMap<String, Vec<String>>,
+    /// With agent_scopes set, deny agents that aren't listed instead of giving them every folder
+    #[serde(default)]
+    pub deny_unscoped_agents: bool,
+    /// Cut off all agent access without removing any folders: every tool call fails and SSE requests are refused until unpaused
+    #[serde(default)]
+    pub paused: bool,
+    /// Timezone for timestamps in human-facing tool output: "UTC" or a fixed offset like "+05:30"; unset uses local time. JSON output stays RFC 3339.
+    #[serde(default)]
+    pub display_timezone: Option<String>,
+    /// strftime format for timestamps in human-facing tool output (default: "%Y-%m-%d %H:%M:%S")
+    #[serde(default = "default_display_time_format")]
+    pub display_time_format: String,
+    /// Keep up to this many MB of recently read file contents in memory, reused until a file changes (0 = off, the default)
+    #[serde(default)]
+    pub read_cache_mb: usize,
+    /// Whether write_file may replace an existing file when the call does not set overwrite (default: true). Set to false to require overwrite=true for every replacement.
+    #[serde(default = "default_true")]
+    pub default_overwrite: bool,
 }
 
 fn default_max_file_size() -> u32 {
     50
 }
 
+fn default_max_heavy_operations() -> usize {
+    4
+}
+
+fn default_max_response_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_page_size() -> usize {
+    50
+}
+
+fn default_max_page_size() -> usize {
+    100
+}
+
+fn default_max_tree_page_size() -> usize {
+    200
+}
+
+fn default_max_tree_entries() -> usize {
+    10_000
+}
+
+fn default_blocking_timeout_secs() -> u64 {
+    60
+}
+
+fn default_io_retry_attempts() -> u32 {
+    3
+}
+
+fn default_confirmation_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_path_length() -> usize {
+    4096
+}
+
+fn default_max_path_components() -> usize {
+    128
+}
+
+fn default_max_filename_length() -> usize {
+    255
+}
+
+fn default_max_search_results() -> usize {
+    200
+}
+
+fn default_display_time_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             folders: Vec::new(),
             max_file_size_mb: 50,
+            max_heavy_operations: default_max_heavy_operations(),
+            redact_home: false,
+            global_ignore: Vec::new(),
+            max_response_bytes: default_max_response_bytes(),
+            default_page_size: default_page_size(),
+            max_page_size: default_max_page_size(),
+            max_tree_page_size: default_max_tree_page_size(),
+            max_tree_entries: default_max_tree_entries(),
+            extra_extensions: Vec::new(),
+            sign_activity: false,
+            sse_stateful: false,
+            created_file_mode: None,
+            blocking_timeout_secs: default_blocking_timeout_secs(),
+            io_retry_attempts: default_io_retry_attempts(),
+            require_confirmation_for_destructive: false,
+            confirmation_timeout_secs: default_confirmation_timeout_secs(),
+            max_path_length: default_max_path_length(),
+            max_path_components: default_max_path_components(),
+            max_filename_length: default_max_filename_length(),
+            server_instructions: None,
+            replace_instructions: false,
+            case_insensitive_ignore: None,
+            max_search_results: default_max_search_results(),
+            use_default_ignores: true,
+            agent_scopes: HashMap::new(),
+            deny_unscoped_agents: false,
+            paused: false,
+            display_timezone: None,
+            display_time_format: default_display_time_format(),
+            read_cache_mb: 0,
+            default_overwrite: true,
         }
     }
 }
@@ -60,6 +249,23 @@ pub struct FolderScanResult {
     pub unsupported_list: Vec<String>,
 }
 
+/// Whether a path is hidden by an ignore rule, and by which one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IgnoreCheck {
+    pub path: String,
+    /// The shared folder containing the path
+    pub folder: String,
+    pub ignored: bool,
+    /// The matching rule, as written
+    pub rule: Option<String>,
+    /// The `.mcpignore` holding the rule, or `None` for the global ignore list
+    pub file: Option<String>,
+    /// Line in `file`, or position in the global ignore list
+    pub line: Option<usize>,
+    /// `file:line`, or `global_ignore[n]`
+    pub source: Option<String>,
+}
+
 /// File category for type filtering
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -0,0 +1,71 @@
+//! The single list of file types shared with AI agents.
+//!
+//! Used by the desktop app's folder scan (`file_filter`) and by the MCP server's
+//! sandbox, which includes this file with `#[path]`. Keep it free of `crate::`
+//! imports so it compiles in both.
+
+use std::path::Path;
+
+pub const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "tsx", "jsx", "go", "java", "c", "cpp", "h", "hpp",
+    "rb", "php", "swift", "kt", "sh", "bat", "ps1", "r", "scala", "lua",
+    "dart", "zig", "nim", "ex", "exs", "clj", "hs", "ml", "fs", "cs",
+];
+
+pub const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "csv", "log", "env", "gitignore", "dockerignore", "editorconfig",
+];
+
+pub const DATA_EXTENSIONS: &[&str] = &[
+    "json", "yaml", "yml", "toml", "xml", "html", "htm", "css", "scss", "sass",
+    "less", "sql", "graphql", "proto", "ini", "cfg", "conf",
+];
+
+pub const DOCUMENT_EXTENSIONS: &[&str] = &["pdf"];
+
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "ico",
+];
+
+/// Files without extensions that are commonly text
+pub const EXTENSIONLESS_SUPPORTED: &[&str] = &[
+    "Makefile", "Dockerfile", "Jenkinsfile", "Vagrantfile",
+    "Gemfile", "Rakefile", "Procfile", "LICENSE", "README",
+    "CHANGELOG", "CONTRIBUTING", "AUTHORS",
+];
+
+/// The built-in lists by category. Names match `FileCategory`'s serialized form.
+pub const CATEGORIES: &[(&str, &[&str])] = &[
+    ("code", CODE_EXTENSIONS),
+    ("text", TEXT_EXTENSIONS),
+    ("data", DATA_EXTENSIONS),
+    ("document", DOCUMENT_EXTENSIONS),
+    ("image", IMAGE_EXTENSIONS),
+];
+
+/// Check a bare extension (with or without the leading dot) against the built-in lists
+pub fn is_builtin_extension(extension: &str) -> bool {
+    let ext = extension.to_lowercase();
+    let ext = ext.trim_start_matches('.');
+    CATEGORIES.iter().any(|(_, list)| list.contains(&ext))
+}
+
+/// Check a file name against the built-in lists plus `extra_extensions`.
+/// Dotfiles such as `.gitignore` or `.env` are matched by the name after the dot.
+pub fn is_supported_filename(filename: &str, extra_extensions: &[String]) -> bool {
+    let path = Path::new(filename);
+    let basename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+    let ext = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext,
+        None => match basename.strip_prefix('.') {
+            Some(dotfile) => dotfile,
+            None => return EXTENSIONLESS_SUPPORTED.contains(&basename),
+        },
+    };
+
+    is_builtin_extension(ext)
+        || extra_extensions
+            .iter()
+            .any(|extra| extra.trim().trim_start_matches('.').eq_ignore_ascii_case(ext))
+}
@@ -1,29 +1,11 @@
+pub mod extensions;
 pub mod types;
 
+use extensions::{
+    CODE_EXTENSIONS, DATA_EXTENSIONS, DOCUMENT_EXTENSIONS, IMAGE_EXTENSIONS, TEXT_EXTENSIONS,
+};
 use types::FileCategory;
 
-/// All file extensions considered supported for AI agent consumption
-const CODE_EXTENSIONS: &[&str] = &[
-    "rs", "py", "js", "ts", "tsx", "jsx", "go", "java", "c", "cpp", "h", "hpp",
-    "rb", "php", "swift", "kt", "sh", "bat", "ps1", "r", "scala", "lua",
-    "dart", "zig", "nim", "ex", "exs", "clj", "hs", "ml", "fs", "cs",
-];
-
-const TEXT_EXTENSIONS: &[&str] = &[
-    "txt", "md", "csv", "log", "env", "gitignore", "dockerignore", "editorconfig",
-];
-
-const DATA_EXTENSIONS: &[&str] = &[
-    "json", "yaml", "yml", "toml", "xml", "html", "htm", "css", "scss", "sass",
-    "less", "sql", "graphql", "proto", "ini", "cfg", "conf",
-];
-
-const DOCUMENT_EXTENSIONS: &[&str] = &["pdf"];
-
-const IMAGE_EXTENSIONS: &[&str] = &[
-    "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "ico",
-];
-
 /// Check if a file extension is supported for sharing with AI agents
 pub fn is_supported(extension: &str) -> bool {
     get_file_category(extension) != FileCategory::Unsupported
@@ -49,29 +31,11 @@ pub fn get_file_category(extension: &str) -> FileCategory {
     }
 }
 
-/// Check if a file at the given path is supported based on its extension.
+/// Check if a file at the given path is supported based on its extension,
+/// either built in or listed in `extra_extensions`.
 /// Files without extensions are treated as text (e.g., Makefile, Dockerfile).
-pub fn is_file_supported(filename: &str) -> bool {
-    // Files without extensions that are commonly text
-    let extensionless_supported = [
-        "Makefile", "Dockerfile", "Jenkinsfile", "Vagrantfile",
-        "Gemfile", "Rakefile", "Procfile", "LICENSE", "README",
-        "CHANGELOG", "CONTRIBUTING", "AUTHORS",
-    ];
-
-    if let Some(ext) = std::path::Path::new(filename)
-        .extension()
-        .and_then(|e| e.to_str())
-    {
-        is_supported(ext)
-    } else {
-        // Check if it's a known extensionless file
-        let basename = std::path::Path::new(filename)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-        extensionless_supported.iter().any(|&name| basename == name)
-    }
+pub fn is_file_supported(filename: &str, extra_extensions: &[String]) -> bool {
+    extensions::is_supported_filename(filename, extra_extensions)
 }
 
 #[cfg(test)]
@@ -127,10 +91,43 @@ mod tests {
 
     #[test]
     fn test_extensionless_files() {
-        assert!(is_file_supported("Makefile"));
-        assert!(is_file_supported("Dockerfile"));
-        assert!(is_file_supported("LICENSE"));
-        assert!(!is_file_supported("randomfile"));
+        assert!(is_file_supported("Makefile", &[]));
+        assert!(is_file_supported("Dockerfile", &[]));
+        assert!(is_file_supported("LICENSE", &[]));
+        assert!(!is_file_supported("randomfile", &[]));
+    }
+
+    #[test]
+    fn test_extra_extensions() {
+        let extra = vec!["tf".to_string(), ".astro".to_string()];
+        assert!(!is_file_supported("main.tf", &[]));
+        assert!(is_file_supported("main.tf", &extra));
+        assert!(is_file_supported("Page.ASTRO", &extra));
+    }
+
+    #[test]
+    fn test_categories_agree_with_shared_list() {
+        // The server's sandbox checks the shared list directly; the UI goes through
+        // categories. Both must give the same answer.
+        let samples = [
+            "rs", "tsx", "md", "editorconfig", "dockerignore", "sass", "scss", "json",
+            "pdf", "svg", "exe", "zip", "docx", "mp4", "",
+        ];
+        for ext in samples {
+            assert_eq!(
+                is_supported(ext),
+                extensions::is_builtin_extension(ext),
+                "disagreement on {:?}",
+                ext
+            );
+        }
+    }
+
+    #[test]
+    fn test_dotfiles_use_name_after_dot() {
+        assert!(is_file_supported(".gitignore", &[]));
+        assert!(is_file_supported(".env", &[]));
+        assert!(!is_file_supported(".DS_Store", &[]));
     }
 
     #[test]
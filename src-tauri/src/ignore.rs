@@ -0,0 +1,134 @@
+//! Ignore rule matching for shared folders: the global ignore list plus the
+//! `.mcpignore` at each folder's root.
+//!
+//! The MCP server enforces these rules and the desktop app explains them, so
+//! both use this file. The server includes it with `#[path]`, so keep it free
+//! of `crate::` imports.
+
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// The per-folder ignore file, read from the shared folder's root
+pub const IGNORE_FILE_NAME: &str = ".mcpignore";
+
+/// One ignore rule and where it was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreRule {
+    pub pattern: String,
+    /// The `.mcpignore` the rule comes from, or `None` for the global ignore list
+    pub file: Option<PathBuf>,
+    /// 1-based line in `file`, or position in the global list
+    pub line: usize,
+}
+
+impl IgnoreRule {
+    /// Where the rule is defined, e.g. `/docs/.mcpignore:3` or `global_ignore[1]`
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file.display(), self.line),
+            None => format!("global_ignore[{}]", self.line),
+        }
+    }
+}
+
+/// The global ignore rules followed by the lines of the folder's .mcpignore
+pub fn load_rules(folder_root: &Path, global_ignore: &[String]) -> Vec<IgnoreRule> {
+    let mut rules: Vec<IgnoreRule> = global_ignore
+        .iter()
+        .enumerate()
+        .map(|(i, pattern)| IgnoreRule { pattern: pattern.clone(), file: None, line: i + 1 })
+        .collect();
+
+    let ignore_file = folder_root.join(IGNORE_FILE_NAME);
+    if let Ok(file) = fs::File::open(&ignore_file) {
+        let reader = std::io::BufReader::new(file);
+        rules.extend(reader.lines().map_while(Result::ok).enumerate().map(|(i, pattern)| IgnoreRule {
+            pattern,
+            file: Some(ignore_file.clone()),
+            line: i + 1,
+        }));
+    }
+
+    rules
+}
+
+/// The first rule that hides `target`, a path inside `folder_root`
+pub fn first_match<'a>(
+    rules: &'a [IgnoreRule],
+    target: &Path,
+    folder_root: &Path,
+    case_insensitive: bool,
+) -> Option<&'a IgnoreRule> {
+    let relative = target.strip_prefix(folder_root).ok()?.to_string_lossy().to_string();
+    let options = glob::MatchOptions {
+        case_sensitive: !case_insensitive,
+        ..glob::MatchOptions::new()
+    };
+
+    rules.iter().find(|rule| rule_matches(&rule.pattern, &relative, target, options))
+}
+
+fn rule_matches(rule: &str, relative: &str, target: &Path, options: glob::MatchOptions) -> bool {
+    let pattern = rule.trim();
+    // Skip empty lines and comments
+    if pattern.is_empty() || pattern.starts_with('#') {
+        return false;
+    }
+
+    // Directory rules ("build/" or "build/**") hide the directory itself, not just its contents
+    let (pattern, dir_only) = match pattern.strip_suffix("/**").or_else(|| pattern.strip_suffix('/')) {
+        Some(base) if !base.is_empty() => (base, true),
+        _ => (pattern, false),
+    };
+
+    // Match against the relative path — support glob patterns
+    let glob_str = if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        // Bare name like "node_modules" should match anywhere in the tree
+        format!("**/{}", pattern)
+    };
+
+    if let Ok(compiled) = glob::Pattern::new(&glob_str) {
+        if compiled.matches_with(relative, options) && (!dir_only || target.is_dir()) {
+            return true;
+        }
+        // Also check if any parent directory matches (e.g. "node_modules" ignores all children)
+        let with_wildcard = format!("{}/**", glob_str);
+        if let Ok(compiled_deep) = glob::Pattern::new(&with_wildcard) {
+            if compiled_deep.matches_with(relative, options) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_match_reports_file_and_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(IGNORE_FILE_NAME), "# secrets\n\n*.key\nbuild/\n").unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        let rules = load_rules(root, &["*.tmp".to_string()]);
+
+        let rule = first_match(&rules, &root.join("certs/signing.key"), root, false).unwrap();
+        assert_eq!(rule.pattern, "*.key");
+        assert_eq!(rule.file.as_deref(), Some(root.join(IGNORE_FILE_NAME).as_path()));
+        assert_eq!(rule.line, 3);
+
+        let rule = first_match(&rules, &root.join("build/out.js"), root, false).unwrap();
+        assert_eq!(rule.location(), format!("{}:4", root.join(IGNORE_FILE_NAME).display()));
+
+        let rule = first_match(&rules, &root.join("notes.tmp"), root, false).unwrap();
+        assert_eq!(rule.location(), "global_ignore[1]");
+
+        assert_eq!(first_match(&rules, &root.join("src/main.rs"), root, false), None);
+    }
+}
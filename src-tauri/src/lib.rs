@@ -1,6 +1,8 @@
 mod config;
 mod commands;
 mod file_filter;
+mod audit;
+mod ignore;
 
 use commands::AppState;
 use config::store::read_shared_config;
@@ -19,6 +21,10 @@ pub fn run() {
         .manage(AppState {
             config: Mutex::new(initial_config),
         })
+        .setup(|app| {
+            commands::confirm::watch_confirmations(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::add_folder,
             commands::remove_folder,
@@ -26,18 +32,29 @@ pub fn run() {
             commands::toggle_permission,
             commands::toggle_folder_enabled,
             commands::scan_folder_files,
+            commands::check_ignore,
             commands::get_omnidrive_path,
             commands::get_app_config,
             commands::update_max_file_size,
+            commands::update_global_ignore,
+            commands::update_extra_extensions,
+            commands::set_paused,
+            commands::get_active_profile,
+            commands::switch_profile,
             commands::activity::get_activity_log,
             commands::activity::get_connected_agents,
             commands::activity::clear_activity_log,
+            commands::activity::get_activity_stats,
+            commands::activity::verify_activity_log,
+            commands::activity::get_recent_files,
             commands::sse::start_sse_mode,
             commands::sse::stop_sse_mode,
             commands::sse::get_sse_status,
             commands::sse::approve_origin,
             commands::sse::revoke_origin,
             commands::sse::get_approved_origins,
+            commands::confirm::get_pending_confirmations,
+            commands::confirm::respond_to_confirmation,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -17,7 +17,9 @@ import { Sidebar, type SidebarItemConfig } from "./components/layout/Sidebar";
 import { ConnectedAppsPanel } from "./components/connected/ConnectedAppsPanel";
 import { ActivityLogPanel } from "./components/activity/ActivityLogPanel";
 import { PairingDialog } from "./components/connection/PairingDialog";
+import { ConfirmationDialog } from "./components/connection/ConfirmationDialog";
 import { useActivityLog } from "./hooks/useActivityLog";
+import { useConfirmations } from "./hooks/useConfirmations";
 import type {
   AppTab,
   ResolvedTheme,
@@ -52,6 +54,7 @@ function App() {
   const { preference, resolvedTheme, setPreference } = useTheme();
 
   const [pendingOrigin, setPendingOrigin] = useState<string | null>(null);
+  const { pending: pendingConfirmations, respond: respondToConfirmation } = useConfirmations();
 
   useEffect(() => {
     // Scan recent logs for blocked SSE connections
@@ -78,6 +81,14 @@ function App() {
     }
   };
 
+  const handleConfirmation = async (id: string, approved: boolean) => {
+    try {
+      await respondToConfirmation(id, approved);
+    } catch (err) {
+      pushToast(`Could not answer the request: ${err}`);
+    }
+  };
+
   const activeFolders = useMemo(
     () => folders.filter((folder) => folder.available && folder.enabled).length,
     [folders],
@@ -237,6 +248,15 @@ function App() {
             onDeny={() => setPendingOrigin(null)}
           />
         )}
+
+        {!pendingOrigin && pendingConfirmations.length > 0 && (
+          <ConfirmationDialog
+            key={pendingConfirmations[0].id}
+            request={pendingConfirmations[0]}
+            queued={pendingConfirmations.length - 1}
+            onRespond={handleConfirmation}
+          />
+        )}
       </div>
     </AppShell>
   );
@@ -0,0 +1,73 @@
+import { useEffect, useState } from "react";
+import type React from "react";
+import { ShieldAlert, Check, X } from "lucide-react";
+import type { PendingConfirmation } from "../../lib/types";
+
+interface ConfirmationDialogProps {
+    request: PendingConfirmation;
+    queued: number;
+    onRespond: (id: string, approved: boolean) => void;
+}
+
+function secondsLeft(expiresAt: string): number {
+    return Math.max(0, Math.ceil((Date.parse(expiresAt) - Date.now()) / 1000));
+}
+
+export const ConfirmationDialog: React.FC<ConfirmationDialogProps> = ({ request, queued, onRespond }) => {
+    const [remaining, setRemaining] = useState(() => secondsLeft(request.expires_at));
+
+    useEffect(() => {
+        setRemaining(secondsLeft(request.expires_at));
+        const interval = setInterval(() => setRemaining(secondsLeft(request.expires_at)), 1000);
+        return () => clearInterval(interval);
+    }, [request.expires_at]);
+
+    return (
+        <div className="modal-overlay">
+            <div className="modal-panel modal-panel--warning">
+                <div className="modal-header">
+                    <div className="modal-header__icon modal-header__icon--warning">
+                        <ShieldAlert size={20} />
+                    </div>
+                    <div className="modal-header__content">
+                        <h3>Confirm {request.action}</h3>
+                        <p>
+                            {request.agent} wants to run {request.tool}
+                            {queued > 0 ? ` (${queued} more waiting)` : ""}.
+                        </p>
+                    </div>
+                </div>
+
+                <div className="modal-body">
+                    <div className="pairing-box">
+                        <p className="pairing-box__label">Path</p>
+                        <div className="pairing-box__value" data-mono>{request.path}</div>
+                    </div>
+
+                    <p className="modal-description">
+                        This cannot be undone. If you don't answer within {remaining}s, the request is denied.
+                    </p>
+                </div>
+
+                <div className="modal-footer">
+                    <button
+                        type="button"
+                        className="button button--secondary"
+                        onClick={() => onRespond(request.id, false)}
+                    >
+                        <X size={16} />
+                        Deny
+                    </button>
+                    <button
+                        type="button"
+                        className="button button--danger"
+                        onClick={() => onRespond(request.id, true)}
+                    >
+                        <Check size={16} />
+                        Allow
+                    </button>
+                </div>
+            </div>
+        </div>
+    );
+};
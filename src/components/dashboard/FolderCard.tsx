@@ -1,11 +1,20 @@
 import {
     AlertTriangle,
+    FilePlus,
+    FileText,
     Folder,
     Lock,
     Trash2,
     Unlock,
 } from "lucide-react";
-import type { SharedFolder } from "../../lib/types";
+import type { Permission, SharedFolder } from "../../lib/types";
+
+const PERMISSION_OPTIONS: { value: Permission; label: string; icon: typeof Lock; description: string }[] = [
+    { value: "readonly", label: "Read Only", icon: Lock, description: "AI can only read files" },
+    { value: "createonly", label: "Create Only", icon: FilePlus, description: "AI can add new files but not change existing ones" },
+    { value: "appendonly", label: "Append Only", icon: FileText, description: "AI can add new files and append to existing ones" },
+    { value: "readwrite", label: "Read & Write", icon: Unlock, description: "AI can create and modify files" },
+];
 
 interface FolderCardProps {
     folder: SharedFolder;
@@ -25,7 +34,7 @@ export function FolderCard({
     disabled = false,
 }: FolderCardProps) {
     const folderName = folder.path.split(/[/\\]/).filter(Boolean).pop() || folder.path;
-    const isReadWrite = folder.permission === "readwrite";
+    const current = PERMISSION_OPTIONS.find((option) => option.value === folder.permission) ?? PERMISSION_OPTIONS[0];
 
     return (
         <article
@@ -51,30 +60,20 @@ export function FolderCard({
                     <p className="folder-row__path">{folder.path}</p>
                     <div className="folder-row__meta">
                         <div className="permission-chips" role="group" aria-label="Access level">
-                            <button
-                                type="button"
-                                className={`permission-chip ${!isReadWrite ? "is-active" : ""}`}
-                                onClick={() => onSetPermission(folder.path, "readonly")}
-                                disabled={disabled || !isReadWrite}
-                            >
-                                <Lock size={12} />
-                                Read Only
-                            </button>
-                            <button
-                                type="button"
-                                className={`permission-chip ${isReadWrite ? "is-active" : ""}`}
-                                onClick={() => onSetPermission(folder.path, "readwrite")}
-                                disabled={disabled || isReadWrite}
-                            >
-                                <Unlock size={12} />
-                                Read &amp; Write
-                            </button>
+                            {PERMISSION_OPTIONS.map(({ value, label, icon: Icon }) => (
+                                <button
+                                    key={value}
+                                    type="button"
+                                    className={`permission-chip ${value === current.value ? "is-active" : ""}`}
+                                    onClick={() => onSetPermission(folder.path, value)}
+                                    disabled={disabled || value === current.value}
+                                >
+                                    <Icon size={12} />
+                                    {label}
+                                </button>
+                            ))}
                         </div>
-                        <span className="folder-row__description">
-                            {isReadWrite
-                                ? "AI can create and modify files"
-                                : "AI can only read files"}
-                        </span>
+                        <span className="folder-row__description">{current.description}</span>
                     </div>
                 </div>
             </div>
@@ -0,0 +1,39 @@
+import { useState, useEffect, useCallback } from "react";
+import { getPendingConfirmations, onConfirmationRequested, respondToConfirmation } from "../lib/tauri";
+import type { PendingConfirmation } from "../lib/types";
+
+/// Destructive operations waiting on the user, oldest first
+export function useConfirmations() {
+    const [pending, setPending] = useState<PendingConfirmation[]>([]);
+
+    const fetchPending = useCallback(async () => {
+        try {
+            setPending(await getPendingConfirmations());
+        } catch (err) {
+            console.error("Failed to fetch pending confirmations:", err);
+        }
+    }, []);
+
+    // Show new requests as soon as they are announced; polling drops the ones
+    // that expired or were answered elsewhere
+    useEffect(() => {
+        fetchPending();
+        const unlisten = onConfirmationRequested((request) => {
+            setPending((current) =>
+                current.some((c) => c.id === request.id) ? current : [...current, request],
+            );
+        });
+        const interval = setInterval(fetchPending, 2000);
+        return () => {
+            clearInterval(interval);
+            unlisten.then((fn) => fn());
+        };
+    }, [fetchPending]);
+
+    const respond = async (id: string, approved: boolean) => {
+        setPending((current) => current.filter((c) => c.id !== id));
+        await respondToConfirmation(id, approved);
+    };
+
+    return { pending, respond };
+}
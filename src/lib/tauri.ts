@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
-import { AppConfig, FolderScanResult, Permission, SharedFolder, ActivityEntry, ConnectedAgent, SseStatus } from "./types";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
+import { AppConfig, FolderScanResult, IgnoreCheck, Permission, SharedFolder, ActivityEntry, ByteStats, ConnectedAgent, RecentFile, LogVerification, PendingConfirmation, SseStatus } from "./types";
 
 /// --- Folder Management ---
 
@@ -27,6 +28,10 @@ export async function scanFolderFiles(path: string): Promise<FolderScanResult> {
     return invoke<FolderScanResult>("scan_folder_files", { path });
 }
 
+export async function checkIgnore(path: string): Promise<IgnoreCheck> {
+    return invoke<IgnoreCheck>("check_ignore", { path });
+}
+
 /// --- Config ---
 
 export async function getMcpServerPath(): Promise<string> {
@@ -41,6 +46,26 @@ export async function updateMaxFileSize(maxSizeMb: number): Promise<void> {
     return invoke<void>("update_max_file_size", { maxSizeMb });
 }
 
+export async function updateGlobalIgnore(patterns: string[]): Promise<void> {
+    return invoke<void>("update_global_ignore", { patterns });
+}
+
+export async function updateExtraExtensions(extensions: string[]): Promise<void> {
+    return invoke<void>("update_extra_extensions", { extensions });
+}
+
+export async function setPaused(paused: boolean): Promise<void> {
+    return invoke<void>("set_paused", { paused });
+}
+
+export async function getActiveProfile(): Promise<string | null> {
+    return invoke<string | null>("get_active_profile");
+}
+
+export async function switchProfile(profile: string | null): Promise<AppConfig> {
+    return invoke<AppConfig>("switch_profile", { profile });
+}
+
 /// --- Activity Tracking ---
 
 export async function getActivityLog(limit: number, offset: number, category?: string): Promise<ActivityEntry[]> {
@@ -55,6 +80,18 @@ export async function clearActivityLog(): Promise<void> {
     return invoke<void>("clear_activity_log");
 }
 
+export async function getActivityStats(): Promise<ByteStats[]> {
+    return invoke<ByteStats[]>("get_activity_stats");
+}
+
+export async function verifyActivityLog(): Promise<LogVerification> {
+    return invoke<LogVerification>("verify_activity_log");
+}
+
+export async function getRecentFiles(limit?: number): Promise<RecentFile[]> {
+    return invoke<RecentFile[]>("get_recent_files", { limit });
+}
+
 /// --- SSE ---
 
 export async function startSseMode(port: number, allowedOrigins: string[]): Promise<SseStatus> {
@@ -80,3 +117,17 @@ export async function revokeOrigin(origin: string): Promise<void> {
 export async function getApprovedOrigins(): Promise<string[]> {
     return invoke<string[]>("get_approved_origins");
 }
+
+/// --- Confirmations ---
+
+export async function getPendingConfirmations(): Promise<PendingConfirmation[]> {
+    return invoke<PendingConfirmation[]>("get_pending_confirmations");
+}
+
+export async function respondToConfirmation(id: string, approved: boolean): Promise<void> {
+    return invoke<void>("respond_to_confirmation", { id, approved });
+}
+
+export async function onConfirmationRequested(handler: (request: PendingConfirmation) => void): Promise<UnlistenFn> {
+    return listen<PendingConfirmation>("confirmation-requested", (event) => handler(event.payload));
+}
@@ -1,6 +1,6 @@
 /// TypeScript types mirroring the Rust backend types
 
-export type Permission = "readonly" | "readwrite";
+export type Permission = "readonly" | "readwrite" | "createonly" | "appendonly";
 export type ThemePreference = "system" | "light" | "dark";
 export type ResolvedTheme = "light" | "dark";
 export type AppTab = "folders" | "connectedApps" | "activityLog" | "settings";
@@ -11,11 +11,43 @@ export interface SharedFolder {
     permission: Permission;
     enabled: boolean;
     available: boolean;
+    case_insensitive_ignore?: boolean | null;
 }
 
 export interface AppConfig {
     folders: SharedFolder[];
     max_file_size_mb: number;
+    max_heavy_operations: number;
+    redact_home: boolean;
+    global_ignore: string[];
+    max_response_bytes: number;
+    default_page_size: number;
+    max_page_size: number;
+    max_tree_page_size: number;
+    max_tree_entries: number;
+    extra_extensions: string[];
+    sign_activity: boolean;
+    sse_stateful: boolean;
+    created_file_mode: string | null;
+    blocking_timeout_secs: number;
+    io_retry_attempts: number;
+    require_confirmation_for_destructive: boolean;
+    confirmation_timeout_secs: number;
+    max_path_length: number;
+    max_path_components: number;
+    max_filename_length: number;
+    server_instructions: string | null;
+    replace_instructions: boolean;
+    case_insensitive_ignore: boolean | null;
+    max_search_results: number;
+    use_default_ignores: boolean;
+    agent_scopes: Record<string, string[]>;
+    deny_unscoped_agents: boolean;
+    paused: boolean;
+    display_timezone: string | null;
+    display_time_format: string;
+    read_cache_mb: number;
+    default_overwrite: boolean;
 }
 
 export interface FolderScanResult {
@@ -25,6 +57,16 @@ export interface FolderScanResult {
     unsupported_list: string[];
 }
 
+export interface IgnoreCheck {
+    path: string;
+    folder: string;
+    ignored: boolean;
+    rule: string | null;
+    file: string | null;
+    line: number | null;
+    source: string | null;
+}
+
 export type ServerStatus = "live" | "offline" | "checking";
 
 export interface ConnectionClient {
@@ -50,6 +92,30 @@ export interface ActivityEntry {
     path: string | null;
     agent: string;
     summary: string;
+    bytes?: number;
+    sig?: string;
+}
+
+export interface LogVerification {
+    total_lines: number;
+    signed: number;
+    unsigned: number;
+    first_invalid_line: number | null;
+    valid: boolean;
+}
+
+export interface ByteStats {
+    agent: string;
+    folder: string;
+    bytes_read: number;
+    bytes_written: number;
+}
+
+export interface RecentFile {
+    agent: string;
+    path: string;
+    timestamp: string;
+    category: string;
 }
 
 export interface SseStatus {
@@ -57,3 +123,13 @@ export interface SseStatus {
     port: number;
     url: string | null;
 }
+
+export interface PendingConfirmation {
+    id: string;
+    agent: string;
+    tool: string;
+    path: string;
+    action: string;
+    requested_at: string;
+    expires_at: string;
+}